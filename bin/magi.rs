@@ -45,6 +45,17 @@ pub struct Cli {
     network: String,
     #[clap(long)]
     l1_rpc_url: Option<String>,
+    /// Additional L1 RPC endpoints to fail over to, comma separated
+    #[clap(long, value_delimiter = ',')]
+    l1_fallback_rpc_url: Vec<String>,
+    /// Number of L1 RPC endpoints that must agree on each block, log, call and storage read at a block number or hash
+    #[clap(long)]
+    l1_rpc_quorum: Option<usize>,
+    #[clap(long)]
+    l1_beacon_url: Option<String>,
+    /// L1 WebSocket endpoint to subscribe to new heads on, instead of polling for them
+    #[clap(long)]
+    l1_ws_url: Option<String>,
     #[clap(long)]
     l2_rpc_url: Option<String>,
     #[clap(short = 'm', long, default_value = "full")]
@@ -76,6 +87,15 @@ pub struct Cli {
     local_sequencer: LocalSequencerCli,
     #[clap(long)]
     watcher_delay: Option<u64>,
+    /// Maximum number of L1 blocks to fetch ahead while catching up
+    #[clap(long)]
+    l1_prefetch_window: Option<usize>,
+    /// Replay a recorded L1 fixture instead of watching the L1 RPC
+    #[clap(long)]
+    l1_fixture: Option<PathBuf>,
+    /// Persist the derivation pipeline state to this file and resume from it on restart
+    #[clap(long)]
+    pipeline_snapshot: Option<PathBuf>,
 }
 
 #[derive(Parser, Serialize)]
@@ -139,6 +159,10 @@ impl From<Cli> for CliConfig {
         let jwt_secret = value.jwt_secret();
         Self {
             l1_rpc_url: value.l1_rpc_url,
+            l1_fallback_rpc_urls: value.l1_fallback_rpc_url,
+            l1_rpc_quorum: value.l1_rpc_quorum,
+            l1_beacon_url: value.l1_beacon_url,
+            l1_ws_url: value.l1_ws_url,
             l2_rpc_url: value.l2_rpc_url,
             l2_engine_url: value.l2_engine_url,
             jwt_secret,
@@ -147,6 +171,9 @@ impl From<Cli> for CliConfig {
             devnet: value.devnet,
             local_sequencer: Some(value.local_sequencer.into()),
             watcher_delay: value.watcher_delay,
+            l1_prefetch_window: value.l1_prefetch_window,
+            l1_fixture: value.l1_fixture,
+            pipeline_snapshot: value.pipeline_snapshot,
         }
     }
 }
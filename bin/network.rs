@@ -18,13 +18,13 @@ async fn main() -> Result<()> {
     let (_, recv) = watch::channel(Address::from_str(
         "0x715b7219d986641df9efd9c7ef01218d528e19ec",
     )?);
-    let (block_handler, block_recv) = BlockHandler::new(chain_id, recv);
+    let (block_handler, mut block_recv) = BlockHandler::new(chain_id, recv);
 
     Service::new(addr, chain_id)
         .add_handler(Box::new(block_handler))
         .start()?;
 
-    while let Ok(payload) = block_recv.recv() {
+    while let Some(payload) = block_recv.recv().await {
         tracing::info!("received unsafe block with hash: {:?}", payload.block_hash);
     }
 
@@ -0,0 +1,95 @@
+use std::{path::PathBuf, sync::Arc};
+
+use clap::Parser;
+use eyre::Result;
+
+use magi::{
+    config::{ChainConfig, Config},
+    l1::fixture::FixtureRecorder,
+    telemetry,
+};
+
+/// Records the L1 data of a block range to a fixture that magi can replay with
+/// `--l1-fixture`
+#[derive(Parser)]
+pub struct Cli {
+    #[clap(short, long, default_value = "optimism")]
+    network: String,
+    #[clap(long)]
+    l1_rpc_url: String,
+    #[clap(long)]
+    l1_beacon_url: Option<String>,
+    /// Used to read the system config at the L2 start block
+    #[clap(long, default_value = "http://127.0.0.1:8545")]
+    l2_rpc_url: String,
+    #[clap(long)]
+    l1_start_block: u64,
+    #[clap(long)]
+    l1_end_block: u64,
+    /// Defaults to the L2 genesis block
+    #[clap(long)]
+    l2_start_block: Option<u64>,
+    #[clap(short, long)]
+    output: PathBuf,
+    /// Directory to write the batcher data of each block to, for use with the
+    /// `files` data source
+    #[clap(long)]
+    data_dir: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let _guards = telemetry::init(false, false, None, None);
+    let cli = Cli::parse();
+
+    let chain = match cli.network.as_str() {
+        "optimism" => ChainConfig::optimism(),
+        "optimism-goerli" => ChainConfig::optimism_goerli(),
+        "optimism-sepolia" => ChainConfig::optimism_sepolia(),
+        "base" => ChainConfig::base(),
+        "base-goerli" => ChainConfig::base_goerli(),
+        path if ChainConfig::is_specular_config(path) => ChainConfig::from_specular_json(path),
+        file if file.ends_with(".json") => ChainConfig::from_json(file),
+        _ => eyre::bail!("invalid network name: {}", cli.network),
+    };
+
+    let l2_start_block = cli.l2_start_block.unwrap_or(chain.l2_genesis.number);
+
+    let config = Arc::new(Config {
+        l1_rpc_url: cli.l1_rpc_url,
+        l1_fallback_rpc_urls: Vec::new(),
+        l1_rpc_quorum: None,
+        l1_beacon_url: cli.l1_beacon_url,
+        l1_ws_url: None,
+        l2_rpc_url: cli.l2_rpc_url,
+        l2_engine_url: String::new(),
+        chain,
+        jwt_secret: String::new(),
+        checkpoint_sync_url: None,
+        rpc_port: 0,
+        devnet: false,
+        local_sequencer: Default::default(),
+        watcher_delay: Default::default(),
+        l1_prefetch_window: None,
+        l1_fixture: None,
+        pipeline_snapshot: None,
+    });
+
+    let mut recorder = FixtureRecorder::new(config);
+    if let Some(data_dir) = cli.data_dir {
+        recorder = recorder.with_data_dir(data_dir);
+    }
+
+    recorder
+        .record(
+            &cli.output,
+            cli.l1_start_block,
+            cli.l1_end_block,
+            l2_start_block,
+        )
+        .await?;
+
+    tracing::info!("recorded L1 fixture to {:?}", cli.output);
+
+    Ok(())
+}
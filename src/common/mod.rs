@@ -95,8 +95,14 @@ pub struct AttributesDepositedCall {
     pub hash: H256,
     pub sequence_number: u64,
     pub batcher_hash: H256,
+    /// L1 fee overhead, zero from Ecotone on
     pub fee_overhead: U256,
+    /// L1 fee scalar, the base fee scalar from Ecotone on
     pub fee_scalar: U256,
+    /// L1 blob base fee, set from Ecotone on
+    pub blob_base_fee: Option<U256>,
+    /// L1 blob base fee scalar, set from Ecotone on
+    pub blob_base_fee_scalar: Option<u32>,
 }
 
 type SetL1BlockValueInput = (u64, u64, U256, H256, u64, H256, U256, U256);
@@ -104,10 +110,20 @@ const L1_BLOCK_CONTRACT_ABI: &str = r#"[
     function setL1BlockValues(uint64 _number,uint64 _timestamp, uint256 _basefee, bytes32 _hash,uint64 _sequenceNumber,bytes32 _batcherHash,uint256 _l1FeeOverhead,uint256 _l1FeeScalar) external
 ]"#;
 
+/// Selector of the tightly packed `setL1BlockValuesEcotone()` call
+const SET_L1_BLOCK_VALUES_ECOTONE_SELECTOR: [u8; 4] = [0x44, 0x0a, 0x5e, 0x20];
+
+/// Length of the `setL1BlockValuesEcotone()` calldata, including the selector
+const SET_L1_BLOCK_VALUES_ECOTONE_LEN: usize = 164;
+
 impl TryFrom<Bytes> for AttributesDepositedCall {
     type Error = eyre::Report;
 
     fn try_from(value: Bytes) -> Result<Self> {
+        if value.starts_with(&SET_L1_BLOCK_VALUES_ECOTONE_SELECTOR) {
+            return Self::decode_ecotone(&value);
+        }
+
         let abi = BaseContract::from(parse_abi_str(L1_BLOCK_CONTRACT_ABI)?);
 
         let (
@@ -130,6 +146,35 @@ impl TryFrom<Bytes> for AttributesDepositedCall {
             batcher_hash,
             fee_overhead,
             fee_scalar,
+            blob_base_fee: None,
+            blob_base_fee_scalar: None,
+        })
+    }
+}
+
+impl AttributesDepositedCall {
+    /// Decodes the tightly packed `setL1BlockValuesEcotone()` calldata
+    fn decode_ecotone(data: &[u8]) -> Result<Self> {
+        if data.len() != SET_L1_BLOCK_VALUES_ECOTONE_LEN {
+            eyre::bail!("invalid ecotone l1 attributes length: {}", data.len());
+        }
+
+        let u32_at =
+            |offset: usize| u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap());
+        let u64_at =
+            |offset: usize| u64::from_be_bytes(data[offset..offset + 8].try_into().unwrap());
+
+        Ok(Self {
+            fee_scalar: u32_at(4).into(),
+            blob_base_fee_scalar: Some(u32_at(8)),
+            sequence_number: u64_at(12),
+            timestamp: u64_at(20),
+            number: u64_at(28),
+            basefee: U256::from_big_endian(&data[36..68]),
+            blob_base_fee: Some(U256::from_big_endian(&data[68..100])),
+            hash: H256::from_slice(&data[100..132]),
+            batcher_hash: H256::from_slice(&data[132..164]),
+            fee_overhead: U256::zero(),
         })
     }
 }
@@ -151,6 +196,18 @@ impl Decodable for RawTransaction {
     }
 }
 
+impl RawTransaction {
+    /// Returns the hash of the transaction
+    pub fn hash(&self) -> H256 {
+        H256::from(ethers::utils::keccak256(&self.0))
+    }
+
+    /// Returns true if the transaction is a deposit
+    pub fn is_deposit(&self) -> bool {
+        self.0.first() == Some(&0x7E)
+    }
+}
+
 impl Debug for RawTransaction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "0x{}", hex::encode(&self.0))
@@ -200,6 +257,42 @@ mod tests {
             assert_eq!(call.hash, expected_hash);
             assert_eq!(call.number, expected_block_number);
             assert_eq!(call.timestamp, expected_timestamp);
+            assert!(call.blob_base_fee.is_none());
+
+            Ok(())
+        }
+
+        #[test]
+        fn decode_ecotone_from_bytes() -> eyre::Result<()> {
+            // the l1 attributes of an OP mainnet block in epoch 19655712
+            let calldata = "0x440a5e2000000558000c5fc5000000000000000500000000661c277300000000012bec20000000000000000000000000000000000000000000000000000000026e9f109900000000000000000000000000000000000000000000000000000000000000011c4c84c50740386c7dc081efddd644405f04cde73e30a2e381737acce9f5add30000000000000000000000006887246668a3b87f54deb3b94ba47a6f63f32985";
+
+            let call = AttributesDepositedCall::try_from(Bytes::from_str(calldata)?)?;
+
+            assert_eq!(call.number, 19655712);
+            assert_eq!(call.timestamp, 1713121139);
+            assert_eq!(call.sequence_number, 5);
+            assert_eq!(call.basefee, 10445852825u64.into());
+            assert_eq!(call.blob_base_fee, Some(1.into()));
+            assert_eq!(call.fee_scalar, 1368.into());
+            assert_eq!(call.blob_base_fee_scalar, Some(810949));
+            assert_eq!(
+                call.hash,
+                H256::from_str("1c4c84c50740386c7dc081efddd644405f04cde73e30a2e381737acce9f5add3")?
+            );
+            assert_eq!(
+                call.batcher_hash,
+                H256::from_str("0000000000000000000000006887246668a3b87f54deb3b94ba47a6f63f32985")?
+            );
+
+            Ok(())
+        }
+
+        #[test]
+        fn decode_ecotone_invalid_length() -> eyre::Result<()> {
+            let calldata = "0x440a5e200000055800000000";
+
+            assert!(AttributesDepositedCall::try_from(Bytes::from_str(calldata)?).is_err());
 
             Ok(())
         }
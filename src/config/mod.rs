@@ -1,6 +1,6 @@
 use std::{iter, path::PathBuf, process::exit, str::FromStr};
 
-use ethers::types::{Address, H256, U256};
+use ethers::types::{Address, H256, H64, U256};
 use figment::{
     providers::{Format, Serialized, Toml},
     Figment,
@@ -20,6 +20,8 @@ pub enum SyncMode {
     Challenge,
     /// Full sync mode
     Full,
+    /// Verifies the blocks of the L2 node against the derived chain, without driving it
+    Verify,
 }
 
 impl FromStr for SyncMode {
@@ -31,6 +33,7 @@ impl FromStr for SyncMode {
             "checkpoint" => Ok(Self::Checkpoint),
             "challenge" => Ok(Self::Challenge),
             "full" => Ok(Self::Full),
+            "verify" => Ok(Self::Verify),
             _ => Err("invalid sync mode".to_string()),
         }
     }
@@ -41,6 +44,15 @@ impl FromStr for SyncMode {
 pub struct Config {
     /// The base chain RPC URL
     pub l1_rpc_url: String,
+    /// Additional base chain RPC URLs to fail over to when `l1_rpc_url` is unhealthy
+    #[serde(default)]
+    pub l1_fallback_rpc_urls: Vec<String>,
+    /// The number of L1 RPC endpoints that must agree on each block, log, call and storage read at a block number or hash
+    pub l1_rpc_quorum: Option<usize>,
+    /// The base chain beacon node URL, used to fetch blob sidecars
+    pub l1_beacon_url: Option<String>,
+    /// The base chain WebSocket URL, used to subscribe to new heads instead of polling
+    pub l1_ws_url: Option<String>,
     /// The L2 chain RPC URL
     pub l2_rpc_url: String,
     /// The L2 engine API URL
@@ -60,6 +72,13 @@ pub struct Config {
     pub local_sequencer: LocalSequencerConfig,
     /// Watcher query delay
     pub watcher_delay: u64,
+    /// The maximum number of L1 blocks the watcher fetches ahead while catching up
+    pub l1_prefetch_window: Option<usize>,
+    /// A recorded L1 fixture to replay instead of watching the L1 RPC
+    pub l1_fixture: Option<PathBuf>,
+    /// Where to persist the derivation pipeline state after each L1 block, so
+    /// restarts resume at the last derived block
+    pub pipeline_snapshot: Option<PathBuf>,
 }
 
 /// A local sequencer configuration.
@@ -101,6 +120,31 @@ impl Config {
             }
         }
     }
+
+    /// A config for OP mainnet without any URLs or optional features, used as the
+    /// base of test configs
+    #[cfg(test)]
+    pub fn default_for_tests() -> Self {
+        Self {
+            l1_rpc_url: String::new(),
+            l1_fallback_rpc_urls: Vec::new(),
+            l1_rpc_quorum: None,
+            l1_beacon_url: None,
+            l1_ws_url: None,
+            l2_rpc_url: "http://127.0.0.1:8545".to_string(),
+            l2_engine_url: String::new(),
+            chain: ChainConfig::optimism(),
+            jwt_secret: String::new(),
+            checkpoint_sync_url: None,
+            rpc_port: 9545,
+            devnet: false,
+            local_sequencer: Default::default(),
+            watcher_delay: Default::default(),
+            l1_prefetch_window: None,
+            l1_fixture: None,
+            pipeline_snapshot: None,
+        }
+    }
 }
 
 /// Chain config items derived from the CLI
@@ -108,6 +152,14 @@ impl Config {
 pub struct CliConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub l1_rpc_url: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub l1_fallback_rpc_urls: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub l1_rpc_quorum: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub l1_beacon_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub l1_ws_url: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub l2_rpc_url: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -124,6 +176,12 @@ pub struct CliConfig {
     pub local_sequencer: Option<LocalSequencerConfig>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub watcher_delay: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub l1_prefetch_window: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub l1_fixture: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pipeline_snapshot: Option<PathBuf>,
 }
 
 /// A Chain Configuration
@@ -157,6 +215,24 @@ pub struct ChainConfig {
     pub max_seq_drift: u64,
     /// Timestamp of the regolith hardfork
     pub regolith_time: u64,
+    /// Timestamp of the canyon hardfork
+    #[serde(default = "default_fork_time")]
+    pub canyon_time: u64,
+    /// Timestamp of the delta hardfork
+    #[serde(default = "default_fork_time")]
+    pub delta_time: u64,
+    /// Timestamp of the ecotone hardfork
+    #[serde(default = "default_fork_time")]
+    pub ecotone_time: u64,
+    /// Timestamp of the fjord hardfork
+    #[serde(default = "default_fork_time")]
+    pub fjord_time: u64,
+    /// Timestamp of the granite hardfork
+    #[serde(default = "default_fork_time")]
+    pub granite_time: u64,
+    /// Timestamp of the holocene hardfork
+    #[serde(default = "default_fork_time")]
+    pub holocene_time: u64,
     /// Network blocktime
     #[serde(default = "default_blocktime")]
     pub blocktime: u64,
@@ -164,6 +240,9 @@ pub struct ChainConfig {
     pub l2_to_l1_message_passer: Address,
     /// Protocol meta configuration
     pub meta: ProtocolMetaConfig,
+    /// Sources of batcher data, read in order for each L1 block
+    #[serde(default = "default_data_sources")]
+    pub data_sources: Vec<DataSourceConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -173,6 +252,124 @@ pub struct ProtocolMetaConfig {
     pub enable_full_derivation: bool,
 }
 
+/// A source of batcher data
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DataSourceConfig {
+    /// Calldata of batcher transactions to the batch inbox
+    Calldata,
+    /// Calldata and EIP-4844 blobs of batcher transactions to the batch inbox
+    Blobs,
+    /// Batcher data stored in local files, one JSON array of hex strings per L1 block
+    Files { dir: PathBuf },
+    /// Batcher data posted as commitments and served by an alt-DA server. Challenges
+    /// are tracked when a DataAvailabilityChallenge contract is set.
+    AltDa {
+        server_url: String,
+        #[serde(default)]
+        challenge_contract: Option<Address>,
+        /// Number of L1 blocks after inclusion in which a commitment can be challenged
+        #[serde(default)]
+        challenge_window: u64,
+        /// Number of L1 blocks after a challenge in which it can be resolved
+        #[serde(default)]
+        resolve_window: u64,
+    },
+}
+
+/// Maximum sequencer drift from Fjord onwards
+const FJORD_MAX_SEQ_DRIFT: u64 = 1800;
+
+/// Channel timeout in L1 blocks from Granite onwards
+const GRANITE_CHANNEL_TIMEOUT: u64 = 50;
+
+/// Maximum decompressed bytes per channel before Fjord
+const MAX_RLP_BYTES_PER_CHANNEL: u64 = 10_000_000;
+
+/// Maximum decompressed bytes per channel from Fjord onwards
+const FJORD_MAX_RLP_BYTES_PER_CHANNEL: u64 = 100_000_000;
+
+impl ChainConfig {
+    /// Returns true if the regolith hardfork is active at the timestamp
+    pub fn is_regolith_active(&self, timestamp: u64) -> bool {
+        timestamp >= self.regolith_time
+    }
+
+    /// Returns true if the canyon hardfork is active at the timestamp
+    pub fn is_canyon_active(&self, timestamp: u64) -> bool {
+        timestamp >= self.canyon_time
+    }
+
+    /// Returns true if the delta hardfork is active at the timestamp
+    pub fn is_delta_active(&self, timestamp: u64) -> bool {
+        timestamp >= self.delta_time
+    }
+
+    /// Returns true if the ecotone hardfork is active at the timestamp
+    pub fn is_ecotone_active(&self, timestamp: u64) -> bool {
+        timestamp >= self.ecotone_time
+    }
+
+    /// Returns true if the L2 block at the timestamp is the first block of the ecotone hardfork
+    pub fn is_ecotone_activation_block(&self, timestamp: u64) -> bool {
+        self.is_activation_block(self.ecotone_time, timestamp)
+    }
+
+    /// Returns true if the fjord hardfork is active at the timestamp
+    pub fn is_fjord_active(&self, timestamp: u64) -> bool {
+        timestamp >= self.fjord_time
+    }
+
+    /// Returns true if the L2 block at the timestamp is the first block of the fjord hardfork
+    pub fn is_fjord_activation_block(&self, timestamp: u64) -> bool {
+        self.is_activation_block(self.fjord_time, timestamp)
+    }
+
+    /// Returns true if the granite hardfork is active at the timestamp
+    pub fn is_granite_active(&self, timestamp: u64) -> bool {
+        timestamp >= self.granite_time
+    }
+
+    /// Returns true if the holocene hardfork is active at the timestamp
+    pub fn is_holocene_active(&self, timestamp: u64) -> bool {
+        timestamp >= self.holocene_time
+    }
+
+    /// Returns true if the L2 block at the timestamp is the first block at or after `fork_time`
+    fn is_activation_block(&self, fork_time: u64, timestamp: u64) -> bool {
+        timestamp >= fork_time
+            && timestamp >= self.blocktime
+            && timestamp - self.blocktime < fork_time
+    }
+
+    /// The maximum sequencer drift for an L2 block whose L1 origin has the timestamp
+    pub fn max_seq_drift_at(&self, l1_origin_timestamp: u64) -> u64 {
+        if self.is_fjord_active(l1_origin_timestamp) {
+            FJORD_MAX_SEQ_DRIFT
+        } else {
+            self.max_seq_drift
+        }
+    }
+
+    /// The channel timeout for an L1 block at the timestamp
+    pub fn channel_timeout_at(&self, l1_timestamp: u64) -> u64 {
+        if self.is_granite_active(l1_timestamp) {
+            GRANITE_CHANNEL_TIMEOUT
+        } else {
+            self.channel_timeout
+        }
+    }
+
+    /// The maximum decompressed size of a channel read at the L1 timestamp
+    pub fn max_rlp_bytes_per_channel(&self, l1_timestamp: u64) -> u64 {
+        if self.is_fjord_active(l1_timestamp) {
+            FJORD_MAX_RLP_BYTES_PER_CHANNEL
+        } else {
+            MAX_RLP_BYTES_PER_CHANNEL
+        }
+    }
+}
+
 impl ProtocolMetaConfig {
     pub fn optimism() -> Self {
         Self {
@@ -196,16 +393,42 @@ pub struct SystemConfig {
     pub l1_fee_scalar: U256,
     /// Sequencer's signer for unsafe blocks
     pub unsafe_block_signer: Address,
+    /// EIP-1559 base fee parameters from Holocene on, packed as `denominator ++ elasticity`
+    #[serde(default)]
+    pub eip_1559_params: u64,
 }
 
 impl SystemConfig {
     /// Encoded batch sender as a H256
     pub fn batcher_hash(&self) -> H256 {
         let mut batch_sender_bytes = self.batch_sender.as_bytes().to_vec();
-        let mut batcher_hash = iter::repeat(0).take(12).collect::<Vec<_>>();
+        let mut batcher_hash = iter::repeat_n(0, 12).collect::<Vec<_>>();
         batcher_hash.append(&mut batch_sender_bytes);
         H256::from_slice(&batcher_hash)
     }
+
+    /// Decodes the Ecotone `(base_fee_scalar, blob_base_fee_scalar)` pair from the versioned
+    /// fee scalar. Version 0 only carries the base fee scalar, version 1 carries both. Errors
+    /// on unknown versions and on version 1 scalars with non-zero padding, which the system
+    /// config tracker never applies. A version 0 scalar set before Ecotone may still have
+    /// non-zero padding, in which case the spec sets the base fee scalar to `u32::MAX`.
+    pub fn ecotone_scalars(&self) -> eyre::Result<(u32, u32)> {
+        let mut scalar = [0u8; 32];
+        self.l1_fee_scalar.to_big_endian(&mut scalar);
+
+        let base_fee_scalar = u32::from_be_bytes(scalar[28..32].try_into()?);
+        let blob_base_fee_scalar = u32::from_be_bytes(scalar[24..28].try_into()?);
+
+        match scalar[0] {
+            0 if scalar[1..28].iter().all(|b| *b == 0) => Ok((base_fee_scalar, 0)),
+            0 => Ok((u32::MAX, 0)),
+            1 if scalar[1..24].iter().all(|b| *b == 0) => {
+                Ok((base_fee_scalar, blob_base_fee_scalar))
+            }
+            1 => eyre::bail!("invalid padding of fee scalar {:?}", self.l1_fee_scalar),
+            version => eyre::bail!("unknown fee scalar version {}", version),
+        }
+    }
 }
 
 /// System accounts
@@ -276,6 +499,7 @@ impl ChainConfig {
                 l1_fee_overhead: U256::from(188),
                 l1_fee_scalar: U256::from(684000),
                 unsafe_block_signer: addr("0xAAAA45d9549EDA09E70937013520214382Ffc4A2"),
+                eip_1559_params: 0,
             },
             batch_inbox: addr("0xff00000000000000000000000000000000000010"),
             deposit_contract: addr("0xbEb5Fc579115071764c7423A4f12eDde41f106Ed"),
@@ -287,7 +511,15 @@ impl ChainConfig {
             max_seq_drift: 600,
             blocktime: 2,
             regolith_time: 0,
+            canyon_time: 1704992401,
+            delta_time: 1708560000,
+            ecotone_time: 1710374401,
+            fjord_time: 1720627201,
+            granite_time: 1726070401,
+            // the holocene derivation rules are not implemented yet
+            holocene_time: u64::MAX,
             meta: ProtocolMetaConfig::optimism(),
+            data_sources: default_data_sources(),
         }
     }
 
@@ -315,6 +547,7 @@ impl ChainConfig {
                 l1_fee_overhead: U256::from(2100),
                 l1_fee_scalar: U256::from(1000000),
                 unsafe_block_signer: addr("0x715b7219D986641DF9eFd9C7Ef01218D528e19ec"),
+                eip_1559_params: 0,
             },
             system_config_contract: addr("0xAe851f927Ee40dE99aaBb7461C00f9622ab91d60"),
             batch_inbox: addr("0xff00000000000000000000000000000000000420"),
@@ -325,8 +558,15 @@ impl ChainConfig {
             seq_window_size: 3600,
             max_seq_drift: 600,
             regolith_time: 1679079600,
+            canyon_time: 1699981200,
+            delta_time: 1703116800,
+            ecotone_time: 1707238800,
+            fjord_time: u64::MAX,
+            granite_time: u64::MAX,
+            holocene_time: u64::MAX,
             blocktime: 2,
             meta: ProtocolMetaConfig::optimism(),
+            data_sources: default_data_sources(),
         }
     }
     pub fn optimism_sepolia() -> Self {
@@ -353,6 +593,7 @@ impl ChainConfig {
                 l1_fee_overhead: U256::from(188),
                 l1_fee_scalar: U256::from(684000),
                 unsafe_block_signer: addr("0x0000000000000000000000000000000000000000"),
+                eip_1559_params: 0,
             },
             system_config_contract: addr("0x034edd2a225f7f429a63e0f1d2084b9e0a93b538"),
             batch_inbox: addr("0xff00000000000000000000000000000011155420"),
@@ -363,8 +604,16 @@ impl ChainConfig {
             seq_window_size: 3600,
             max_seq_drift: 600,
             regolith_time: 0,
+            canyon_time: 1699981200,
+            delta_time: 1703203200,
+            ecotone_time: 1708534800,
+            fjord_time: 1716998400,
+            granite_time: 1723478400,
+            // the holocene derivation rules are not implemented yet
+            holocene_time: u64::MAX,
             blocktime: 2,
             meta: ProtocolMetaConfig::optimism(),
+            data_sources: default_data_sources(),
         }
     }
 
@@ -390,6 +639,7 @@ impl ChainConfig {
                 l1_fee_overhead: U256::from(188),
                 l1_fee_scalar: U256::from(684000),
                 unsafe_block_signer: addr("0xAf6E19BE0F9cE7f8afd49a1824851023A8249e8a"),
+                eip_1559_params: 0,
             },
             batch_inbox: addr("0xff00000000000000000000000000000000008453"),
             deposit_contract: addr("0x49048044d57e1c92a77f79988d21fa8faf74e97e"),
@@ -401,7 +651,15 @@ impl ChainConfig {
             max_seq_drift: 600,
             blocktime: 2,
             regolith_time: 0,
+            canyon_time: 1704992401,
+            delta_time: 1708560000,
+            ecotone_time: 1710374401,
+            fjord_time: 1720627201,
+            granite_time: 1726070401,
+            // the holocene derivation rules are not implemented yet
+            holocene_time: u64::MAX,
             meta: ProtocolMetaConfig::optimism(),
+            data_sources: default_data_sources(),
         }
     }
 
@@ -427,6 +685,7 @@ impl ChainConfig {
                 l1_fee_overhead: U256::from(2100),
                 l1_fee_scalar: U256::from(1000000),
                 unsafe_block_signer: addr("0x32a4e99A72c11E9DD3dC159909a2D7BD86C1Bc51"),
+                eip_1559_params: 0,
             },
             system_config_contract: addr("0xb15eea247ece011c68a614e4a77ad648ff495bc1"),
             batch_inbox: addr("0x8453100000000000000000000000000000000000"),
@@ -437,8 +696,15 @@ impl ChainConfig {
             seq_window_size: 3600,
             max_seq_drift: 600,
             regolith_time: 1683219600,
+            canyon_time: 1699981200,
+            delta_time: 1703203200,
+            ecotone_time: 1708534800,
+            fjord_time: u64::MAX,
+            granite_time: u64::MAX,
+            holocene_time: u64::MAX,
             blocktime: 2,
             meta: ProtocolMetaConfig::optimism(),
+            data_sources: default_data_sources(),
         }
     }
 }
@@ -465,6 +731,14 @@ fn default_blocktime() -> u64 {
     2
 }
 
+fn default_fork_time() -> u64 {
+    u64::MAX
+}
+
+fn default_data_sources() -> Vec<DataSourceConfig> {
+    vec![DataSourceConfig::Blobs]
+}
+
 /// External chain config
 ///
 /// This is used to parse external chain configs from JSON.
@@ -480,6 +754,12 @@ struct ExternalChainConfig {
     l1_chain_id: u64,
     l2_chain_id: u64,
     regolith_time: u64,
+    canyon_time: Option<u64>,
+    delta_time: Option<u64>,
+    ecotone_time: Option<u64>,
+    fjord_time: Option<u64>,
+    granite_time: Option<u64>,
+    holocene_time: Option<u64>,
     batch_inbox_address: Address,
     deposit_contract_address: Address,
     l1_system_config_address: Address,
@@ -501,6 +781,8 @@ struct SystemConfigInfo {
     scalar: H256,
     #[serde(rename = "gasLimit")]
     gas_limit: u64,
+    #[serde(rename = "eip1559Params", default)]
+    eip_1559_params: Option<H64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -532,6 +814,12 @@ impl From<ExternalChainConfig> for ChainConfig {
                 l1_fee_overhead: external.genesis.system_config.overhead.0.into(),
                 l1_fee_scalar: external.genesis.system_config.scalar.0.into(),
                 unsafe_block_signer: Address::zero(),
+                eip_1559_params: external
+                    .genesis
+                    .system_config
+                    .eip_1559_params
+                    .map(|params| u64::from_be_bytes(params.0))
+                    .unwrap_or_default(),
             },
             batch_inbox: external.batch_inbox_address,
             deposit_contract: external.deposit_contract_address,
@@ -541,9 +829,16 @@ impl From<ExternalChainConfig> for ChainConfig {
             seq_window_size: external.seq_window_size,
             max_seq_drift: external.max_sequencer_drift,
             regolith_time: external.regolith_time,
+            canyon_time: external.canyon_time.unwrap_or(u64::MAX),
+            delta_time: external.delta_time.unwrap_or(u64::MAX),
+            ecotone_time: external.ecotone_time.unwrap_or(u64::MAX),
+            fjord_time: external.fjord_time.unwrap_or(u64::MAX),
+            granite_time: external.granite_time.unwrap_or(u64::MAX),
+            holocene_time: external.holocene_time.unwrap_or(u64::MAX),
             blocktime: external.block_time,
             l2_to_l1_message_passer: addr("0x4200000000000000000000000000000000000016"),
             meta: ProtocolMetaConfig::optimism(),
+            data_sources: default_data_sources(),
         }
     }
 }
@@ -580,6 +875,9 @@ mod test {
             "l1_chain_id": 900,
             "l2_chain_id": 901,
             "regolith_time": 0,
+            "canyon_time": 0,
+            "delta_time": 0,
+            "ecotone_time": 10,
             "batch_inbox_address": "0xff00000000000000000000000000000000000000",
             "deposit_contract_address": "0x6900000000000000000000000000000000000001",
             "l1_system_config_address": "0x6900000000000000000000000000000000000009"
@@ -626,10 +924,106 @@ mod test {
         assert_eq!(chain.seq_window_size, 200);
         assert_eq!(chain.max_seq_drift, 300);
         assert_eq!(chain.regolith_time, 0);
+        assert_eq!(chain.canyon_time, 0);
+        assert_eq!(chain.delta_time, 0);
+        assert_eq!(chain.ecotone_time, 10);
+        assert_eq!(chain.fjord_time, u64::MAX);
+        assert!(chain.is_delta_active(0));
+        assert!(!chain.is_ecotone_active(9));
+        assert!(chain.is_ecotone_active(10));
+        assert!(!chain.is_fjord_active(u64::MAX - 1));
         assert_eq!(chain.blocktime, 2);
         assert_eq!(
             chain.l2_to_l1_message_passer,
             addr("0x4200000000000000000000000000000000000016")
         );
     }
+
+    #[test]
+    fn test_fork_schedule() {
+        let chain = ChainConfig::optimism();
+
+        assert!(!chain.is_ecotone_active(1710374400));
+        assert!(chain.is_ecotone_active(1710374401));
+        assert!(chain.is_ecotone_activation_block(1710374401));
+        assert!(!chain.is_ecotone_activation_block(1710374403));
+
+        assert_eq!(chain.max_seq_drift_at(1720627200), 600);
+        assert_eq!(chain.max_seq_drift_at(1720627201), 1800);
+
+        assert_eq!(chain.channel_timeout_at(1726070400), 300);
+        assert_eq!(chain.channel_timeout_at(1726070401), 50);
+
+        assert_eq!(chain.max_rlp_bytes_per_channel(1720627200), 10_000_000);
+        assert_eq!(chain.max_rlp_bytes_per_channel(1720627201), 100_000_000);
+    }
+
+    #[test]
+    fn test_ecotone_scalars() {
+        let mut system_config = ChainConfig::optimism().system_config;
+
+        system_config.l1_fee_scalar = U256::from(684_000);
+        assert_eq!(system_config.ecotone_scalars().unwrap(), (684_000, 0));
+
+        system_config.l1_fee_scalar = U256::from_big_endian(
+            &hex::decode("010000000000000000000000000000000000000000000000000c5fc500000558")
+                .unwrap(),
+        );
+        assert_eq!(system_config.ecotone_scalars().unwrap(), (1368, 810_949));
+
+        // a pre-ecotone scalar with non-zero padding, as defined by the spec
+        system_config.l1_fee_scalar = U256::from(1) << 100;
+        assert_eq!(system_config.ecotone_scalars().unwrap(), (u32::MAX, 0));
+
+        system_config.l1_fee_scalar = U256::from(1) << 248 | U256::from(1) << 200;
+        assert!(system_config.ecotone_scalars().is_err());
+
+        system_config.l1_fee_scalar = U256::from(2) << 248;
+        assert!(system_config.ecotone_scalars().is_err());
+    }
+
+    #[test]
+    fn test_data_sources() {
+        let chain: Serialized<ChainProvider> = ChainConfig::optimism().into();
+        let default: ChainConfig = Figment::new()
+            .merge(chain.clone())
+            .extract_inner("chain")
+            .unwrap();
+
+        assert_eq!(default.data_sources, vec![DataSourceConfig::Blobs]);
+
+        let toml = r#"
+            [[chain.data_sources]]
+            type = "files"
+            dir = "/tmp/batches"
+
+            [[chain.data_sources]]
+            type = "alt_da"
+            server_url = "http://127.0.0.1:3100"
+            challenge_contract = "0x0000000000000000000000000000000000000001"
+            challenge_window = 3600
+            resolve_window = 3600
+        "#;
+
+        let chain: ChainConfig = Figment::new()
+            .merge(chain)
+            .merge(Toml::string(toml))
+            .extract_inner("chain")
+            .unwrap();
+
+        assert_eq!(
+            chain.data_sources,
+            vec![
+                DataSourceConfig::Files {
+                    dir: PathBuf::from("/tmp/batches")
+                },
+                DataSourceConfig::AltDa {
+                    server_url: "http://127.0.0.1:3100".to_string(),
+                    challenge_contract: Some(Address::from_low_u64_be(1)),
+                    challenge_window: 3600,
+                    resolve_window: 3600,
+                },
+            ]
+        );
+    }
 }
@@ -1,15 +1,24 @@
 use std::sync::{mpsc, Arc, RwLock};
 
 use async_trait::async_trait;
+use ethers::providers::{Http, Provider};
 use eyre::Result;
 
 use crate::specular::stages::{
     batcher_transactions::SpecularBatcherTransactions, batches::SpecularBatches,
 };
-use crate::{config::Config, engine::PayloadAttributes};
+use crate::{
+    config::Config,
+    engine::PayloadAttributes,
+    l1::{
+        data_source::{self, DataSource},
+        L1Info,
+    },
+};
 
 use self::{
     async_iterator::AsyncIterator,
+    snapshot::PipelineSnapshot,
     stages::{
         attributes::Attributes,
         batcher_transactions::{BatcherTransactionMessage, BatcherTransactions},
@@ -20,16 +29,22 @@ use self::{
 };
 
 pub mod async_iterator;
+pub mod snapshot;
+pub mod speculative;
 pub mod stages;
 pub mod state;
+pub mod trace;
 
 mod purgeable;
 pub use purgeable::PurgeableAsyncIterator;
 
 pub struct Pipeline {
     batcher_transaction_sender: mpsc::Sender<BatcherTransactionMessage>,
+    /// Source of the batcher data of each L1 block
+    data_source: Box<dyn DataSource>,
     attributes: Attributes,
     pending_attributes: Option<PayloadAttributes>,
+    state: Arc<RwLock<State>>,
 }
 
 #[async_trait]
@@ -52,7 +67,13 @@ impl Pipeline {
             if config.chain.meta.enable_full_derivation {
                 let batcher_transactions = BatcherTransactions::new(rx);
                 let channels = Channels::new(batcher_transactions, config.clone());
-                let batches = Batches::new(channels, state.clone(), config.clone());
+                let l2_provider = Provider::<Http>::try_from(config.l2_rpc_url.as_str())?;
+                let batches = Batches::new(
+                    channels,
+                    state.clone(),
+                    config.clone(),
+                    Arc::new(l2_provider),
+                );
                 Box::new(batches)
             } else {
                 let batcher_transactions = SpecularBatcherTransactions::new(rx);
@@ -60,18 +81,48 @@ impl Pipeline {
                     SpecularBatches::new(batcher_transactions, state.clone(), config.clone());
                 Box::new(batches)
             };
-        let attributes = Attributes::new(batch_iter, state, config, seq);
+        let attributes = Attributes::new(batch_iter, state.clone(), config.clone(), seq);
 
         Ok(Self {
             batcher_transaction_sender: tx,
+            data_source: data_source::from_config(&config)?,
             attributes,
             pending_attributes: None,
+            state,
         })
     }
 
-    pub fn push_batcher_transactions(&self, txs: Vec<Vec<u8>>, l1_origin: u64) -> Result<()> {
+    /// Takes the error that halted derivation, if the pipeline stopped producing
+    /// attributes because a batch could not be turned into attributes
+    pub fn take_critical_error(&mut self) -> Option<eyre::Report> {
+        self.attributes.take_critical_error()
+    }
+
+    /// Replaces the data source selected by the chain config
+    pub fn with_data_source(mut self, data_source: Box<dyn DataSource>) -> Self {
+        self.data_source = data_source;
+        self
+    }
+
+    /// Reads the batcher data of the L1 block from the data source and feeds it
+    /// into the pipeline
+    pub async fn push_l1_block(&mut self, l1_info: &L1Info) -> Result<()> {
+        let txs = self.data_source.open_data(l1_info).await?;
+        self.push_batcher_transactions(txs, l1_info.block_info.number, l1_info.block_info.timestamp)
+    }
+
+    fn push_batcher_transactions(
+        &self,
+        txs: Vec<Vec<u8>>,
+        l1_origin: u64,
+        l1_timestamp: u64,
+    ) -> Result<()> {
         self.batcher_transaction_sender
-            .send(BatcherTransactionMessage { txs, l1_origin })?;
+            .send(BatcherTransactionMessage {
+                txs,
+                l1_origin,
+                l1_timestamp,
+            })?;
         Ok(())
     }
 
@@ -88,28 +139,322 @@ impl Pipeline {
         self.attributes.purge().await;
         Ok(())
     }
+
+    /// Takes a snapshot of the pipeline stages and the derivation state. The
+    /// pipeline should be drained first, as peeked attributes are not included.
+    pub fn snapshot(&self) -> Result<PipelineSnapshot> {
+        eyre::ensure!(
+            self.pending_attributes.is_none(),
+            "cannot snapshot the pipeline with pending attributes"
+        );
+
+        let state = self
+            .state
+            .read()
+            .map_err(|_| eyre::eyre!("lock poisoned"))?
+            .snapshot();
+
+        let mut snapshot = PipelineSnapshot::new(state);
+        self.attributes.snapshot(&mut snapshot)?;
+
+        Ok(snapshot)
+    }
+
+    /// Restores the pipeline stages and the derivation state from a snapshot
+    pub fn restore(&mut self, snapshot: PipelineSnapshot) -> Result<()> {
+        self.attributes.restore(&snapshot)?;
+        self.pending_attributes = None;
+
+        self.state
+            .write()
+            .map_err(|_| eyre::eyre!("lock poisoned"))?
+            .restore(snapshot.state);
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::{
         env,
+        io::Write,
         sync::{Arc, RwLock},
     };
 
     use ethers::{
         providers::{Middleware, Provider},
         types::H256,
-        utils::keccak256,
+        utils::{keccak256, rlp::RlpStream},
     };
+    use libflate::zlib::Encoder;
+
+    use tokio::time::{timeout, Duration};
 
     use crate::{
-        common::RawTransaction,
-        config::{ChainConfig, Config},
-        derive::*,
-        l1::{BlockUpdate, ChainWatcher},
+        common::{BlockInfo, Epoch, RawTransaction},
+        config::{ChainConfig, Config, DataSourceConfig},
+        derive::{speculative::SpeculativePipeline, *},
+        l1::{BatcherTransactionData, BlockUpdate, ChainWatcher, L1BlockInfo, L1Info},
     };
 
+    #[tokio::test]
+    async fn test_attributes_from_fixture() {
+        let path = env::temp_dir().join(format!("magi-pipeline-{}.jsonl", std::process::id()));
+
+        let mut chain = ChainConfig::optimism();
+        chain.data_sources = vec![DataSourceConfig::Calldata];
+
+        let config = Arc::new(Config {
+            chain,
+            l1_fixture: Some(path.clone()),
+            ..Config::default_for_tests()
+        });
+
+        let epoch = config.chain.l1_start_epoch;
+        let genesis = config.chain.l2_genesis;
+        let timestamp = genesis.timestamp + config.chain.blocktime;
+
+        let l1_info = l1_info(
+            epoch,
+            vec![batcher_tx(
+                genesis.hash,
+                epoch.number,
+                epoch.hash,
+                timestamp,
+            )],
+            &config,
+        );
+
+        let update = BlockUpdate::NewBlock(Box::new(l1_info));
+        std::fs::write(&path, serde_json::to_string(&update).unwrap()).unwrap();
+
+        let mut chain_watcher =
+            ChainWatcher::new(epoch.number, genesis.number, config.clone()).unwrap();
+        chain_watcher.start().unwrap();
+
+        let state = Arc::new(RwLock::new(State::new(genesis, epoch, config.clone())));
+        let mut pipeline = Pipeline::new(state.clone(), config.clone(), 0).unwrap();
+
+        let l1_info = match chain_watcher.recv_from_channel().await.unwrap() {
+            BlockUpdate::NewBlock(block) => *block,
+            _ => panic!("wrong update type"),
+        };
+
+        pipeline.push_l1_block(&l1_info).await.unwrap();
+        state.write().unwrap().update_l1_info(l1_info);
+
+        let attributes = pipeline.next().await.unwrap();
+        assert_eq!(attributes.timestamp.as_u64(), timestamp);
+        assert_eq!(attributes.epoch.unwrap(), epoch);
+        assert_eq!(attributes.transactions.unwrap().len(), 1);
+
+        assert!(chain_watcher.recv_from_channel().await.is_none());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resume_from_snapshot() {
+        let path = env::temp_dir().join(format!("magi-snapshot-{}.json", std::process::id()));
+
+        let mut chain = ChainConfig::optimism();
+        chain.data_sources = vec![DataSourceConfig::Calldata];
+
+        let config = Arc::new(Config {
+            chain,
+            pipeline_snapshot: Some(path.clone()),
+            ..Config::default_for_tests()
+        });
+
+        let epoch = config.chain.l1_start_epoch;
+        let genesis = config.chain.l2_genesis;
+        let timestamp = genesis.timestamp + config.chain.blocktime;
+
+        // split the channel over two L1 blocks, so it is pending in the snapshot
+        let channel = channel_data(genesis.hash, epoch.number, epoch.hash, timestamp);
+        let (first, last) = channel.split_at(channel.len() / 2);
+
+        let next_epoch = Epoch {
+            number: epoch.number + 1,
+            hash: H256::from_low_u64_be(epoch.number + 1),
+            timestamp: epoch.timestamp + 12,
+        };
+        let first_block = l1_info(epoch, vec![frame(1, 0, first, false)], &config);
+        let last_block = l1_info(next_epoch, vec![frame(1, 1, last, true)], &config);
+
+        let state = Arc::new(RwLock::new(State::new(genesis, epoch, config.clone())));
+        let mut pipeline = Pipeline::new(state.clone(), config.clone(), 0).unwrap();
+
+        pipeline.push_l1_block(&first_block).await.unwrap();
+        state.write().unwrap().update_l1_info(first_block);
+        assert!(pipeline.next().await.is_none());
+
+        pipeline.snapshot().unwrap().save(&path).unwrap();
+        let snapshot = PipelineSnapshot::load(&path).unwrap().unwrap();
+        assert_eq!(
+            snapshot.state.l1_head().unwrap().block_info.hash,
+            epoch.hash
+        );
+
+        let state = Arc::new(RwLock::new(State::new(genesis, epoch, config.clone())));
+        let mut pipeline = Pipeline::new(state.clone(), config.clone(), 0).unwrap();
+        pipeline.restore(snapshot).unwrap();
+        assert_eq!(state.read().unwrap().current_epoch_num, epoch.number);
+
+        pipeline.push_l1_block(&last_block).await.unwrap();
+        state.write().unwrap().update_l1_info(last_block);
+
+        let attributes = pipeline.next().await.unwrap();
+        assert_eq!(attributes.timestamp.as_u64(), timestamp);
+        assert_eq!(attributes.epoch.unwrap(), epoch);
+        assert_eq!(attributes.l1_inclusion_block, Some(next_epoch.number));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_speculative_derivation() {
+        let mut chain = ChainConfig::optimism();
+        chain.data_sources = vec![DataSourceConfig::Calldata];
+
+        let config = Arc::new(Config {
+            chain,
+            ..Config::default_for_tests()
+        });
+
+        let epoch = config.chain.l1_start_epoch;
+        let genesis = config.chain.l2_genesis;
+        let first_timestamp = genesis.timestamp + config.chain.blocktime;
+        let second_timestamp = first_timestamp + config.chain.blocktime;
+
+        // the second batch can only be checked once the first block is executed
+        let first_hash = H256::from_low_u64_be(0x22);
+        let l1_block = || {
+            Box::new(l1_info(
+                epoch,
+                vec![
+                    frame(
+                        1,
+                        0,
+                        &channel_data(genesis.hash, epoch.number, epoch.hash, first_timestamp),
+                        true,
+                    ),
+                    frame(
+                        2,
+                        0,
+                        &channel_data(first_hash, epoch.number, epoch.hash, second_timestamp),
+                        true,
+                    ),
+                ],
+                &config,
+            ))
+        };
+
+        let state = Arc::new(RwLock::new(State::new(genesis, epoch, config.clone())));
+        let pipeline = Pipeline::new(state.clone(), config.clone(), 0).unwrap();
+        let (reset_sender, _reset_recv) = tokio::sync::mpsc::unbounded_channel();
+        let mut pipeline =
+            SpeculativePipeline::spawn(pipeline, state.clone(), 4, None, reset_sender);
+
+        pipeline.push_l1_block(l1_block()).unwrap();
+
+        let attributes = pipeline.recv().await.unwrap().unwrap();
+        assert_eq!(attributes.timestamp.as_u64(), first_timestamp);
+        assert!(!state.read().unwrap().is_safe_head_confirmed());
+
+        let pending = timeout(Duration::from_millis(100), pipeline.recv()).await;
+        assert!(pending.is_err());
+
+        let first_block = BlockInfo {
+            hash: first_hash,
+            number: genesis.number + 1,
+            parent_hash: genesis.hash,
+            timestamp: first_timestamp,
+        };
+        pipeline.confirm(first_block, epoch).unwrap();
+
+        let attributes = pipeline.recv().await.unwrap().unwrap();
+        assert_eq!(attributes.timestamp.as_u64(), second_timestamp);
+
+        // attributes derived before a reset are dropped
+        pipeline.push_l1_block(l1_block()).unwrap();
+        pipeline.reset(genesis, epoch).unwrap();
+        pipeline.push_l1_block(l1_block()).unwrap();
+
+        let attributes = pipeline.recv().await.unwrap().unwrap();
+        assert_eq!(attributes.timestamp.as_u64(), first_timestamp);
+        assert_eq!(state.read().unwrap().safe_head.number, genesis.number + 1);
+    }
+
+    fn l1_info(epoch: Epoch, calldata: Vec<Vec<u8>>, config: &Config) -> L1Info {
+        L1Info {
+            block_info: L1BlockInfo {
+                number: epoch.number,
+                hash: epoch.hash,
+                timestamp: epoch.timestamp,
+                base_fee: 1.into(),
+                mix_hash: H256::zero(),
+                state_root: H256::zero(),
+                parent_beacon_root: None,
+                excess_blob_gas: None,
+                blob_base_fee: 1.into(),
+            },
+            system_config: config.chain.system_config,
+            user_deposits: Vec::new(),
+            batcher_transactions: calldata
+                .into_iter()
+                .map(|calldata| BatcherTransactionData {
+                    calldata,
+                    ..Default::default()
+                })
+                .collect(),
+            finalized: true,
+        }
+    }
+
+    /// Encodes a batcher transaction with a single frame holding a channel with an
+    /// empty batch
+    fn batcher_tx(parent_hash: H256, epoch_num: u64, epoch_hash: H256, timestamp: u64) -> Vec<u8> {
+        let channel = channel_data(parent_hash, epoch_num, epoch_hash, timestamp);
+        frame(1, 0, &channel, true)
+    }
+
+    /// Encodes a compressed channel holding an empty batch
+    fn channel_data(
+        parent_hash: H256,
+        epoch_num: u64,
+        epoch_hash: H256,
+        timestamp: u64,
+    ) -> Vec<u8> {
+        let mut batch = RlpStream::new_list(5);
+        batch.append(&parent_hash);
+        batch.append(&epoch_num);
+        batch.append(&epoch_hash);
+        batch.append(&timestamp);
+        batch.begin_list(0);
+
+        let batch_data = [vec![0], batch.out().to_vec()].concat();
+        let mut encoder = Encoder::new(Vec::new()).unwrap();
+        encoder
+            .write_all(&ethers::utils::rlp::encode(&batch_data))
+            .unwrap();
+        encoder.finish().into_result().unwrap()
+    }
+
+    /// Encodes a batcher transaction with a single frame
+    fn frame(channel_id: u128, frame_number: u16, data: &[u8], is_last: bool) -> Vec<u8> {
+        [
+            [0].as_slice(),
+            &channel_id.to_be_bytes(),
+            &frame_number.to_be_bytes(),
+            &(data.len() as u32).to_be_bytes(),
+            data,
+            &[is_last as u8],
+        ]
+        .concat()
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_attributes_match() {
         if std::env::var("L1_TEST_RPC_URL").is_ok() && std::env::var("L2_TEST_RPC_URL").is_ok() {
@@ -120,13 +465,7 @@ mod tests {
                 l1_rpc_url: rpc.to_string(),
                 l2_rpc_url: l2_rpc.to_string(),
                 chain: ChainConfig::optimism_goerli(),
-                l2_engine_url: String::new(),
-                jwt_secret: String::new(),
-                checkpoint_sync_url: None,
-                rpc_port: 9545,
-                devnet: false,
-                local_sequencer: Default::default(),
-                watcher_delay: Default::default(),
+                ..Config::default_for_tests()
             });
 
             let mut chain_watcher = ChainWatcher::new(
@@ -154,12 +493,7 @@ mod tests {
                 _ => panic!("wrong update type"),
             };
 
-            pipeline
-                .push_batcher_transactions(
-                    l1_info.batcher_transactions.clone(),
-                    l1_info.block_info.number,
-                )
-                .unwrap();
+            pipeline.push_l1_block(&l1_info).await.unwrap();
 
             state.write().unwrap().update_l1_info(l1_info);
 
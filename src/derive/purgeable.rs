@@ -1,8 +1,21 @@
 use crate::derive::async_iterator::AsyncIterator;
+use crate::derive::snapshot::PipelineSnapshot;
 use async_trait::async_trait;
+use eyre::Result;
 
 /// AsyncIterator that can purge itself
 #[async_trait]
 pub trait PurgeableAsyncIterator: AsyncIterator {
     async fn purge(&mut self);
+
+    /// Writes the data buffered by this stage and its inner stages to the snapshot.
+    /// Stages that buffer nothing between L1 blocks can keep the default.
+    fn snapshot(&self, _snapshot: &mut PipelineSnapshot) -> Result<()> {
+        Ok(())
+    }
+
+    /// Restores the data buffered by this stage and its inner stages
+    fn restore(&mut self, _snapshot: &PipelineSnapshot) -> Result<()> {
+        Ok(())
+    }
 }
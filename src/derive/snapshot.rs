@@ -0,0 +1,70 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use eyre::Result;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use super::state::StateSnapshot;
+
+/// A snapshot of the derivation pipeline, taken once all data of an L1 block has
+/// been derived. Restoring it lets derivation continue at the next L1 block
+/// instead of rewinding the chain watcher by the channel timeout.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineSnapshot {
+    /// The derivation state, including the L1 info of the recent L1 blocks
+    pub state: StateSnapshot,
+    /// The buffered data of each pipeline stage, by stage name
+    stages: BTreeMap<String, Value>,
+}
+
+impl PipelineSnapshot {
+    pub fn new(state: StateSnapshot) -> Self {
+        Self {
+            state,
+            stages: BTreeMap::new(),
+        }
+    }
+
+    /// Stores the buffered data of a stage
+    pub fn set_stage<T: Serialize>(&mut self, name: &str, data: &T) -> Result<()> {
+        self.stages
+            .insert(name.to_string(), serde_json::to_value(data)?);
+        Ok(())
+    }
+
+    /// Returns the buffered data of a stage, if the snapshot contains it
+    pub fn stage<T: DeserializeOwned>(&self, name: &str) -> Result<Option<T>> {
+        self.stages
+            .get(name)
+            .map(|data| serde_json::from_value(data.clone()))
+            .transpose()
+            .map_err(eyre::Report::from)
+    }
+
+    /// Reads a snapshot from disk. Returns `None` if there is no snapshot yet.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let data = fs::read(path)?;
+        Ok(Some(serde_json::from_slice(&data)?))
+    }
+
+    /// Writes the snapshot to disk. The snapshot is written to a temporary file
+    /// first, so a crash never leaves a partially written snapshot behind.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut tmp_path = PathBuf::from(path);
+        tmp_path.set_extension("tmp");
+
+        fs::write(&tmp_path, serde_json::to_vec(self)?)?;
+        fs::rename(&tmp_path, path)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,320 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+    time::Duration,
+};
+
+use eyre::Result;
+use tokio::{
+    select, spawn,
+    sync::{mpsc, Notify},
+    task::JoinHandle,
+    time::sleep,
+};
+
+use crate::{
+    common::{BlockInfo, Epoch, RawTransaction},
+    engine::PayloadAttributes,
+    l1::{alt_da::ExpiredChallenge, L1Info},
+};
+
+use super::{async_iterator::AsyncIterator, state::State, Pipeline};
+
+/// Runs the derivation pipeline in its own task, ahead of the engine. Derived
+/// attributes are queued in a bounded channel, and derivation pauses while the
+/// queue is full. The pipeline advances the safe head in [State] speculatively,
+/// and batches whose validity depends on the hash of a block that the engine has
+/// not executed yet wait until the block is confirmed.
+pub struct SpeculativePipeline {
+    /// Commands for the pipeline task, handled in order
+    commands: mpsc::UnboundedSender<Command>,
+    /// Derived attributes or the critical error that halted derivation, tagged with
+    /// the generation they were derived in
+    attributes: mpsc::Receiver<(u64, Result<PayloadAttributes>)>,
+    /// The current generation, incremented on every reset
+    generation: Arc<AtomicU64>,
+    /// Number of attributes in the queue
+    queued: Arc<AtomicUsize>,
+    /// Wakes up the pipeline task once a block is confirmed
+    confirmed: Arc<Notify>,
+    state: Arc<RwLock<State>>,
+    handle: JoinHandle<()>,
+}
+
+enum Command {
+    /// Feeds an L1 block into the pipeline
+    L1Block(Box<L1Info>),
+    /// Purges the pipeline and resets the safe head
+    Reset {
+        generation: u64,
+        safe_head: BlockInfo,
+        safe_epoch: Epoch,
+    },
+    /// Writes a pipeline snapshot, if the engine has executed all derived attributes
+    Snapshot,
+}
+
+impl SpeculativePipeline {
+    /// Spawns the pipeline task. At most `queue_size` attributes are derived ahead
+    /// of the engine. If the blocks derived from an L1 block turn out to be invalid,
+    /// the L1 block is sent to `reset_requests`, and L1 blocks are ignored until the
+    /// pipeline is reset to before it.
+    pub fn spawn(
+        pipeline: Pipeline,
+        state: Arc<RwLock<State>>,
+        queue_size: usize,
+        snapshot_path: Option<PathBuf>,
+        reset_requests: mpsc::UnboundedSender<u64>,
+    ) -> Self {
+        let (command_sender, command_receiver) = mpsc::unbounded_channel();
+        let (attributes_sender, attributes_receiver) = mpsc::channel(queue_size);
+
+        let generation = Arc::new(AtomicU64::new(0));
+        let queued = Arc::new(AtomicUsize::new(0));
+        let confirmed = Arc::new(Notify::new());
+
+        let task = PipelineTask {
+            pipeline,
+            state: state.clone(),
+            commands: command_receiver,
+            attributes: attributes_sender,
+            generation: generation.clone(),
+            current_generation: 0,
+            queued: queued.clone(),
+            confirmed: confirmed.clone(),
+            snapshot_path,
+            last_snapshot: None,
+            reset_requests,
+            awaiting_reset: false,
+        };
+
+        Self {
+            commands: command_sender,
+            attributes: attributes_receiver,
+            generation,
+            queued,
+            confirmed,
+            state,
+            handle: spawn(task.run()),
+        }
+    }
+
+    /// Feeds an L1 block into the pipeline
+    pub fn push_l1_block(&self, l1_info: Box<L1Info>) -> Result<()> {
+        self.send(Command::L1Block(l1_info))
+    }
+
+    /// Receives the next derived attributes, skipping any that were derived before
+    /// the last reset. Errors if derivation halted on a critical error. Cancel safe.
+    pub async fn recv(&mut self) -> Option<Result<PayloadAttributes>> {
+        loop {
+            let (generation, attributes) = self.attributes.recv().await?;
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+
+            if generation == self.generation.load(Ordering::SeqCst) {
+                return Some(attributes);
+            }
+        }
+    }
+
+    /// Records a block the engine has executed from derived attributes, so batches
+    /// building on it can be checked
+    pub fn confirm(&self, safe_head: BlockInfo, safe_epoch: Epoch) -> Result<()> {
+        self.state
+            .write()
+            .map_err(|_| eyre::eyre!("lock poisoned"))?
+            .confirm_safe_head(safe_head, safe_epoch);
+
+        self.confirmed.notify_one();
+        Ok(())
+    }
+
+    /// Invalidates all queued attributes and restarts derivation from the given
+    /// safe head. L1 blocks pushed after the reset are derived on top of it.
+    pub fn reset(&self, safe_head: BlockInfo, safe_epoch: Epoch) -> Result<()> {
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        self.send(Command::Reset {
+            generation,
+            safe_head,
+            safe_epoch,
+        })
+    }
+
+    /// Requests a pipeline snapshot. It is written once the pipeline has derived
+    /// all pushed L1 blocks, and only if the engine has executed every derived block.
+    pub fn snapshot(&self) -> Result<()> {
+        self.send(Command::Snapshot)
+    }
+
+    /// Returns the number of attributes waiting for the engine
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::SeqCst)
+    }
+
+    fn send(&self, command: Command) -> Result<()> {
+        self.commands
+            .send(command)
+            .map_err(|_| eyre::eyre!("pipeline task stopped"))
+    }
+}
+
+impl Drop for SpeculativePipeline {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+struct PipelineTask {
+    pipeline: Pipeline,
+    state: Arc<RwLock<State>>,
+    commands: mpsc::UnboundedReceiver<Command>,
+    attributes: mpsc::Sender<(u64, Result<PayloadAttributes>)>,
+    generation: Arc<AtomicU64>,
+    /// The generation the pipeline is currently deriving in
+    current_generation: u64,
+    queued: Arc<AtomicUsize>,
+    confirmed: Arc<Notify>,
+    snapshot_path: Option<PathBuf>,
+    /// The L1 block and safe head number of the last written snapshot
+    last_snapshot: Option<(u64, u64)>,
+    reset_requests: mpsc::UnboundedSender<u64>,
+    /// Set once a reset has been requested, until it arrives
+    awaiting_reset: bool,
+}
+
+impl PipelineTask {
+    async fn run(mut self) {
+        loop {
+            select! {
+                command = self.commands.recv() => match command {
+                    Some(command) => self.handle_command(command).await,
+                    None => return,
+                },
+                _ = self.confirmed.notified() => (),
+            }
+
+            if let Err(err) = self.derive().await {
+                tracing::error!("pipeline task stopped: {}", err);
+                return;
+            }
+        }
+    }
+
+    async fn handle_command(&mut self, command: Command) {
+        match command {
+            Command::L1Block(l1_info) => {
+                if self.awaiting_reset {
+                    return;
+                }
+
+                // skipping the batcher data of a block would corrupt the derived chain
+                while let Err(err) = self.pipeline.push_l1_block(&l1_info).await {
+                    if let Some(expired) = err.downcast_ref::<ExpiredChallenge>() {
+                        tracing::warn!("{}, requesting a reset", expired);
+                        let _ = self.reset_requests.send(expired.l1_block);
+                        self.awaiting_reset = true;
+                        return;
+                    }
+
+                    tracing::warn!(
+                        "failed to read batcher data of block {}: {}",
+                        l1_info.block_info.number,
+                        err
+                    );
+                    sleep(Duration::from_secs(1)).await;
+                }
+
+                self.state.write().unwrap().update_l1_info(*l1_info);
+            }
+            Command::Reset {
+                generation,
+                safe_head,
+                safe_epoch,
+            } => {
+                self.state.write().unwrap().purge(safe_head, safe_epoch);
+                if let Err(err) = self.pipeline.purge().await {
+                    tracing::error!("failed to purge pipeline: {}", err);
+                }
+
+                self.current_generation = generation;
+                self.last_snapshot = None;
+                self.awaiting_reset = false;
+            }
+            Command::Snapshot => {
+                if let Some(path) = self.snapshot_path.clone() {
+                    if let Err(err) = self.save_snapshot(&path) {
+                        tracing::warn!("failed to write pipeline snapshot {:?}: {}", path, err);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Derives attributes until the pipeline runs out of data, halts or is reset.
+    /// Errors if the receiving side has been dropped.
+    async fn derive(&mut self) -> Result<()> {
+        while self.current_generation == self.generation.load(Ordering::SeqCst) {
+            let Some(attributes) = self.pipeline.next().await else {
+                if let Some(err) = self.pipeline.take_critical_error() {
+                    self.queued.fetch_add(1, Ordering::SeqCst);
+                    self.attributes
+                        .send((self.current_generation, Err(err)))
+                        .await?;
+                }
+                break;
+            };
+
+            let epoch = attributes
+                .epoch
+                .ok_or(eyre::eyre!("attributes without epoch"))?;
+            let timestamp = attributes.timestamp.as_u64();
+            let batch_transactions = attributes
+                .transactions
+                .iter()
+                .flatten()
+                .filter(|tx| !tx.is_deposit())
+                .map(RawTransaction::hash)
+                .collect();
+
+            {
+                let mut state = self.state.write().unwrap();
+                state.speculate_safe_head(timestamp, epoch);
+                state.set_l2_transactions(timestamp, batch_transactions);
+            }
+
+            self.queued.fetch_add(1, Ordering::SeqCst);
+            self.attributes
+                .send((self.current_generation, Ok(attributes)))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    fn save_snapshot(&mut self, path: &Path) -> Result<()> {
+        if self.current_generation != self.generation.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let position = {
+            let state = self.state.read().unwrap();
+            if !state.is_safe_head_confirmed() {
+                return Ok(());
+            }
+
+            (state.current_epoch_num, state.safe_head.number)
+        };
+
+        if self.last_snapshot == Some(position) {
+            return Ok(());
+        }
+
+        self.pipeline.snapshot()?.save(path)?;
+        self.last_snapshot = Some(position);
+
+        Ok(())
+    }
+}
@@ -6,16 +6,20 @@ use ethers::types::{Address, Log, H256, U256, U64};
 use ethers::utils::{keccak256, rlp::Encodable, rlp::RlpStream};
 
 use eyre::Result;
+use serde::{Deserialize, Serialize};
 
 use crate::common::{Epoch, RawTransaction};
 use crate::config::{Config, SystemAccounts};
 use crate::derive::async_iterator::AsyncIterator;
+use crate::derive::snapshot::PipelineSnapshot;
 use crate::derive::state::State;
 use crate::derive::PurgeableAsyncIterator;
 use crate::engine::PayloadAttributes;
 use crate::l1::L1Info;
+use crate::telemetry::metrics;
 
 use super::batches::Batch;
+use super::upgrades::{upgrade_deposits, UpgradeDeposit};
 
 pub struct Attributes {
     batch_iter: Box<dyn PurgeableAsyncIterator<Item = Batch> + Send>,
@@ -23,6 +27,10 @@ pub struct Attributes {
     sequence_number: u64,
     epoch_hash: H256,
     config: Arc<Config>,
+    /// A batch whose attributes could not be derived, kept to be retried
+    failed_batch: Option<Batch>,
+    /// Why the attributes of the failed batch could not be derived
+    critical_error: Option<eyre::Report>,
 }
 
 #[async_trait]
@@ -30,10 +38,25 @@ impl AsyncIterator for Attributes {
     type Item = PayloadAttributes;
 
     async fn next(&mut self) -> Option<Self::Item> {
-        self.batch_iter
-            .next()
-            .await
-            .map(|batch| self.derive_attributes(batch))
+        let batch = match self.failed_batch.take() {
+            Some(batch) => batch,
+            None => self.batch_iter.next().await?,
+        };
+
+        // attributes that cannot be derived halt derivation, like a critical error in
+        // op-node: the batch is kept and the error is reported by `take_critical_error`
+        let (sequence_number, epoch_hash) = (self.sequence_number, self.epoch_hash);
+        match self.derive_attributes(batch.clone()) {
+            Ok(attributes) => Some(attributes),
+            Err(err) => {
+                tracing::error!("failed to derive attributes: {}", err);
+                self.sequence_number = sequence_number;
+                self.epoch_hash = epoch_hash;
+                self.failed_batch = Some(batch);
+                self.critical_error = Some(err);
+                None
+            }
+        }
     }
 }
 
@@ -41,9 +64,39 @@ impl AsyncIterator for Attributes {
 impl PurgeableAsyncIterator for Attributes {
     async fn purge(&mut self) {
         self.batch_iter.purge().await;
+        self.failed_batch = None;
+        self.critical_error = None;
         self.sequence_number = 0;
         self.epoch_hash = self.state.read().unwrap().safe_epoch.hash;
     }
+
+    fn snapshot(&self, snapshot: &mut PipelineSnapshot) -> Result<()> {
+        self.batch_iter.snapshot(snapshot)?;
+        snapshot.set_stage(
+            "attributes",
+            &AttributesSnapshot {
+                sequence_number: self.sequence_number,
+                epoch_hash: self.epoch_hash,
+            },
+        )
+    }
+
+    fn restore(&mut self, snapshot: &PipelineSnapshot) -> Result<()> {
+        self.batch_iter.restore(snapshot)?;
+        if let Some(attributes) = snapshot.stage::<AttributesSnapshot>("attributes")? {
+            self.sequence_number = attributes.sequence_number;
+            self.epoch_hash = attributes.epoch_hash;
+        }
+
+        Ok(())
+    }
+}
+
+/// The sequence number tracking as stored in a [PipelineSnapshot]
+#[derive(Serialize, Deserialize)]
+struct AttributesSnapshot {
+    sequence_number: u64,
+    epoch_hash: H256,
 }
 
 impl Attributes {
@@ -61,10 +114,22 @@ impl Attributes {
             sequence_number: seq,
             epoch_hash,
             config,
+            failed_batch: None,
+            critical_error: None,
         }
     }
 
-    fn derive_attributes(&mut self, batch: Batch) -> PayloadAttributes {
+    /// Takes the error that halted derivation, if the attributes of a batch could
+    /// not be derived. The batch is retried by the next call to `next`.
+    pub fn take_critical_error(&mut self) -> Option<eyre::Report> {
+        self.critical_error.take()
+    }
+
+    fn derive_attributes(&mut self, batch: Batch) -> Result<PayloadAttributes> {
+        let _timer = metrics::DERIVE_STAGE_SECONDS
+            .with_label_values(&["attributes"])
+            .start_timer();
+
         tracing::debug!("attributes derived from block {}", batch.epoch_num);
         tracing::debug!("batch epoch hash {:?}", batch.epoch_hash);
 
@@ -83,55 +148,75 @@ impl Attributes {
         let l1_inclusion_block = Some(batch.l1_inclusion_block);
         let seq_number = Some(self.sequence_number);
         let prev_randao = l1_info.block_info.mix_hash;
-        let transactions = Some(self.derive_transactions(batch, l1_info));
+        let transactions = Some(self.derive_transactions(batch, l1_info)?);
         let suggested_fee_recipient = if self.config.chain.meta.enable_deposited_txs {
             SystemAccounts::default().fee_vault
         } else {
             self.config.chain.system_config.batch_sender
         };
+        let withdrawals = self
+            .config
+            .chain
+            .is_canyon_active(timestamp.as_u64())
+            .then(Vec::new);
+        let parent_beacon_block_root = if self.config.chain.is_ecotone_active(timestamp.as_u64()) {
+            Some(l1_info.block_info.parent_beacon_root.unwrap_or_default())
+        } else {
+            None
+        };
 
-        PayloadAttributes {
+        Ok(PayloadAttributes {
             timestamp,
             prev_randao,
             suggested_fee_recipient,
             transactions,
             no_tx_pool: true,
             gas_limit: U64([l1_info.system_config.gas_limit.as_u64()]),
+            withdrawals,
+            parent_beacon_block_root,
             epoch,
             l1_inclusion_block,
             seq_number,
-        }
+        })
     }
 
-    fn derive_transactions(&self, batch: Batch, l1_info: &L1Info) -> Vec<RawTransaction> {
+    fn derive_transactions(&self, batch: Batch, l1_info: &L1Info) -> Result<Vec<RawTransaction>> {
         let mut transactions = Vec::new();
 
         if self.config.chain.meta.enable_deposited_txs {
-            let attributes_tx = self.derive_attributes_deposited(l1_info, batch.timestamp);
+            let attributes_tx = self.derive_attributes_deposited(l1_info, batch.timestamp)?;
             transactions.push(attributes_tx);
 
             if self.sequence_number == 0 {
                 let mut user_deposited_txs = self.derive_user_deposited();
                 transactions.append(&mut user_deposited_txs);
             }
+
+            let mut upgrade_txs = upgrade_deposits(&self.config.chain, batch.timestamp)?
+                .into_iter()
+                .map(|deposit| {
+                    RawTransaction(DepositedTransaction::from(deposit).rlp_bytes().to_vec())
+                })
+                .collect();
+            transactions.append(&mut upgrade_txs);
         }
 
         let mut rest = batch.transactions;
         transactions.append(&mut rest);
 
-        transactions
+        Ok(transactions)
     }
 
     fn derive_attributes_deposited(
         &self,
         l1_info: &L1Info,
         batch_timestamp: u64,
-    ) -> RawTransaction {
+    ) -> Result<RawTransaction> {
         let seq = self.sequence_number;
         let attributes_deposited =
-            AttributesDeposited::from_block_info(l1_info, seq, batch_timestamp, &self.config);
+            AttributesDeposited::from_block_info(l1_info, seq, batch_timestamp, &self.config)?;
         let attributes_tx = DepositedTransaction::from(attributes_deposited);
-        RawTransaction(attributes_tx.rlp_bytes().to_vec())
+        Ok(RawTransaction(attributes_tx.rlp_bytes().to_vec()))
     }
 
     fn derive_user_deposited(&self) -> Vec<RawTransaction> {
@@ -230,6 +315,21 @@ impl From<UserDeposited> for DepositedTransaction {
     }
 }
 
+impl From<UpgradeDeposit> for DepositedTransaction {
+    fn from(upgrade_deposit: UpgradeDeposit) -> Self {
+        Self {
+            source_hash: upgrade_deposit.source_hash(),
+            from: upgrade_deposit.from,
+            to: upgrade_deposit.to,
+            mint: U256::zero(),
+            value: U256::zero(),
+            gas: upgrade_deposit.gas,
+            is_system_tx: false,
+            data: upgrade_deposit.data,
+        }
+    }
+}
+
 impl Encodable for DepositedTransaction {
     fn rlp_append(&self, s: &mut RlpStream) {
         s.append_raw(&[0x7E], 1);
@@ -261,18 +361,39 @@ struct AttributesDeposited {
     batcher_hash: H256,
     fee_overhead: U256,
     fee_scalar: U256,
+    blob_base_fee: U256,
+    base_fee_scalar: u32,
+    blob_base_fee_scalar: u32,
     gas: u64,
     is_system_tx: bool,
+    is_ecotone: bool,
 }
 
 impl AttributesDeposited {
-    fn from_block_info(l1_info: &L1Info, seq: u64, batch_timestamp: u64, config: &Config) -> Self {
-        let is_regolith = batch_timestamp >= config.chain.regolith_time;
+    /// Builds the L1 attributes of a block. Errors if the block is in Ecotone and the
+    /// fee scalar of the system config cannot be decoded.
+    fn from_block_info(
+        l1_info: &L1Info,
+        seq: u64,
+        batch_timestamp: u64,
+        config: &Config,
+    ) -> Result<Self> {
+        let is_regolith = config.chain.is_regolith_active(batch_timestamp);
         let is_system_tx = !is_regolith;
 
+        // the L1Block contract is upgraded during the first ecotone block, so that block
+        // still uses the bedrock format
+        let is_ecotone = config.chain.is_ecotone_active(batch_timestamp)
+            && !config.chain.is_ecotone_activation_block(batch_timestamp);
+
         let gas = if is_regolith { 1_000_000 } else { 150_000_000 };
+        let (base_fee_scalar, blob_base_fee_scalar) = if is_ecotone {
+            l1_info.system_config.ecotone_scalars()?
+        } else {
+            (0, 0)
+        };
 
-        Self {
+        Ok(Self {
             number: l1_info.block_info.number,
             timestamp: l1_info.block_info.timestamp,
             base_fee: l1_info.block_info.base_fee,
@@ -281,12 +402,25 @@ impl AttributesDeposited {
             batcher_hash: l1_info.system_config.batcher_hash(),
             fee_overhead: l1_info.system_config.l1_fee_overhead,
             fee_scalar: l1_info.system_config.l1_fee_scalar,
+            blob_base_fee: l1_info.block_info.blob_base_fee,
+            base_fee_scalar,
+            blob_base_fee_scalar,
             gas,
             is_system_tx,
-        }
+            is_ecotone,
+        })
     }
 
     fn encode(&self) -> Vec<u8> {
+        if self.is_ecotone {
+            self.encode_ecotone()
+        } else {
+            self.encode_bedrock()
+        }
+    }
+
+    /// Encodes the ABI `setL1BlockValues` call
+    fn encode_bedrock(&self) -> Vec<u8> {
         let tokens = vec![
             Token::Uint(self.number.into()),
             Token::Uint(self.timestamp.into()),
@@ -303,9 +437,30 @@ impl AttributesDeposited {
 
         [selector, data].concat()
     }
+
+    /// Encodes the tightly packed `setL1BlockValuesEcotone` call
+    fn encode_ecotone(&self) -> Vec<u8> {
+        let mut data = hex::decode("440a5e20").unwrap();
+        data.extend_from_slice(&self.base_fee_scalar.to_be_bytes());
+        data.extend_from_slice(&self.blob_base_fee_scalar.to_be_bytes());
+        data.extend_from_slice(&self.sequence_number.to_be_bytes());
+        data.extend_from_slice(&self.timestamp.to_be_bytes());
+        data.extend_from_slice(&self.number.to_be_bytes());
+
+        let mut fee = [0u8; 32];
+        self.base_fee.to_big_endian(&mut fee);
+        data.extend_from_slice(&fee);
+        self.blob_base_fee.to_big_endian(&mut fee);
+        data.extend_from_slice(&fee);
+
+        data.extend_from_slice(self.hash.as_bytes());
+        data.extend_from_slice(self.batcher_hash.as_bytes());
+
+        data
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserDeposited {
     pub from: Address,
     pub to: Address,
@@ -358,3 +513,223 @@ impl TryFrom<Log> for UserDeposited {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use ethers::providers::{Http, Middleware, Provider};
+    use ethers::types::{Bytes, H256, U256, U64};
+    use ethers::utils::{keccak256, rlp::Encodable};
+
+    use std::collections::VecDeque;
+    use std::sync::{Arc, RwLock};
+
+    use async_trait::async_trait;
+
+    use crate::common::{AttributesDepositedCall, BlockInfo, Epoch};
+    use crate::config::{ChainConfig, Config};
+    use crate::derive::async_iterator::AsyncIterator;
+    use crate::derive::stages::batches::Batch;
+    use crate::derive::stages::upgrades::{ecotone_upgrade_deposits, fjord_upgrade_deposits};
+    use crate::derive::state::State;
+    use crate::derive::PurgeableAsyncIterator;
+    use crate::l1::{L1BlockInfo, L1Info};
+
+    use super::{Attributes, AttributesDeposited, DepositedTransaction};
+
+    /// L1 block 19655712, the origin of the ecotone attributes on OP mainnet below
+    fn l1_info(chain: &ChainConfig) -> L1Info {
+        L1Info {
+            block_info: L1BlockInfo {
+                number: 19655712,
+                hash: H256::from_str(
+                    "0x1c4c84c50740386c7dc081efddd644405f04cde73e30a2e381737acce9f5add3",
+                )
+                .unwrap(),
+                timestamp: 1713121139,
+                base_fee: U256::from(10445852825u64),
+                mix_hash: H256::zero(),
+                state_root: H256::zero(),
+                parent_beacon_root: Some(H256::repeat_byte(0xbb)),
+                excess_blob_gas: Some(0),
+                blob_base_fee: U256::one(),
+            },
+            system_config: chain.system_config,
+            user_deposits: Vec::new(),
+            batcher_transactions: Vec::new(),
+            finalized: false,
+        }
+    }
+
+    fn config(chain: ChainConfig) -> Config {
+        Config {
+            chain,
+            ..Config::default_for_tests()
+        }
+    }
+
+    #[test]
+    fn test_attributes_deposited_ecotone() {
+        let mut chain = ChainConfig::optimism();
+        chain.system_config.l1_fee_scalar = U256::from_big_endian(
+            &hex::decode("010000000000000000000000000000000000000000000000000c5fc500000558")
+                .unwrap(),
+        );
+        let l1_info = l1_info(&chain);
+        let config = config(chain.clone());
+
+        // the l1 attributes of an OP mainnet block with sequence number 5 in epoch 19655712
+        let timestamp = chain.ecotone_time + chain.blocktime;
+        let deposited =
+            AttributesDeposited::from_block_info(&l1_info, 5, timestamp, &config).unwrap();
+        assert_eq!(
+            hex::encode(deposited.encode()),
+            "440a5e2000000558000c5fc5000000000000000500000000661c277300000000012bec20000000000000000000000000000000000000000000000000000000026e9f109900000000000000000000000000000000000000000000000000000000000000011c4c84c50740386c7dc081efddd644405f04cde73e30a2e381737acce9f5add30000000000000000000000006887246668a3b87f54deb3b94ba47a6f63f32985"
+        );
+    }
+
+    #[test]
+    fn test_attributes_deposited_invalid_scalar() {
+        let mut chain = ChainConfig::optimism();
+        chain.system_config.l1_fee_scalar = U256::from(2) << 248;
+        let l1_info = l1_info(&chain);
+        let config = config(chain.clone());
+
+        let timestamp = chain.ecotone_time + chain.blocktime;
+        assert!(AttributesDeposited::from_block_info(&l1_info, 5, timestamp, &config).is_err());
+    }
+
+    #[test]
+    fn test_attributes_deposited_ecotone_activation_block() {
+        let chain = ChainConfig::optimism();
+        let l1_info = l1_info(&chain);
+        let config = config(chain.clone());
+
+        let deposited =
+            AttributesDeposited::from_block_info(&l1_info, 0, chain.ecotone_time, &config).unwrap();
+        let data = deposited.encode();
+        assert_eq!(data[..4], hex::decode("015d8eb9").unwrap());
+
+        let call = AttributesDepositedCall::try_from(Bytes::from(data)).unwrap();
+        assert_eq!(call.number, 19655712);
+        assert_eq!(call.fee_scalar, chain.system_config.l1_fee_scalar);
+        assert!(call.blob_base_fee.is_none());
+    }
+
+    /// Yields the given batches in order
+    struct MockBatches(VecDeque<Batch>);
+
+    #[async_trait]
+    impl AsyncIterator for MockBatches {
+        type Item = Batch;
+
+        async fn next(&mut self) -> Option<Batch> {
+            self.0.pop_front()
+        }
+    }
+
+    #[async_trait]
+    impl PurgeableAsyncIterator for MockBatches {
+        async fn purge(&mut self) {
+            self.0.clear();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_underivable_batch_halts() {
+        let mut chain = ChainConfig::optimism();
+        chain.system_config.l1_fee_scalar = U256::from(2) << 248;
+        let l1_info = l1_info(&chain);
+        let config = Arc::new(config(chain.clone()));
+
+        let epoch = Epoch {
+            number: l1_info.block_info.number,
+            hash: l1_info.block_info.hash,
+            timestamp: l1_info.block_info.timestamp,
+        };
+        let batch = |timestamp| Batch {
+            parent_hash: H256::zero(),
+            epoch_num: epoch.number,
+            epoch_hash: epoch.hash,
+            timestamp,
+            transactions: Vec::new(),
+            l1_inclusion_block: epoch.number,
+        };
+
+        let mut state = State::new(BlockInfo::default(), epoch, config.clone());
+        state.update_l1_info(l1_info);
+        let state = Arc::new(RwLock::new(state));
+
+        let timestamp = chain.ecotone_time + chain.blocktime;
+        let batches = MockBatches(VecDeque::from([batch(timestamp), batch(timestamp + 2)]));
+        let mut attributes = Attributes::new(Box::new(batches), state, config, 0);
+
+        // the batch is kept and retried instead of being skipped
+        for _ in 0..2 {
+            assert!(attributes.next().await.is_none());
+            assert!(attributes.take_critical_error().is_some());
+            assert_eq!(
+                attributes.failed_batch.as_ref().unwrap().timestamp,
+                timestamp
+            );
+        }
+
+        attributes.purge().await;
+        assert!(attributes.failed_batch.is_none());
+        assert!(attributes.take_critical_error().is_none());
+    }
+
+    #[test]
+    fn test_upgrade_deposit_encoding() {
+        let set_ecotone = ecotone_upgrade_deposits().pop().unwrap();
+        let tx = DepositedTransaction::from(set_ecotone);
+
+        assert_eq!(
+            hex::encode(tx.rlp_bytes()),
+            "7ef857a00c1cb38e99dbc9cbfab3bb80863380b0905290b37eb3d6ab18dc01c1f3e75f9394deaddeaddeaddeaddeaddeaddeaddeaddead000194420000000000000000000000000000000000000f808083013880808422b908b3"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_upgrade_deposits_match_activation_blocks() -> eyre::Result<()> {
+        if std::env::var("L2_TEST_RPC_URL").is_ok() {
+            let l2_rpc = std::env::var("L2_TEST_RPC_URL")?;
+            let provider = Provider::<Http>::try_from(l2_rpc)?;
+            let chain = ChainConfig::optimism();
+
+            let forks = [
+                (chain.ecotone_time, ecotone_upgrade_deposits()),
+                (chain.fjord_time, fjord_upgrade_deposits()),
+            ];
+
+            for (fork_time, upgrade_deposits) in forks {
+                let blocks = (fork_time - chain.l2_genesis.timestamp).div_ceil(chain.blocktime);
+                let number = chain.l2_genesis.number + blocks;
+                let block = provider
+                    .get_block_with_txs(number)
+                    .await?
+                    .ok_or(eyre::eyre!("block {} not found", number))?;
+                let timestamp = block.timestamp.as_u64();
+                assert!(timestamp >= fork_time && timestamp - chain.blocktime < fork_time);
+
+                // the upgrade deposits are the last deposits of the activation block
+                let deposits = block
+                    .transactions
+                    .iter()
+                    .filter(|tx| tx.transaction_type == Some(U64::from(0x7E)))
+                    .collect::<Vec<_>>();
+                let deposits = &deposits[deposits.len() - upgrade_deposits.len()..];
+
+                for (deposit, upgrade_deposit) in deposits.iter().zip(upgrade_deposits) {
+                    assert_eq!(deposit.source_hash, upgrade_deposit.source_hash());
+
+                    let tx = DepositedTransaction::from(upgrade_deposit);
+                    assert_eq!(deposit.hash, H256::from(keccak256(tx.rlp_bytes())));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
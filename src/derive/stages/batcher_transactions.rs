@@ -2,14 +2,28 @@ use std::sync::mpsc;
 
 use async_trait::async_trait;
 use eyre::Result;
+use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 
 use crate::derive::async_iterator::AsyncIterator;
+use crate::derive::trace::{self, DerivationEvent, DropReason, EventTarget};
 use crate::derive::PurgeableAsyncIterator;
+use crate::telemetry::metrics;
+
+/// The only supported batcher transaction version
+const DERIVATION_VERSION_0: u8 = 0;
+
+/// The maximum length of the data of a single frame
+const MAX_FRAME_LEN: u32 = 1_000_000;
+
+/// Size of the frame header, `channel_id ++ frame_number ++ frame_data_length`
+const FRAME_HEADER_LEN: usize = 22;
 
 pub struct BatcherTransactionMessage {
     pub txs: Vec<Vec<u8>>,
     pub l1_origin: u64,
+    /// The timestamp of the L1 origin, which selects the active hardforks
+    pub l1_timestamp: u64,
 }
 
 pub struct BatcherTransactions {
@@ -45,17 +59,39 @@ impl BatcherTransactions {
     }
 
     pub fn process_incoming(&mut self) {
-        while let Ok(BatcherTransactionMessage { txs, l1_origin }) = self.transaction_rx.try_recv()
+        while let Ok(BatcherTransactionMessage {
+            txs,
+            l1_origin,
+            l1_timestamp,
+        }) = self.transaction_rx.try_recv()
         {
-            for data in txs {
-                let res = BatcherTransaction::new(&data, l1_origin).map(|tx| {
-                    self.txs.push_back(tx);
-                });
+            let _timer = metrics::DERIVE_STAGE_SECONDS
+                .with_label_values(&["batcher_transactions"])
+                .start_timer();
 
-                if res.is_err() {
-                    tracing::warn!("dropping invalid batcher transaction");
+            let queued = self.txs.len();
+            for data in txs {
+                match BatcherTransaction::new(&data, l1_origin, l1_timestamp) {
+                    Ok(tx) => self.txs.push_back(tx),
+                    Err(err) => trace::record(DerivationEvent::new(
+                        EventTarget::BatcherTransaction,
+                        DropReason::Decode,
+                        l1_origin,
+                        format!("invalid batcher transaction: {}", err),
+                    )),
                 }
             }
+
+            // the channel bank evaluates timeouts at every L1 block, so blocks without
+            // batcher data still yield a transaction without frames
+            if self.txs.len() == queued {
+                self.txs.push_back(BatcherTransaction {
+                    version: DERIVATION_VERSION_0,
+                    frames: Vec::new(),
+                    l1_origin,
+                    l1_timestamp,
+                });
+            }
         }
     }
 }
@@ -64,12 +100,27 @@ impl BatcherTransactions {
 pub struct BatcherTransaction {
     pub version: u8,
     pub frames: Vec<Frame>,
+    /// The L1 block the transaction was included in
+    pub l1_origin: u64,
+    /// The timestamp of the L1 block the transaction was included in
+    pub l1_timestamp: u64,
 }
 
 impl BatcherTransaction {
-    pub fn new(data: &[u8], l1_origin: u64) -> Result<Self> {
-        let version = data[0];
-        let frame_data = data.get(1..).ok_or(eyre::eyre!("No frame data"))?;
+    /// Parses all frames of a batcher transaction. If any frame is invalid the whole
+    /// transaction is rejected.
+    pub fn new(data: &[u8], l1_origin: u64, l1_timestamp: u64) -> Result<Self> {
+        let version = *data
+            .first()
+            .ok_or(eyre::eyre!("empty batcher transaction"))?;
+        if version != DERIVATION_VERSION_0 {
+            eyre::bail!("unsupported batcher transaction version: {}", version);
+        }
+
+        let frame_data = &data[1..];
+        if frame_data.is_empty() {
+            eyre::bail!("no frame data");
+        }
 
         let mut offset = 0;
         let mut frames = Vec::new();
@@ -79,11 +130,16 @@ impl BatcherTransaction {
             offset = next_offset;
         }
 
-        Ok(Self { version, frames })
+        Ok(Self {
+            version,
+            frames,
+            l1_origin,
+            l1_timestamp,
+        })
     }
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Frame {
     pub channel_id: u128,
     pub frame_number: u16,
@@ -94,10 +150,11 @@ pub struct Frame {
 }
 
 impl Frame {
+    /// Parses the frame starting at `offset`, returning it with the offset of the next frame
     fn from_data(data: &[u8], offset: usize, l1_inclusion_block: u64) -> Result<(Self, usize)> {
         let data = &data[offset..];
 
-        if data.len() < 23 {
+        if data.len() < FRAME_HEADER_LEN + 1 {
             eyre::bail!("invalid frame size");
         }
 
@@ -105,12 +162,16 @@ impl Frame {
         let frame_number = u16::from_be_bytes(data[16..18].try_into()?);
         let frame_data_len = u32::from_be_bytes(data[18..22].try_into()?);
 
-        let frame_data_end = 22 + frame_data_len as usize;
-        if data.len() < frame_data_end {
+        if frame_data_len > MAX_FRAME_LEN {
+            eyre::bail!("frame data too large: {}", frame_data_len);
+        }
+
+        let frame_data_end = FRAME_HEADER_LEN + frame_data_len as usize;
+        if data.len() < frame_data_end + 1 {
             eyre::bail!("invalid frame size");
         }
 
-        let frame_data = data[22..frame_data_end].to_vec();
+        let frame_data = data[FRAME_HEADER_LEN..frame_data_end].to_vec();
 
         let is_last = if data[frame_data_end] > 1 {
             eyre::bail!("invalid is_last flag");
@@ -134,7 +195,7 @@ impl Frame {
             is_last
         );
 
-        Ok((frame, offset + data.len()))
+        Ok((frame, offset + frame_data_end + 1))
     }
 }
 
@@ -148,7 +209,7 @@ mod tests {
     fn test_decode_tx() {
         let data = hex::decode(TX_DATA).unwrap();
 
-        let tx = BatcherTransaction::new(&data, 123456).unwrap();
+        let tx = BatcherTransaction::new(&data, 123456, 0).unwrap();
         let frame = &tx.frames[0];
 
         assert_eq!(tx.version, 0);
@@ -169,6 +230,7 @@ mod tests {
         let res = tx.send(BatcherTransactionMessage {
             txs,
             l1_origin: 123456,
+            l1_timestamp: 0,
         });
         assert!(res.is_ok());
 
@@ -182,4 +244,73 @@ mod tests {
         assert_eq!(tx.frames.len(), 1);
         assert_eq!(frame.channel_id, 239159748140584302248388764660258118408);
     }
+
+    #[test]
+    fn test_push_empty_block() {
+        let (tx, rx) = mpsc::channel();
+        let mut stage = BatcherTransactions::new(rx);
+
+        tx.send(BatcherTransactionMessage {
+            txs: vec![vec![1, 2, 3]],
+            l1_origin: 10,
+            l1_timestamp: 120,
+        })
+        .unwrap();
+        stage.process_incoming();
+
+        // the L1 origin still advances without valid batcher transactions
+        assert_eq!(stage.txs.len(), 1);
+        assert_eq!(stage.txs[0].l1_origin, 10);
+        assert_eq!(stage.txs[0].l1_timestamp, 120);
+        assert!(stage.txs[0].frames.is_empty());
+    }
+
+    fn encode_frame(channel_id: u128, frame_number: u16, data: &[u8], is_last: bool) -> Vec<u8> {
+        [
+            channel_id.to_be_bytes().as_slice(),
+            &frame_number.to_be_bytes(),
+            &(data.len() as u32).to_be_bytes(),
+            data,
+            &[is_last as u8],
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn test_decode_multi_frame_tx() {
+        let data = [
+            vec![0],
+            encode_frame(1, 0, b"first", false),
+            encode_frame(1, 1, b"second", true),
+            encode_frame(2, 0, b"", true),
+        ]
+        .concat();
+
+        let tx = BatcherTransaction::new(&data, 10, 0).unwrap();
+
+        assert_eq!(tx.frames.len(), 3);
+        assert_eq!(tx.frames[0].frame_data, b"first");
+        assert!(!tx.frames[0].is_last);
+        assert_eq!(tx.frames[1].frame_number, 1);
+        assert_eq!(tx.frames[1].frame_data, b"second");
+        assert!(tx.frames[1].is_last);
+        assert_eq!(tx.frames[2].channel_id, 2);
+        assert!(tx.frames.iter().all(|f| f.l1_inclusion_block == 10));
+    }
+
+    #[test]
+    fn test_decode_invalid_tx() {
+        let frame = encode_frame(1, 0, b"data", true);
+
+        // unknown version
+        assert!(BatcherTransaction::new(&[vec![1], frame.clone()].concat(), 0, 0).is_err());
+        // truncated trailing frame rejects the whole transaction
+        let truncated = [vec![0], frame.clone(), frame[..10].to_vec()].concat();
+        assert!(BatcherTransaction::new(&truncated, 0, 0).is_err());
+        // invalid is_last flag
+        let mut invalid_flag = [vec![0], frame].concat();
+        *invalid_flag.last_mut().unwrap() = 2;
+        assert!(BatcherTransaction::new(&invalid_flag, 0, 0).is_err());
+        assert!(BatcherTransaction::new(&[], 0, 0).is_err());
+    }
 }
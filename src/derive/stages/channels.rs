@@ -1,22 +1,34 @@
+use std::collections::{BTreeMap, VecDeque};
 use std::sync::Arc;
 
 use async_trait::async_trait;
+use eyre::Result;
+use serde::{Deserialize, Serialize};
 
 use super::batcher_transactions::{BatcherTransaction, Frame};
 use crate::{
-    config::Config, derive::async_iterator::AsyncIterator, derive::PurgeableAsyncIterator,
+    config::Config,
+    derive::async_iterator::AsyncIterator,
+    derive::snapshot::PipelineSnapshot,
+    derive::trace::{self, DerivationEvent, DropReason, EventTarget},
+    derive::PurgeableAsyncIterator,
+    telemetry::metrics,
 };
 
+/// Fixed overhead added to the frame data length when accounting for the channel bank size
+const FRAME_OVERHEAD: u64 = 200;
+
 pub struct Channels<I> {
     batcher_tx_iter: I,
-    /// List of incomplete channels
-    pending_channels: Vec<PendingChannel>,
-    /// A bank of frames and their version numbers pulled from a [BatcherTransaction]
-    frame_bank: Vec<Frame>,
+    /// Incomplete channels, in the order their first frame was seen
+    pending_channels: VecDeque<PendingChannel>,
     /// The maximum total byte size of pending channels to hold in the bank
     max_channel_size: u64,
-    /// The max timeout for a channel (as measured by the frame L1 block number)
-    channel_timeout: u64,
+    /// The most recent L1 block frames have been read from
+    current_l1_block: u64,
+    /// The timestamp of the current L1 block, which selects the active hardforks
+    current_l1_timestamp: u64,
+    config: Arc<Config>,
 }
 
 #[async_trait]
@@ -39,18 +51,51 @@ where
     async fn purge(&mut self) {
         self.batcher_tx_iter.purge().await;
         self.pending_channels.clear();
-        self.frame_bank.clear();
+        self.current_l1_block = 0;
+        self.current_l1_timestamp = 0;
+    }
+
+    fn snapshot(&self, snapshot: &mut PipelineSnapshot) -> Result<()> {
+        self.batcher_tx_iter.snapshot(snapshot)?;
+        snapshot.set_stage(
+            "channels",
+            &ChannelsSnapshot {
+                pending_channels: self.pending_channels.clone(),
+                current_l1_block: self.current_l1_block,
+                current_l1_timestamp: self.current_l1_timestamp,
+            },
+        )
     }
+
+    fn restore(&mut self, snapshot: &PipelineSnapshot) -> Result<()> {
+        self.batcher_tx_iter.restore(snapshot)?;
+        if let Some(channels) = snapshot.stage::<ChannelsSnapshot>("channels")? {
+            self.pending_channels = channels.pending_channels;
+            self.current_l1_block = channels.current_l1_block;
+            self.current_l1_timestamp = channels.current_l1_timestamp;
+        }
+
+        Ok(())
+    }
+}
+
+/// The channel bank as stored in a [PipelineSnapshot]
+#[derive(Serialize, Deserialize)]
+struct ChannelsSnapshot {
+    pending_channels: VecDeque<PendingChannel>,
+    current_l1_block: u64,
+    current_l1_timestamp: u64,
 }
 
 impl<I> Channels<I> {
     pub fn new(batcher_tx_iter: I, config: Arc<Config>) -> Self {
         Self {
             batcher_tx_iter,
-            pending_channels: Vec::new(),
-            frame_bank: Vec::new(),
+            pending_channels: VecDeque::new(),
             max_channel_size: config.chain.max_channel_size,
-            channel_timeout: config.chain.channel_timeout,
+            current_l1_block: 0,
+            current_l1_timestamp: 0,
+            config,
         }
     }
 }
@@ -59,185 +104,276 @@ impl<I> Channels<I>
 where
     I: AsyncIterator<Item = BatcherTransaction> + Send,
 {
-    /// Pushes a frame into the correct pending channel
+    /// Pushes a frame into the correct pending channel, opening a new channel if there is
+    /// none for the frame's id. Frames for timed out channels and frames the channel
+    /// rejects are dropped.
     fn push_frame(&mut self, frame: Frame) {
-        // Find a pending channel matching on the channel id
-        let pending_index = self
+        let current_l1_block = self.current_l1_block;
+        let channel_timeout = self.channel_timeout();
+
+        let pending = self
             .pending_channels
             .iter_mut()
-            .position(|c| c.channel_id == frame.channel_id);
+            .find(|c| c.channel_id == frame.channel_id);
+
+        let pending = match pending {
+            Some(pending) => pending,
+            None => {
+                let pending = PendingChannel::new(frame.channel_id, current_l1_block);
+                self.pending_channels.push_back(pending);
+                self.pending_channels.back_mut().unwrap()
+            }
+        };
 
-        // Insert frame if pending channel exists
-        // Otherwise, construct a new pending channel with the frame's id
-        if let Some(pending_index) = pending_index {
-            self.pending_channels[pending_index].push_frame(frame);
+        if pending.is_timed_out(current_l1_block, channel_timeout) {
+            trace::record(
+                DerivationEvent::new(
+                    EventTarget::Frame,
+                    DropReason::ChannelTimeout,
+                    frame.l1_inclusion_block,
+                    format!("frame {} for timed out channel", frame.frame_number),
+                )
+                .with_channel_id(frame.channel_id),
+            );
+            return;
+        }
 
-            if self.pending_channels[pending_index].is_timed_out(self.channel_timeout) {
-                self.pending_channels.remove(pending_index);
-            }
-        } else {
-            let pending = PendingChannel::new(frame);
-            self.pending_channels.push(pending);
+        let channel_id = frame.channel_id;
+        let l1_inclusion_block = frame.l1_inclusion_block;
+        if let Err(err) = pending.push_frame(frame) {
+            trace::record(
+                DerivationEvent::new(
+                    EventTarget::Frame,
+                    DropReason::InvalidFrame,
+                    l1_inclusion_block,
+                    err.to_string(),
+                )
+                .with_channel_id(channel_id),
+            );
         }
+
+        self.prune();
     }
 
-    /// Pull the next batcher transaction from the [BatcherTransactions] stage
-    async fn fill_bank(&mut self) {
-        if let Some(tx) = self.batcher_tx_iter.next().await {
-            self.frame_bank.append(&mut tx.frames.to_vec());
+    /// Moves the channel bank to the L1 block the next frames were included in.
+    /// Timeouts are evaluated against it, also for L1 blocks without frames.
+    fn advance_origin(&mut self, l1_block: u64, l1_timestamp: u64) {
+        if l1_block >= self.current_l1_block {
+            self.current_l1_block = l1_block;
+            self.current_l1_timestamp = l1_timestamp;
         }
     }
 
-    /// Fetch the completed channel if it is ready
-    fn fetch_ready_channel(&mut self, id: u128) -> Option<Channel> {
-        let channel_index = self
-            .pending_channels
-            .iter()
-            .position(|c| c.channel_id == id && c.is_complete());
-
-        channel_index.map(|index| {
-            let pc = self.pending_channels.remove(index);
-            Channel::from(pc)
-        })
+    /// The channel timeout at the current L1 block, which depends on the active hardforks
+    fn channel_timeout(&self) -> u64 {
+        self.config
+            .chain
+            .channel_timeout_at(self.current_l1_timestamp)
     }
 
-    /// Processes frames until there are either none left or a channel is ready
-    async fn process_frames(&mut self) -> Option<Channel> {
-        self.fill_bank().await;
-
-        while !self.frame_bank.is_empty() {
-            // Append the frame to the channel
-            let frame = self.frame_bank.remove(0);
-            let frame_channel_id = frame.channel_id;
-            self.push_frame(frame);
-            self.prune();
+    /// Reads the next ready channel from the bank. Timed out channels at the front of the
+    /// queue are dropped first. Before Canyon only the oldest channel may be read, from
+    /// Canyon on the oldest ready channel is read.
+    fn read_channel(&mut self) -> Option<Channel> {
+        let current_l1_block = self.current_l1_block;
+        let channel_timeout = self.channel_timeout();
 
-            if let Some(channel) = self.fetch_ready_channel(frame_channel_id) {
-                return Some(channel);
+        while let Some(pending) = self.pending_channels.front() {
+            if !pending.is_timed_out(current_l1_block, channel_timeout) {
+                break;
             }
+
+            trace::record(
+                DerivationEvent::new(
+                    EventTarget::Channel,
+                    DropReason::ChannelTimeout,
+                    pending.highest_l1_block,
+                    format!("channel opened at {} timed out", pending.opening_l1_block),
+                )
+                .with_channel_id(pending.channel_id),
+            );
+            self.pending_channels.pop_front();
         }
 
-        None
+        let is_canyon = self
+            .config
+            .chain
+            .is_canyon_active(self.current_l1_timestamp);
+
+        let index = if is_canyon {
+            self.pending_channels
+                .iter()
+                .position(|c| c.is_ready() && !c.is_timed_out(current_l1_block, channel_timeout))
+        } else {
+            self.pending_channels
+                .front()
+                .filter(|c| c.is_ready())
+                .map(|_| 0)
+        };
+
+        // the batches of a channel count as included at the L1 block it is read at
+        index
+            .and_then(|index| self.pending_channels.remove(index))
+            .map(|pending| Channel {
+                id: pending.channel_id,
+                frames: pending.frames.into_values().map(|f| f.frame_data).collect(),
+                l1_inclusion_block: current_l1_block,
+                l1_inclusion_timestamp: self.current_l1_timestamp,
+            })
     }
 
-    /// Removes a pending channel from the bank
-    fn remove(&mut self) -> Option<PendingChannel> {
-        match self.pending_channels.is_empty() {
-            true => None,
-            false => Some(self.pending_channels.remove(0)),
+    /// Reads channels until one is ready, ingesting the frames of the next batcher
+    /// transaction whenever none is. Every transaction first advances the L1 origin. Returns `None` once there are no transactions left.
+    async fn process_frames(&mut self) -> Option<Channel> {
+        loop {
+            if let Some(channel) = self.read_channel() {
+                return Some(channel);
+            }
+
+            let tx = self.batcher_tx_iter.next().await?;
+
+            let _timer = metrics::DERIVE_STAGE_SECONDS
+                .with_label_values(&["channels"])
+                .start_timer();
+            self.advance_origin(tx.l1_origin, tx.l1_timestamp);
+            for frame in tx.frames {
+                self.push_frame(frame);
+            }
         }
     }
 
     /// Gets the total size of all pending channels
     fn total_size(&self) -> u64 {
-        self.pending_channels
-            .iter()
-            .map(|p| p.frames.iter().fold(0, |a, f| a + f.frame_data_len))
-            .sum::<u32>() as u64
+        self.pending_channels.iter().map(|c| c.size).sum()
     }
 
-    /// Prunes channels to the max size
+    /// Prunes the oldest channels until the bank is within the max size
     fn prune(&mut self) {
         while self.total_size() > self.max_channel_size {
-            self.remove().expect("should have removed a channel");
+            let pending = self
+                .pending_channels
+                .pop_front()
+                .expect("should have removed a channel");
+
+            trace::record(
+                DerivationEvent::new(
+                    EventTarget::Channel,
+                    DropReason::ChannelBankFull,
+                    pending.highest_l1_block,
+                    "pruned channel from the full channel bank",
+                )
+                .with_channel_id(pending.channel_id),
+            );
         }
     }
 }
 
 /// An intermediate pending channel
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct PendingChannel {
     channel_id: u128,
-    frames: Vec<Frame>,
-    size: Option<u16>,
+    /// Frames by frame number
+    frames: BTreeMap<u16, Frame>,
+    /// The frame number of the last frame, once it has been seen
+    end_frame_number: Option<u16>,
+    /// The L1 block the channel was opened at
+    opening_l1_block: u64,
+    /// The highest L1 block a frame of the channel was included in
     highest_l1_block: u64,
-    lowest_l1_block: u64,
+    /// The size of the channel frames, including the per frame overhead
+    size: u64,
 }
 
 impl PendingChannel {
-    /// Creates a new pending channel with an initial frame
-    pub fn new(frame: Frame) -> Self {
-        let size = if frame.is_last {
-            Some(frame.frame_number + 1)
-        } else {
-            None
-        };
-
+    /// Creates a new empty pending channel opened at the given L1 block
+    pub fn new(channel_id: u128, opening_l1_block: u64) -> Self {
         Self {
-            channel_id: frame.channel_id,
-            highest_l1_block: frame.l1_inclusion_block,
-            lowest_l1_block: frame.l1_inclusion_block,
-            frames: vec![frame],
-            size,
+            channel_id,
+            frames: BTreeMap::new(),
+            end_frame_number: None,
+            opening_l1_block,
+            highest_l1_block: opening_l1_block,
+            size: 0,
         }
     }
 
-    pub fn is_complete(&self) -> bool {
-        self.size == Some(self.frames.len() as u16)
+    /// Returns true if the last frame and all frames before it have been received
+    pub fn is_ready(&self) -> bool {
+        self.end_frame_number
+            .map(|end| self.frames.len() == end as usize + 1)
+            .unwrap_or(false)
     }
 
-    /// Checks if the channel has timed out
-    pub fn is_timed_out(&self, max_timeout: u64) -> bool {
-        self.highest_l1_block - self.lowest_l1_block > max_timeout
+    /// Checks if the channel has timed out at the current L1 block
+    pub fn is_timed_out(&self, current_l1_block: u64, channel_timeout: u64) -> bool {
+        self.opening_l1_block + channel_timeout < current_l1_block
     }
 
-    /// Assembles the pending channel into channel data
-    pub fn assemble(&self) -> Vec<u8> {
-        let mut frames = self.frames.clone();
-        frames.sort_by_key(|f| f.frame_number);
-        frames
-            .iter()
-            .fold(Vec::new(), |a, b| [a, b.frame_data.clone()].concat())
-    }
-
-    pub fn l1_inclusion_block(&self) -> u64 {
-        self.frames
-            .iter()
-            .map(|f| f.l1_inclusion_block)
-            .max()
-            .expect("empty frame not allowed")
-    }
-
-    /// Adds a new frame to the pending channel. If the frame has already
-    /// been seen it ignores it.
-    pub fn push_frame(&mut self, frame: Frame) {
-        let has_seen = self
-            .frames
-            .iter()
-            .map(|f| f.frame_number)
-            .any(|n| n == frame.frame_number);
-
-        if !has_seen {
-            if frame.l1_inclusion_block > self.highest_l1_block {
-                self.highest_l1_block = frame.l1_inclusion_block;
-            } else if frame.l1_inclusion_block < self.lowest_l1_block {
-                self.lowest_l1_block = frame.l1_inclusion_block;
-            }
+    /// Adds a new frame to the pending channel. Duplicate frames, a second last frame and
+    /// frames past the last frame are rejected. A last frame drops any frames after it.
+    pub fn push_frame(&mut self, frame: Frame) -> Result<()> {
+        if frame.is_last && self.end_frame_number.is_some() {
+            eyre::bail!("channel already has a last frame");
+        }
+
+        if self.frames.contains_key(&frame.frame_number) {
+            eyre::bail!("duplicate frame {}", frame.frame_number);
+        }
 
-            if frame.is_last {
-                self.size = Some(frame.frame_number + 1);
+        if let Some(end) = self.end_frame_number {
+            if frame.frame_number > end {
+                eyre::bail!(
+                    "frame {} is past the last frame {}",
+                    frame.frame_number,
+                    end
+                );
             }
+        }
+
+        if frame.is_last {
+            self.end_frame_number = Some(frame.frame_number);
 
-            self.frames.push(frame)
+            if let Some(next) = frame.frame_number.checked_add(1) {
+                let pruned = self.frames.split_off(&next);
+                self.size -= pruned.values().map(frame_size).sum::<u64>();
+            }
         }
+
+        self.highest_l1_block = self.highest_l1_block.max(frame.l1_inclusion_block);
+        self.size += frame_size(&frame);
+        self.frames.insert(frame.frame_number, frame);
+
+        Ok(())
     }
 }
 
+/// The size a frame takes up in the channel bank
+fn frame_size(frame: &Frame) -> u64 {
+    frame.frame_data.len() as u64 + FRAME_OVERHEAD
+}
+
 /// A Channel
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Channel {
     pub id: u128,
-    pub data: Vec<u8>,
+    /// The data of each frame, in frame order. Assembling it into the channel data
+    /// is left to the decoding job.
+    pub frames: Vec<Vec<u8>>,
+    /// The L1 block the channel was read at
     pub l1_inclusion_block: u64,
+    /// The timestamp of the L1 block the channel was read at
+    pub l1_inclusion_timestamp: u64,
 }
 
-impl From<PendingChannel> for Channel {
-    fn from(pc: PendingChannel) -> Self {
-        Channel {
-            id: pc.channel_id,
-            data: pc.assemble(),
-            l1_inclusion_block: pc.l1_inclusion_block(),
-        }
+impl Channel {
+    /// Concatenates the frame data into the channel data
+    pub fn assemble(&self) -> Vec<u8> {
+        self.frames.concat()
+    }
+
+    /// The total size of the frame data
+    pub fn size(&self) -> usize {
+        self.frames.iter().map(Vec::len).sum()
     }
 }
 
@@ -245,164 +381,237 @@ impl From<PendingChannel> for Channel {
 mod tests {
     use crate::{
         config::{ChainConfig, Config},
-        derive::stages::batcher_transactions::{
-            BatcherTransactionMessage, BatcherTransactions, Frame,
+        derive::{
+            async_iterator::AsyncIterator,
+            stages::batcher_transactions::{BatcherTransactionMessage, BatcherTransactions, Frame},
         },
     };
     use std::sync::{mpsc, Arc};
 
     use super::Channels;
 
+    fn frame(channel_id: u128, frame_number: u16, is_last: bool, l1_block: u64) -> Frame {
+        let frame_data = vec![frame_number as u8];
+
+        Frame {
+            channel_id,
+            frame_number,
+            frame_data_len: frame_data.len() as u32,
+            frame_data,
+            is_last,
+            l1_inclusion_block: l1_block,
+        }
+    }
+
+    /// Pushes a frame at its L1 block, as a batcher transaction would
+    fn push(stage: &mut Channels<BatcherTransactions>, frame: Frame) {
+        stage.advance_origin(frame.l1_inclusion_block, 0);
+        stage.push_frame(frame);
+    }
+
     #[test]
     fn test_push_single_channel_frame() {
-        let (mut stage, _tx) = create_stage();
-
-        let frame = Frame {
-            channel_id: 5,
-            frame_number: 0,
-            frame_data: Vec::new(),
-            frame_data_len: 0,
-            is_last: true,
-            l1_inclusion_block: 0,
-        };
+        let (mut stage, _tx) = create_stage(ChainConfig::optimism_goerli());
 
-        stage.push_frame(frame);
+        push(&mut stage, frame(5, 0, true, 0));
 
         assert_eq!(stage.pending_channels.len(), 1);
         assert_eq!(stage.pending_channels[0].channel_id, 5);
-        assert!(stage.pending_channels[0].is_complete());
+        assert!(stage.pending_channels[0].is_ready());
     }
 
     #[test]
     fn test_push_multi_channel_frame() {
-        let (mut stage, _tx) = create_stage();
-
-        let frame_1 = Frame {
-            channel_id: 5,
-            frame_number: 0,
-            frame_data: Vec::new(),
-            frame_data_len: 0,
-            is_last: false,
-            l1_inclusion_block: 0,
-        };
-
-        let frame_2 = Frame {
-            channel_id: 5,
-            frame_number: 1,
-            frame_data: Vec::new(),
-            frame_data_len: 0,
-            is_last: true,
-            l1_inclusion_block: 0,
-        };
+        let (mut stage, _tx) = create_stage(ChainConfig::optimism_goerli());
 
-        stage.push_frame(frame_1);
+        push(&mut stage, frame(5, 0, false, 0));
 
         assert_eq!(stage.pending_channels.len(), 1);
         assert_eq!(stage.pending_channels[0].channel_id, 5);
-        assert!(!stage.pending_channels[0].is_complete());
+        assert!(!stage.pending_channels[0].is_ready());
 
-        stage.push_frame(frame_2);
+        push(&mut stage, frame(5, 1, true, 0));
 
         assert_eq!(stage.pending_channels.len(), 1);
         assert_eq!(stage.pending_channels[0].channel_id, 5);
-        assert!(stage.pending_channels[0].is_complete());
+        assert!(stage.pending_channels[0].is_ready());
     }
 
     #[test]
     fn test_ready_channel() {
-        let (mut stage, _tx) = create_stage();
-
-        let frame_1 = Frame {
-            channel_id: 5,
-            frame_number: 0,
-            frame_data: Vec::new(),
-            frame_data_len: 0,
-            is_last: false,
-            l1_inclusion_block: 43,
-        };
-
-        let frame_2 = Frame {
-            channel_id: 5,
-            frame_number: 1,
-            frame_data: Vec::new(),
-            frame_data_len: 0,
-            is_last: true,
-            l1_inclusion_block: 96,
-        };
+        let (mut stage, _tx) = create_stage(ChainConfig::optimism_goerli());
 
-        stage.push_frame(frame_1);
-        stage.push_frame(frame_2);
+        push(&mut stage, frame(5, 1, true, 43));
+        push(&mut stage, frame(5, 0, false, 96));
 
-        let channel = stage.fetch_ready_channel(5).unwrap();
+        let channel = stage.read_channel().unwrap();
 
         assert_eq!(channel.id, 5);
         assert_eq!(channel.l1_inclusion_block, 96);
+        assert_eq!(channel.assemble(), vec![0, 1]);
+        assert!(stage.pending_channels.is_empty());
     }
 
     #[test]
     fn test_ready_channel_still_pending() {
-        let (mut stage, _tx) = create_stage();
-
-        let frame_1 = Frame {
-            channel_id: 5,
-            frame_number: 0,
-            frame_data: Vec::new(),
-            frame_data_len: 0,
-            is_last: false,
-            l1_inclusion_block: 43,
-        };
-
-        stage.push_frame(frame_1);
+        let (mut stage, _tx) = create_stage(ChainConfig::optimism_goerli());
 
-        let channel = stage.fetch_ready_channel(5);
+        push(&mut stage, frame(5, 0, false, 43));
 
-        assert_eq!(channel, None);
+        assert_eq!(stage.read_channel(), None);
     }
 
     #[test]
     fn test_channel_timeout() {
-        let (mut stage, _tx) = create_stage();
-
-        let frame_1 = Frame {
-            channel_id: 5,
-            frame_number: 0,
-            frame_data: Vec::new(),
-            frame_data_len: 0,
-            is_last: false,
-            l1_inclusion_block: 0,
-        };
+        let chain = ChainConfig::optimism_goerli();
+        let timeout = chain.channel_timeout;
+        let (mut stage, _tx) = create_stage(chain);
 
-        let frame_2 = Frame {
-            channel_id: 5,
-            frame_number: 1,
-            frame_data: Vec::new(),
-            frame_data_len: 0,
-            is_last: true,
-            l1_inclusion_block: 500,
-        };
+        push(&mut stage, frame(5, 0, false, 0));
+        assert_eq!(stage.pending_channels.len(), 1);
+
+        // the last frame arrives after the channel timed out and is ignored
+        push(&mut stage, frame(5, 1, true, timeout + 1));
+        assert!(!stage.pending_channels[0].is_ready());
 
-        stage.push_frame(frame_1);
+        assert_eq!(stage.read_channel(), None);
+        assert!(stage.pending_channels.is_empty());
+    }
+
+    #[test]
+    fn test_channel_timeout_current_l1_block() {
+        let chain = ChainConfig::optimism_goerli();
+        let timeout = chain.channel_timeout;
+        let (mut stage, _tx) = create_stage(chain);
+
+        // a frame of another channel advances the current L1 block past the timeout
+        push(&mut stage, frame(5, 0, true, 0));
+        push(&mut stage, frame(6, 0, false, timeout + 1));
+
+        assert_eq!(stage.read_channel(), None);
         assert_eq!(stage.pending_channels.len(), 1);
+        assert_eq!(stage.pending_channels[0].channel_id, 6);
+    }
+
+    #[tokio::test]
+    async fn test_channel_timeout_empty_l1_block() {
+        let chain = ChainConfig::optimism_goerli();
+        let timeout = chain.channel_timeout;
+        let (mut stage, tx) = create_stage(chain);
+
+        // before canyon, the incomplete channel blocks the ready one behind it
+        push(&mut stage, frame(5, 0, false, 0));
+        push(&mut stage, frame(6, 0, true, 1));
+        assert_eq!(stage.next().await, None);
+
+        // an L1 block without batcher data times out the incomplete channel
+        tx.send(BatcherTransactionMessage {
+            txs: Vec::new(),
+            l1_origin: timeout + 1,
+            l1_timestamp: 0,
+        })
+        .unwrap();
+
+        let channel = stage.next().await.unwrap();
+        assert_eq!(channel.id, 6);
+        assert_eq!(channel.l1_inclusion_block, timeout + 1);
+        assert!(stage.pending_channels.is_empty());
+    }
+
+    #[test]
+    fn test_reject_invalid_frames() {
+        let (mut stage, _tx) = create_stage(ChainConfig::optimism_goerli());
+
+        push(&mut stage, frame(5, 2, false, 0));
+        push(&mut stage, frame(5, 1, true, 0));
+
+        // frames past the last frame are dropped, including the one seen before it
+        assert!(!stage.pending_channels[0].frames.contains_key(&2));
+        push(&mut stage, frame(5, 3, false, 0));
+        assert!(!stage.pending_channels[0].frames.contains_key(&3));
+
+        // a second last frame is dropped
+        push(&mut stage, frame(5, 0, true, 0));
+        assert!(!stage.pending_channels[0].frames.contains_key(&0));
+        assert_eq!(stage.pending_channels[0].end_frame_number, Some(1));
+
+        // duplicate frames are dropped
+        let mut duplicate = frame(5, 1, false, 0);
+        duplicate.frame_data = vec![9];
+        push(&mut stage, duplicate);
+        assert_eq!(stage.pending_channels[0].frames[&1].frame_data, vec![1]);
+
+        push(&mut stage, frame(5, 0, false, 0));
+        assert_eq!(stage.read_channel().unwrap().assemble(), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_read_fifo_before_canyon() {
+        let (mut stage, _tx) = create_stage(ChainConfig::optimism_goerli());
+
+        push(&mut stage, frame(5, 0, false, 0));
+        push(&mut stage, frame(6, 0, true, 0));
+
+        // the oldest channel blocks reading until it is ready
+        assert_eq!(stage.read_channel(), None);
+
+        push(&mut stage, frame(5, 1, true, 0));
+        assert_eq!(stage.read_channel().unwrap().id, 5);
+        assert_eq!(stage.read_channel().unwrap().id, 6);
+    }
+
+    #[test]
+    fn test_read_first_ready_after_canyon() {
+        let mut chain = ChainConfig::optimism_goerli();
+        chain.canyon_time = 0;
+        let (mut stage, _tx) = create_stage(chain);
+
+        push(&mut stage, frame(5, 0, false, 0));
+        push(&mut stage, frame(6, 0, true, 0));
+        push(&mut stage, frame(7, 0, true, 0));
+
+        assert_eq!(stage.read_channel().unwrap().id, 6);
+        assert_eq!(stage.read_channel().unwrap().id, 7);
+        assert_eq!(stage.read_channel(), None);
+    }
+
+    #[test]
+    fn test_forks_follow_l1_timestamp() {
+        let mut chain = ChainConfig::optimism_goerli();
+        chain.canyon_time = 1000;
+        let (mut stage, _tx) = create_stage(chain);
+
+        stage.advance_origin(0, 1000);
+        stage.push_frame(frame(5, 0, false, 0));
+        stage.push_frame(frame(6, 0, true, 0));
+
+        let channel = stage.read_channel().unwrap();
+        assert_eq!(channel.id, 6);
+        assert_eq!(channel.l1_inclusion_timestamp, 1000);
+    }
+
+    #[test]
+    fn test_prune_oldest_channel() {
+        let (mut stage, _tx) = create_stage(ChainConfig::optimism_goerli());
+        stage.max_channel_size = 450;
+
+        push(&mut stage, frame(5, 0, false, 0));
+        push(&mut stage, frame(6, 0, false, 0));
+        push(&mut stage, frame(7, 0, false, 0));
 
-        stage.push_frame(frame_2);
-        assert_eq!(stage.pending_channels.len(), 0);
+        assert_eq!(stage.pending_channels.len(), 2);
+        assert_eq!(stage.pending_channels[0].channel_id, 6);
     }
 
-    fn create_stage() -> (
+    fn create_stage(
+        chain: ChainConfig,
+    ) -> (
         Channels<BatcherTransactions>,
         mpsc::Sender<BatcherTransactionMessage>,
     ) {
         let config = Config {
-            l1_rpc_url: String::new(),
-            l2_rpc_url: String::new(),
-            l2_engine_url: String::new(),
-            jwt_secret: String::new(),
-            rpc_port: 9545,
-            chain: ChainConfig::optimism_goerli(),
-            checkpoint_sync_url: None,
-            devnet: false,
-            local_sequencer: Default::default(),
-            watcher_delay: Default::default(),
+            chain,
+            ..Config::default_for_tests()
         };
 
         let (tx, rx) = mpsc::channel();
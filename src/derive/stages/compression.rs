@@ -0,0 +1,212 @@
+use std::io::Read;
+
+use libflate::zlib::Decoder;
+use thiserror::Error;
+
+/// Channel version byte for brotli compressed channels (introduced in Fjord)
+const CHANNEL_VERSION_BROTLI: u8 = 0x01;
+
+/// Errors that can occur while decompressing a channel
+#[derive(Debug, Error)]
+pub enum CompressionError {
+    #[error("channel data is empty")]
+    Empty,
+    #[error("unsupported channel compression type: {0:#04x}")]
+    UnsupportedType(u8),
+    #[error("brotli compressed channel before fjord")]
+    BrotliBeforeFjord,
+    #[error("failed to decompress channel: {0}")]
+    Decompression(#[from] std::io::Error),
+}
+
+/// Compression algorithms a channel may be encoded with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionAlgorithm {
+    Zlib,
+    Brotli,
+}
+
+impl CompressionAlgorithm {
+    /// Detects the compression algorithm from the first byte of the channel data.
+    /// Zlib streams are recognized by their CM nibble (8 or 15), other channels
+    /// carry an explicit version byte.
+    pub fn from_channel_data(data: &[u8]) -> Result<Self, CompressionError> {
+        let first = *data.first().ok_or(CompressionError::Empty)?;
+
+        match first {
+            b if b & 0x0F == 0x08 || b & 0x0F == 0x0F => Ok(Self::Zlib),
+            CHANNEL_VERSION_BROTLI => Ok(Self::Brotli),
+            b => Err(CompressionError::UnsupportedType(b)),
+        }
+    }
+
+    /// Decompresses the channel data, reading at most `max_size` bytes. Any
+    /// data past the limit is ignored. If the stream is corrupted, the data
+    /// decompressed before the error is kept, as it may hold valid batches.
+    pub fn decompress(
+        &self,
+        data: &[u8],
+        max_size: usize,
+    ) -> Result<Decompressed, CompressionError> {
+        let mut out = Vec::new();
+
+        // read_to_end keeps the bytes read before an error in the buffer
+        let result = match self {
+            Self::Zlib => Decoder::new(data)?
+                .take(max_size as u64)
+                .read_to_end(&mut out),
+            Self::Brotli => brotli::Decompressor::new(&data[1..], 4096)
+                .take(max_size as u64)
+                .read_to_end(&mut out),
+        };
+
+        Ok(Decompressed {
+            data: out,
+            error: result.err().map(CompressionError::from),
+        })
+    }
+}
+
+/// Decompressed channel data
+#[derive(Debug)]
+pub struct Decompressed {
+    /// The data decompressed before the end of the stream or the first error
+    pub data: Vec<u8>,
+    /// The error that ended decompression early, if any
+    pub error: Option<CompressionError>,
+}
+
+/// Decompresses channel data with the algorithm selected by its first byte.
+/// Brotli is only accepted once Fjord is active.
+pub fn decompress_channel(
+    data: &[u8],
+    max_size: usize,
+    is_fjord: bool,
+) -> Result<Decompressed, CompressionError> {
+    let algorithm = CompressionAlgorithm::from_channel_data(data)?;
+    if algorithm == CompressionAlgorithm::Brotli && !is_fjord {
+        return Err(CompressionError::BrotliBeforeFjord);
+    }
+
+    algorithm.decompress(data, max_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use libflate::zlib::Encoder;
+
+    use super::*;
+
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut encoder = Encoder::new(Vec::new()).unwrap();
+        encoder.write_all(data).unwrap();
+        encoder.finish().into_result().unwrap()
+    }
+
+    fn brotli(data: &[u8]) -> Vec<u8> {
+        let mut out = vec![CHANNEL_VERSION_BROTLI];
+        let mut writer = brotli::CompressorWriter::new(&mut out, 4096, 11, 22);
+        writer.write_all(data).unwrap();
+        drop(writer);
+        out
+    }
+
+    #[test]
+    fn test_decompress_zlib() {
+        let data = b"hello zlib channel".repeat(10);
+        let compressed = zlib(&data);
+
+        assert_eq!(
+            CompressionAlgorithm::from_channel_data(&compressed).unwrap(),
+            CompressionAlgorithm::Zlib
+        );
+        assert_eq!(
+            decompress_channel(&compressed, 1000, true).unwrap().data,
+            data
+        );
+    }
+
+    #[test]
+    fn test_decompress_brotli() {
+        let data = b"hello brotli channel".repeat(10);
+        let compressed = brotli(&data);
+
+        assert_eq!(
+            CompressionAlgorithm::from_channel_data(&compressed).unwrap(),
+            CompressionAlgorithm::Brotli
+        );
+        assert_eq!(
+            decompress_channel(&compressed, 1000, true).unwrap().data,
+            data
+        );
+    }
+
+    #[test]
+    fn test_decompress_brotli_before_fjord() {
+        let compressed = brotli(b"hello brotli channel");
+
+        assert!(matches!(
+            decompress_channel(&compressed, 1000, false),
+            Err(CompressionError::BrotliBeforeFjord)
+        ));
+    }
+
+    #[test]
+    fn test_decompress_limit() {
+        let data = vec![7u8; 1000];
+
+        assert_eq!(
+            decompress_channel(&zlib(&data), 100, true)
+                .unwrap()
+                .data
+                .len(),
+            100
+        );
+        assert_eq!(
+            decompress_channel(&brotli(&data), 100, true)
+                .unwrap()
+                .data
+                .len(),
+            100
+        );
+    }
+
+    #[test]
+    fn test_decompress_invalid() {
+        assert!(matches!(
+            decompress_channel(&[], 100, true),
+            Err(CompressionError::Empty)
+        ));
+        assert!(matches!(
+            decompress_channel(&[0x02, 0x00], 100, true),
+            Err(CompressionError::UnsupportedType(0x02))
+        ));
+        assert!(matches!(
+            decompress_channel(&[0x78, 0x00, 0x00], 100, true),
+            Err(CompressionError::Decompression(_))
+        ));
+    }
+
+    #[test]
+    fn test_decompress_truncated_keeps_prefix() {
+        let data: Vec<u8> = (0..20000u32).flat_map(|i| i.to_be_bytes()).collect();
+        // zlib streams are decoded a deflate block at a time, so write several blocks
+        let mut encoder = Encoder::new(Vec::new()).unwrap();
+        for chunk in data.chunks(4096) {
+            encoder.write_all(chunk).unwrap();
+            encoder.flush().unwrap();
+        }
+        let zlib_blocks = encoder.finish().into_result().unwrap();
+
+        for compressed in [zlib_blocks, brotli(&data)] {
+            let truncated = &compressed[..compressed.len() / 2];
+
+            let decompressed = decompress_channel(truncated, data.len(), true).unwrap();
+            assert!(decompressed.error.is_some());
+            assert!(!decompressed.data.is_empty());
+            assert_eq!(decompressed.data, data[..decompressed.data.len()]);
+        }
+    }
+}
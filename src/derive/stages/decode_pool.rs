@@ -0,0 +1,176 @@
+use std::sync::Arc;
+
+use eyre::Result;
+use lazy_static::lazy_static;
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    task::spawn_blocking,
+};
+
+use crate::telemetry::metrics;
+
+/// The memory budget shared by all in-flight decoding jobs
+const DECODE_MEMORY_LIMIT: usize = 256 * 1024 * 1024;
+
+lazy_static! {
+    /// The decoding pool shared by all pipelines
+    pub static ref DECODE_POOL: DecodePool = DecodePool::new(DECODE_MEMORY_LIMIT);
+}
+
+/// Runs CPU heavy derivation work, such as channel decompression and batch decoding,
+/// on the blocking thread pool instead of the async runtime. Each job reserves the
+/// memory it may use up front, and jobs wait until the budget has room for them.
+pub struct DecodePool {
+    memory: Arc<Semaphore>,
+    limit: usize,
+}
+
+impl DecodePool {
+    /// Creates a pool that holds at most `limit` bytes across its running jobs
+    pub fn new(limit: usize) -> Self {
+        let limit = limit.clamp(1, u32::MAX as usize);
+        Self {
+            memory: Arc::new(Semaphore::new(limit)),
+            limit,
+        }
+    }
+
+    /// Runs a job that uses up to `size` bytes of memory on a blocking thread. Jobs
+    /// larger than the whole budget run on their own.
+    pub async fn run<T, F>(&self, size: usize, job: F) -> Result<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let reserved = size.clamp(1, self.limit);
+        let permit = self
+            .memory
+            .clone()
+            .acquire_many_owned(reserved as u32)
+            .await?;
+
+        // The reservation moves into the job, so it is held until the job ends even
+        // if this future is dropped while waiting on it
+        let reservation = Reservation::new(permit, reserved);
+        let result = spawn_blocking(move || {
+            let _reservation = reservation;
+            job()
+        })
+        .await;
+
+        Ok(result?)
+    }
+
+    /// The number of bytes not reserved by running jobs
+    pub fn available(&self) -> usize {
+        self.memory.available_permits()
+    }
+}
+
+/// Memory reserved by a running job. Releases the permits and the gauge on drop.
+struct Reservation {
+    _permit: OwnedSemaphorePermit,
+    size: usize,
+}
+
+impl Reservation {
+    fn new(permit: OwnedSemaphorePermit, size: usize) -> Self {
+        metrics::DECODE_MEMORY.add(size as i64);
+        Self {
+            _permit: permit,
+            size,
+        }
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        metrics::DECODE_MEMORY.sub(self.size as i64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+        time::Duration,
+    };
+
+    use futures::future::join_all;
+
+    use super::DecodePool;
+
+    #[tokio::test]
+    async fn test_jobs_within_budget() {
+        let pool = Arc::new(DecodePool::new(100));
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+
+        let jobs = (0..4).map(|i| {
+            let pool = pool.clone();
+            let running = running.clone();
+            let max_running = max_running.clone();
+
+            async move {
+                pool.run(60, move || {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_running.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(20));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    i
+                })
+                .await
+                .unwrap()
+            }
+        });
+
+        let results = join_all(jobs).await;
+
+        assert_eq!(results, vec![0, 1, 2, 3]);
+        assert_eq!(max_running.load(Ordering::SeqCst), 1);
+        assert_eq!(pool.available(), 100);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_job() {
+        let pool = DecodePool::new(10);
+        let result = pool.run(1000, || 42).await.unwrap();
+
+        assert_eq!(result, 42);
+        assert_eq!(pool.available(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_job_keeps_reservation() {
+        let pool = Arc::new(DecodePool::new(100));
+        let (started_sender, started_recv) = std::sync::mpsc::channel();
+        let (release_sender, release_recv) = std::sync::mpsc::channel::<()>();
+
+        let job = tokio::spawn({
+            let pool = pool.clone();
+            async move {
+                pool.run(60, move || {
+                    started_sender.send(()).unwrap();
+                    release_recv.recv().ok();
+                })
+                .await
+            }
+        });
+
+        tokio::task::spawn_blocking(move || started_recv.recv().unwrap())
+            .await
+            .unwrap();
+        job.abort();
+        assert!(job.await.unwrap_err().is_cancelled());
+        assert_eq!(pool.available(), 40);
+
+        release_sender.send(()).unwrap();
+        while pool.available() != 100 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+}
@@ -2,3 +2,7 @@ pub mod attributes;
 pub mod batcher_transactions;
 pub mod batches;
 pub mod channels;
+pub mod compression;
+pub mod decode_pool;
+pub mod span_batch;
+pub mod upgrades;
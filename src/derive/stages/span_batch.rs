@@ -0,0 +1,533 @@
+use ethers::types::{Address, U256};
+use ethers::utils::rlp::{Rlp, RlpStream};
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::common::RawTransaction;
+use crate::config::ChainConfig;
+
+/// The maximum number of blocks or transactions a span batch may contain
+const MAX_SPAN_BATCH_ELEMENT_COUNT: u64 = 10_000_000;
+
+/// A span batch, covering a contiguous range of L2 blocks. See the
+/// [span batch spec](https://github.com/ethereum-optimism/specs/blob/main/specs/protocol/delta/span-batches.md).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpanBatch {
+    /// First 20 bytes of the parent hash of the first block in the span
+    pub parent_check: [u8; 20],
+    /// First 20 bytes of the L1 origin hash of the last block in the span
+    pub l1_origin_check: [u8; 20],
+    /// The blocks covered by the span, in order
+    pub blocks: Vec<SpanBatchElement>,
+    /// The L1 block the span batch was fully derived in
+    pub l1_inclusion_block: u64,
+}
+
+/// A single L2 block inside a [SpanBatch]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpanBatchElement {
+    /// The L2 block timestamp
+    pub timestamp: u64,
+    /// The L1 origin number of the block
+    pub epoch_num: u64,
+    /// Fully encoded transactions of the block
+    pub transactions: Vec<RawTransaction>,
+}
+
+impl SpanBatch {
+    /// Decodes a span batch from its encoding (excluding the batch version byte)
+    pub fn decode(data: &[u8], l1_inclusion_block: u64, chain: &ChainConfig) -> Result<Self> {
+        let mut r = Reader::new(data);
+
+        // prefix
+        let rel_timestamp = r.uvarint()?;
+        let l1_origin_num = r.uvarint()?;
+        let parent_check = r.bytes(20)?.try_into()?;
+        let l1_origin_check = r.bytes(20)?.try_into()?;
+
+        // payload
+        let block_count = r.uvarint()?;
+        if block_count == 0 || block_count > MAX_SPAN_BATCH_ELEMENT_COUNT {
+            eyre::bail!("invalid span batch block count: {}", block_count);
+        }
+
+        let block_count = block_count as usize;
+        let origin_bits = r.bits(block_count)?;
+
+        let mut block_tx_counts = Vec::with_capacity(block_count);
+        let mut total_tx_count = 0u64;
+        for _ in 0..block_count {
+            let count = r.uvarint()?;
+            total_tx_count = total_tx_count.saturating_add(count);
+            if total_tx_count > MAX_SPAN_BATCH_ELEMENT_COUNT {
+                eyre::bail!("too many transactions in span batch");
+            }
+            block_tx_counts.push(count as usize);
+        }
+
+        let mut transactions =
+            SpanBatchTransactions::decode(&mut r, total_tx_count as usize, chain.l2_chain_id)?
+                .into_iter();
+
+        if !r.is_empty() {
+            eyre::bail!("trailing data after span batch");
+        }
+
+        // derive the L1 origin of each block, walking back from the last one
+        let mut epoch_nums = vec![0; block_count];
+        let mut epoch_num = l1_origin_num;
+        for i in (0..block_count).rev() {
+            epoch_nums[i] = epoch_num;
+            if origin_bits[i] && i > 0 {
+                epoch_num = epoch_num
+                    .checked_sub(1)
+                    .ok_or(eyre::eyre!("invalid span batch origin bits"))?;
+            }
+        }
+
+        let start_timestamp = chain.l2_genesis.timestamp + rel_timestamp;
+        let blocks = block_tx_counts
+            .into_iter()
+            .zip(epoch_nums)
+            .enumerate()
+            .map(|(i, (tx_count, epoch_num))| SpanBatchElement {
+                timestamp: start_timestamp + i as u64 * chain.blocktime,
+                epoch_num,
+                transactions: transactions.by_ref().take(tx_count).collect(),
+            })
+            .collect();
+
+        Ok(Self {
+            parent_check,
+            l1_origin_check,
+            blocks,
+            l1_inclusion_block,
+        })
+    }
+
+    /// Timestamp of the first block in the span
+    pub fn start_timestamp(&self) -> u64 {
+        self.blocks[0].timestamp
+    }
+
+    /// Timestamp of the last block in the span
+    pub fn end_timestamp(&self) -> u64 {
+        self.blocks[self.blocks.len() - 1].timestamp
+    }
+
+    /// L1 origin number of the first block in the span
+    pub fn start_epoch_num(&self) -> u64 {
+        self.blocks[0].epoch_num
+    }
+
+    /// L1 origin number of the last block in the span
+    pub fn end_epoch_num(&self) -> u64 {
+        self.blocks[self.blocks.len() - 1].epoch_num
+    }
+
+    /// Checks the parent hash of the first block against `parent_check`
+    pub fn check_parent_hash(&self, hash: ethers::types::H256) -> bool {
+        hash.as_bytes()[..20] == self.parent_check
+    }
+
+    /// Checks the L1 origin hash of the last block against `l1_origin_check`
+    pub fn check_origin_hash(&self, hash: ethers::types::H256) -> bool {
+        hash.as_bytes()[..20] == self.l1_origin_check
+    }
+}
+
+/// The transaction section of a span batch payload
+struct SpanBatchTransactions;
+
+impl SpanBatchTransactions {
+    /// Decodes `count` transactions and re-assembles their full signed encodings
+    fn decode(r: &mut Reader, count: usize, chain_id: u64) -> Result<Vec<RawTransaction>> {
+        let contract_creation_bits = r.bits(count)?;
+        let y_parity_bits = r.bits(count)?;
+
+        let mut sigs = Vec::with_capacity(count);
+        for _ in 0..count {
+            let r_bytes = r.bytes(32)?;
+            let s_bytes = r.bytes(32)?;
+            sigs.push((
+                U256::from_big_endian(r_bytes),
+                U256::from_big_endian(s_bytes),
+            ));
+        }
+
+        let creation_count = contract_creation_bits.iter().filter(|b| **b).count();
+        let mut tos = Vec::with_capacity(count - creation_count);
+        for _ in 0..count - creation_count {
+            tos.push(Address::from_slice(r.bytes(20)?));
+        }
+
+        let mut datas = Vec::with_capacity(count);
+        for _ in 0..count {
+            datas.push(r.tx_data()?);
+        }
+
+        let mut nonces = Vec::with_capacity(count);
+        for _ in 0..count {
+            nonces.push(r.uvarint()?);
+        }
+
+        let mut gases = Vec::with_capacity(count);
+        for _ in 0..count {
+            gases.push(r.uvarint()?);
+        }
+
+        let legacy_count = datas.iter().filter(|(tx_type, _)| *tx_type == 0).count();
+        let protected_bits = r.bits(legacy_count)?;
+
+        let mut tos = tos.into_iter();
+        let mut protected_bits = protected_bits.into_iter();
+
+        (0..count)
+            .map(|i| {
+                let to = if contract_creation_bits[i] {
+                    None
+                } else {
+                    tos.next()
+                };
+
+                let (tx_type, data) = &datas[i];
+                let tx = SpanBatchTx {
+                    tx_type: *tx_type,
+                    data,
+                    to,
+                    nonce: nonces[i],
+                    gas: gases[i],
+                    y_parity: y_parity_bits[i],
+                    r: sigs[i].0,
+                    s: sigs[i].1,
+                    protected: *tx_type == 0 && protected_bits.next().unwrap_or_default(),
+                };
+
+                tx.encode(chain_id)
+            })
+            .collect()
+    }
+}
+
+/// Components of a single transaction spread across the span batch payload
+struct SpanBatchTx<'a> {
+    tx_type: u8,
+    data: &'a [u8],
+    to: Option<Address>,
+    nonce: u64,
+    gas: u64,
+    y_parity: bool,
+    r: U256,
+    s: U256,
+    protected: bool,
+}
+
+impl SpanBatchTx<'_> {
+    /// Encodes the full signed transaction
+    fn encode(&self, chain_id: u64) -> Result<RawTransaction> {
+        let rlp = Rlp::new(self.data);
+        let mut s = RlpStream::new();
+
+        match self.tx_type {
+            // legacy: [value, gas_price, data]
+            0 => {
+                let v = if self.protected {
+                    chain_id * 2 + 35 + self.y_parity as u64
+                } else {
+                    27 + self.y_parity as u64
+                };
+
+                s.begin_list(9);
+                s.append(&self.nonce);
+                s.append_raw(rlp.at(1)?.as_raw(), 1);
+                s.append(&self.gas);
+                self.append_to(&mut s);
+                s.append_raw(rlp.at(0)?.as_raw(), 1);
+                s.append_raw(rlp.at(2)?.as_raw(), 1);
+                s.append(&v);
+            }
+            // eip-2930: [value, gas_price, data, access_list]
+            1 => {
+                s.begin_list(11);
+                s.append(&chain_id);
+                s.append(&self.nonce);
+                s.append_raw(rlp.at(1)?.as_raw(), 1);
+                s.append(&self.gas);
+                self.append_to(&mut s);
+                s.append_raw(rlp.at(0)?.as_raw(), 1);
+                s.append_raw(rlp.at(2)?.as_raw(), 1);
+                s.append_raw(rlp.at(3)?.as_raw(), 1);
+                s.append(&self.y_parity);
+            }
+            // eip-1559: [value, max_priority_fee_per_gas, max_fee_per_gas, data, access_list]
+            2 => {
+                s.begin_list(12);
+                s.append(&chain_id);
+                s.append(&self.nonce);
+                s.append_raw(rlp.at(1)?.as_raw(), 1);
+                s.append_raw(rlp.at(2)?.as_raw(), 1);
+                s.append(&self.gas);
+                self.append_to(&mut s);
+                s.append_raw(rlp.at(0)?.as_raw(), 1);
+                s.append_raw(rlp.at(3)?.as_raw(), 1);
+                s.append_raw(rlp.at(4)?.as_raw(), 1);
+                s.append(&self.y_parity);
+            }
+            _ => eyre::bail!("invalid span batch transaction type"),
+        }
+
+        s.append(&self.r);
+        s.append(&self.s);
+
+        let encoded = s.out().to_vec();
+        let tx = match self.tx_type {
+            0 => encoded,
+            tx_type => [vec![tx_type], encoded].concat(),
+        };
+
+        Ok(RawTransaction(tx))
+    }
+
+    fn append_to(&self, s: &mut RlpStream) {
+        match self.to {
+            Some(to) => s.append(&to),
+            None => s.append_empty_data(),
+        };
+    }
+}
+
+/// Cursor over span batch bytes
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() < len {
+            eyre::bail!("span batch too short");
+        }
+
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn uvarint(&mut self) -> Result<u64> {
+        let (value, rest) = unsigned_varint::decode::u64(self.data)?;
+        self.data = rest;
+        Ok(value)
+    }
+
+    /// Reads a bitlist of `len` bits, encoded as a big-endian integer where
+    /// bit `i` is the `i`-th least significant bit
+    fn bits(&mut self, len: usize) -> Result<Vec<bool>> {
+        let bytes = self.bytes(len.div_ceil(8))?;
+        let bits = (0..len)
+            .map(|i| bytes[bytes.len() - 1 - i / 8] >> (i % 8) & 1 == 1)
+            .collect();
+
+        Ok(bits)
+    }
+
+    /// Reads a single `tx_data` entry, returning its type and RLP list
+    fn tx_data(&mut self) -> Result<(u8, &'a [u8])> {
+        let tx_type = match self.data.first() {
+            Some(1) | Some(2) => self.bytes(1)?[0],
+            Some(_) => 0,
+            None => eyre::bail!("span batch too short"),
+        };
+
+        let len = Rlp::new(self.data).payload_info()?.total();
+        let data = self.bytes(len)?;
+        if !Rlp::new(data).is_list() {
+            eyre::bail!("invalid span batch transaction data");
+        }
+
+        Ok((tx_type, data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        signers::{LocalWallet, Signer},
+        types::{
+            transaction::eip2718::TypedTransaction, Eip1559TransactionRequest, Transaction,
+            TransactionRequest, H256,
+        },
+        utils::{keccak256, rlp::Decodable},
+    };
+
+    use super::*;
+
+    const KEY: &str = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+
+    async fn signed_txs(chain_id: u64) -> Vec<Vec<u8>> {
+        let wallet = LocalWallet::from_bytes(&hex::decode(KEY).unwrap())
+            .unwrap()
+            .with_chain_id(chain_id);
+
+        let txs: Vec<TypedTransaction> = vec![
+            TransactionRequest::new()
+                .to(Address::repeat_byte(0x11))
+                .value(1000)
+                .gas(21000)
+                .gas_price(7)
+                .nonce(3)
+                .chain_id(chain_id)
+                .into(),
+            Eip1559TransactionRequest::new()
+                .value(0)
+                .data(vec![0x60, 0x00])
+                .gas(100_000)
+                .max_fee_per_gas(100)
+                .max_priority_fee_per_gas(1)
+                .nonce(4)
+                .chain_id(chain_id)
+                .into(),
+        ];
+
+        let mut encoded = Vec::new();
+        for tx in txs {
+            let sig = wallet.sign_transaction(&tx).await.unwrap();
+            encoded.push(tx.rlp_signed(&sig).to_vec());
+        }
+
+        encoded
+    }
+
+    fn bits(bits: &[bool]) -> Vec<u8> {
+        let mut out = vec![0u8; bits.len().div_ceil(8)];
+        let len = out.len();
+        for (i, bit) in bits.iter().enumerate() {
+            if *bit {
+                out[len - 1 - i / 8] |= 1 << (i % 8);
+            }
+        }
+        out
+    }
+
+    fn uvarint(v: u64) -> Vec<u8> {
+        let mut buf = unsigned_varint::encode::u64_buffer();
+        unsigned_varint::encode::u64(v, &mut buf).to_vec()
+    }
+
+    /// Splits the signed legacy and eip-1559 transactions into span batch fields
+    fn encode_span(
+        rel_timestamp: u64,
+        l1_origin_num: u64,
+        origin_bits: &[bool],
+        tx_counts: &[u64],
+        txs: &[Vec<u8>],
+    ) -> Vec<u8> {
+        let legacy = Transaction::decode(&Rlp::new(&txs[0])).unwrap();
+        let dynamic = Transaction::decode(&Rlp::new(&txs[1])).unwrap();
+
+        let mut out = Vec::new();
+        out.extend(uvarint(rel_timestamp));
+        out.extend(uvarint(l1_origin_num));
+        out.extend([0xaa; 20]);
+        out.extend([0xbb; 20]);
+        out.extend(uvarint(origin_bits.len() as u64));
+        out.extend(bits(origin_bits));
+        tx_counts.iter().for_each(|c| out.extend(uvarint(*c)));
+
+        out.extend(bits(&[false, true]));
+        let legacy_parity = legacy.v.as_u64() - (legacy.chain_id.unwrap().as_u64() * 2 + 35);
+        out.extend(bits(&[legacy_parity == 1, dynamic.v.as_u64() == 1]));
+        for tx in [&legacy, &dynamic] {
+            let mut sig = [0u8; 64];
+            tx.r.to_big_endian(&mut sig[..32]);
+            tx.s.to_big_endian(&mut sig[32..]);
+            out.extend(sig);
+        }
+        out.extend(legacy.to.unwrap().as_bytes());
+
+        let mut s = RlpStream::new_list(3);
+        s.append(&legacy.value);
+        s.append(&legacy.gas_price.unwrap());
+        s.append(&legacy.input.to_vec());
+        out.extend(s.out());
+
+        let mut s = RlpStream::new_list(5);
+        s.append(&dynamic.value);
+        s.append(&dynamic.max_priority_fee_per_gas.unwrap());
+        s.append(&dynamic.max_fee_per_gas.unwrap());
+        s.append(&dynamic.input.to_vec());
+        s.begin_list(0);
+        out.push(2);
+        out.extend(s.out());
+
+        out.extend(uvarint(legacy.nonce.as_u64()));
+        out.extend(uvarint(dynamic.nonce.as_u64()));
+        out.extend(uvarint(legacy.gas.as_u64()));
+        out.extend(uvarint(dynamic.gas.as_u64()));
+        out.extend(bits(&[true]));
+
+        out
+    }
+
+    #[tokio::test]
+    async fn test_decode_span_batch() {
+        let chain = ChainConfig::optimism();
+        let txs = signed_txs(chain.l2_chain_id).await;
+
+        let data = encode_span(10, 100, &[false, true, false], &[1, 0, 1], &txs);
+        let batch = SpanBatch::decode(&data, 120, &chain).unwrap();
+
+        assert_eq!(batch.parent_check, [0xaa; 20]);
+        assert_eq!(batch.l1_origin_check, [0xbb; 20]);
+        assert_eq!(batch.l1_inclusion_block, 120);
+        assert_eq!(batch.blocks.len(), 3);
+
+        let genesis = chain.l2_genesis.timestamp;
+        assert_eq!(batch.start_timestamp(), genesis + 10);
+        assert_eq!(batch.end_timestamp(), genesis + 14);
+        assert_eq!(batch.start_epoch_num(), 99);
+        assert_eq!(batch.end_epoch_num(), 100);
+        assert_eq!(batch.blocks[1].epoch_num, 100);
+
+        assert_eq!(
+            batch.blocks[0].transactions,
+            vec![RawTransaction(txs[0].clone())]
+        );
+        assert!(batch.blocks[1].transactions.is_empty());
+        assert_eq!(
+            batch.blocks[2].transactions,
+            vec![RawTransaction(txs[1].clone())]
+        );
+
+        let hash = H256(keccak256(&batch.blocks[2].transactions[0].0));
+        assert_eq!(hash, H256(keccak256(&txs[1])));
+    }
+
+    #[tokio::test]
+    async fn test_decode_span_batch_trailing_data() {
+        let chain = ChainConfig::optimism();
+        let txs = signed_txs(chain.l2_chain_id).await;
+
+        let mut data = encode_span(0, 1, &[true], &[2], &txs);
+        data.push(0);
+
+        assert!(SpanBatch::decode(&data, 0, &chain).is_err());
+    }
+
+    #[test]
+    fn test_decode_span_batch_empty() {
+        let mut data = Vec::new();
+        data.extend(uvarint(0));
+        data.extend(uvarint(1));
+        data.extend([0; 40]);
+        data.extend(uvarint(0));
+
+        assert!(SpanBatch::decode(&data, 0, &ChainConfig::optimism()).is_err());
+    }
+}
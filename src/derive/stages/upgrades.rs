@@ -0,0 +1,302 @@
+use ethers::types::{Address, H256};
+use ethers::utils::{get_contract_address, keccak256};
+use eyre::Result;
+
+use crate::config::{ChainConfig, SystemAccounts};
+
+/// Source hash domain of network upgrade deposits
+const UPGRADE_DEPOSIT_SOURCE_DOMAIN: u64 = 2;
+
+/// Selector of the proxy `upgradeTo(address)` function
+const UPGRADE_TO_SELECTOR: [u8; 4] = [0x36, 0x59, 0xcf, 0xe6];
+
+/// Selector of the GasPriceOracle `setEcotone()` function
+const SET_ECOTONE_SELECTOR: [u8; 4] = [0x22, 0xb9, 0x08, 0xb3];
+
+/// Selector of the GasPriceOracle `setFjord()` function
+const SET_FJORD_SELECTOR: [u8; 4] = [0x8e, 0x98, 0xb1, 0x06];
+
+/// Address of the L1Block predeploy proxy
+const L1_BLOCK_PROXY: &str = "0x4200000000000000000000000000000000000015";
+
+/// Address of the GasPriceOracle predeploy proxy
+const GAS_PRICE_ORACLE_PROXY: &str = "0x420000000000000000000000000000000000000F";
+
+/// Deployer of the Ecotone L1Block implementation
+const ECOTONE_L1_BLOCK_DEPLOYER: &str = "0x4210000000000000000000000000000000000000";
+
+/// Deployer of the Ecotone GasPriceOracle implementation
+const ECOTONE_GAS_PRICE_ORACLE_DEPLOYER: &str = "0x4210000000000000000000000000000000000001";
+
+/// Deployer of the Fjord GasPriceOracle implementation
+const FJORD_GAS_PRICE_ORACLE_DEPLOYER: &str = "0x4210000000000000000000000000000000000002";
+
+/// Creation code of the Ecotone L1Block implementation, as specified for the upgrade. Empty
+/// while it is not bundled, which halts derivation at the activation block.
+const ECOTONE_L1_BLOCK_CREATION_CODE: &str = "";
+
+/// Creation code of the Ecotone GasPriceOracle implementation, as specified for the upgrade
+const ECOTONE_GAS_PRICE_ORACLE_CREATION_CODE: &str = "";
+
+/// Creation code of the Fjord GasPriceOracle implementation, as specified for the upgrade
+const FJORD_GAS_PRICE_ORACLE_CREATION_CODE: &str = "";
+
+/// Sender of the EIP-4788 beacon block roots contract deployment
+const BEACON_ROOTS_DEPLOYER: &str = "0x0B799C86a49DEeb90402691F1041aa3AF2d3C875";
+
+/// EIP-4788 beacon block roots contract creation code
+const BEACON_ROOTS_CREATION_CODE: &str = "60618060095f395ff33373fffffffffffffffffffffffffffffffffffffffe14604d57602036146024575f5ffd5b5f35801560495762001fff810690815414603c575f5ffd5b62001fff01545f5260205ff35b5f5ffd5b62001fff42064281555f359062001fff015500";
+
+/// A deposit transaction that is executed in the first block of a hardfork
+#[derive(Debug, Clone)]
+pub struct UpgradeDeposit {
+    /// Human readable intent, hashed into the source hash
+    pub intent: &'static str,
+    /// Sender of the deposit
+    pub from: Address,
+    /// Recipient of the deposit, `None` for contract creations
+    pub to: Option<Address>,
+    /// Gas limit of the deposit
+    pub gas: u64,
+    /// Deposit calldata or creation code
+    pub data: Vec<u8>,
+}
+
+impl UpgradeDeposit {
+    /// The deposit source hash, `keccak256(bytes32(2) ++ keccak256(intent))`
+    pub fn source_hash(&self) -> H256 {
+        let domain = H256::from_low_u64_be(UPGRADE_DEPOSIT_SOURCE_DOMAIN).to_fixed_bytes();
+        let intent = keccak256(self.intent.as_bytes());
+        H256::from_slice(&keccak256([domain, intent].concat()))
+    }
+}
+
+/// Returns the network upgrade deposits that must be included in the L2 block at the
+/// timestamp, in order. These are only non-empty for the first block of a hardfork that
+/// upgrades predeploys. Errors if the creation code of an implementation deployment is not
+/// bundled, as the block could not match the one of other nodes without it.
+pub fn upgrade_deposits(chain: &ChainConfig, timestamp: u64) -> Result<Vec<UpgradeDeposit>> {
+    let mut deposits = Vec::new();
+
+    if chain.is_ecotone_activation_block(timestamp) {
+        deposits.append(&mut ecotone_upgrade_deposits());
+    }
+
+    if chain.is_fjord_activation_block(timestamp) {
+        deposits.append(&mut fjord_upgrade_deposits());
+    }
+
+    if let Some(deposit) = deposits
+        .iter()
+        .find(|deposit| deposit.to.is_none() && deposit.data.is_empty())
+    {
+        eyre::bail!(
+            "creation code of upgrade deposit {:?} is not bundled",
+            deposit.intent
+        );
+    }
+
+    Ok(deposits)
+}
+
+/// The Ecotone upgrade deposits: the L1Block and GasPriceOracle implementation deployments,
+/// the proxy updates to them, the beacon block roots deployment and the GasPriceOracle
+/// activation
+pub(super) fn ecotone_upgrade_deposits() -> Vec<UpgradeDeposit> {
+    let l1_block = get_contract_address(address(ECOTONE_L1_BLOCK_DEPLOYER), 0);
+    let gas_price_oracle = get_contract_address(address(ECOTONE_GAS_PRICE_ORACLE_DEPLOYER), 0);
+
+    vec![
+        UpgradeDeposit {
+            intent: "Ecotone: L1 Block Deployment",
+            from: address(ECOTONE_L1_BLOCK_DEPLOYER),
+            to: None,
+            gas: 375_000,
+            data: hex::decode(ECOTONE_L1_BLOCK_CREATION_CODE).unwrap(),
+        },
+        UpgradeDeposit {
+            intent: "Ecotone: Gas Price Oracle Deployment",
+            from: address(ECOTONE_GAS_PRICE_ORACLE_DEPLOYER),
+            to: None,
+            gas: 1_000_000,
+            data: hex::decode(ECOTONE_GAS_PRICE_ORACLE_CREATION_CODE).unwrap(),
+        },
+        UpgradeDeposit {
+            intent: "Ecotone: L1 Block Proxy Update",
+            from: Address::zero(),
+            to: Some(address(L1_BLOCK_PROXY)),
+            gas: 50_000,
+            data: upgrade_to(l1_block),
+        },
+        UpgradeDeposit {
+            intent: "Ecotone: Gas Price Oracle Proxy Update",
+            from: Address::zero(),
+            to: Some(address(GAS_PRICE_ORACLE_PROXY)),
+            gas: 50_000,
+            data: upgrade_to(gas_price_oracle),
+        },
+        UpgradeDeposit {
+            intent: "Ecotone: beacon block roots contract deployment",
+            from: address(BEACON_ROOTS_DEPLOYER),
+            to: None,
+            gas: 250_000,
+            data: hex::decode(BEACON_ROOTS_CREATION_CODE).unwrap(),
+        },
+        UpgradeDeposit {
+            intent: "Ecotone: Gas Price Oracle Set Ecotone",
+            from: SystemAccounts::default().attributes_depositor,
+            to: Some(address(GAS_PRICE_ORACLE_PROXY)),
+            gas: 80_000,
+            data: SET_ECOTONE_SELECTOR.to_vec(),
+        },
+    ]
+}
+
+/// The Fjord upgrade deposits: the GasPriceOracle implementation deployment, the proxy
+/// update to it and the GasPriceOracle activation
+pub(super) fn fjord_upgrade_deposits() -> Vec<UpgradeDeposit> {
+    let gas_price_oracle = get_contract_address(address(FJORD_GAS_PRICE_ORACLE_DEPLOYER), 0);
+
+    vec![
+        UpgradeDeposit {
+            intent: "Fjord: Gas Price Oracle Deployment",
+            from: address(FJORD_GAS_PRICE_ORACLE_DEPLOYER),
+            to: None,
+            gas: 1_450_000,
+            data: hex::decode(FJORD_GAS_PRICE_ORACLE_CREATION_CODE).unwrap(),
+        },
+        UpgradeDeposit {
+            intent: "Fjord: Gas Price Oracle Proxy Update",
+            from: Address::zero(),
+            to: Some(address(GAS_PRICE_ORACLE_PROXY)),
+            gas: 50_000,
+            data: upgrade_to(gas_price_oracle),
+        },
+        UpgradeDeposit {
+            intent: "Fjord: Gas Price Oracle Set Fjord",
+            from: SystemAccounts::default().attributes_depositor,
+            to: Some(address(GAS_PRICE_ORACLE_PROXY)),
+            gas: 90_000,
+            data: SET_FJORD_SELECTOR.to_vec(),
+        },
+    ]
+}
+
+/// Encodes a proxy `upgradeTo(implementation)` call
+fn upgrade_to(implementation: Address) -> Vec<u8> {
+    [
+        UPGRADE_TO_SELECTOR.as_slice(),
+        H256::from(implementation).as_bytes(),
+    ]
+    .concat()
+}
+
+fn address(address: &str) -> Address {
+    address.parse().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::H256;
+    use ethers::utils::get_contract_address;
+
+    use crate::config::ChainConfig;
+
+    use super::*;
+
+    fn hash(hash: &str) -> H256 {
+        hash.parse().unwrap()
+    }
+
+    #[test]
+    fn test_ecotone_upgrade_deposits() {
+        let deposits = ecotone_upgrade_deposits();
+        let source_hashes = deposits
+            .iter()
+            .map(UpgradeDeposit::source_hash)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            source_hashes,
+            vec![
+                hash("0x877a6077205782ea15a6dc8699fa5ebcec5e0f4389f09cb8eda09488231346f8"),
+                hash("0xa312b4510adf943510f05fcc8f15f86995a5066bd83ce11384688ae20e6ecf42"),
+                hash("0x18acb38c5ff1c238a7460ebc1b421fa49ec4874bdf1e0a530d234104e5e67dbc"),
+                hash("0xee4f9385eceef498af0be7ec5862229f426dec41c8d42397c7257a5117d9230a"),
+                hash("0x69b763c48478b9dc2f65ada09b3d92133ec592ea715ec65ad6e7f3dc519dc00c"),
+                hash("0x0c1cb38e99dbc9cbfab3bb80863380b0905290b37eb3d6ab18dc01c1f3e75f93"),
+            ]
+        );
+
+        assert_eq!(
+            get_contract_address(deposits[0].from, 0),
+            address("0x07dbe8500fc591d1852B76feE44d5a05e13097Ff")
+        );
+        assert_eq!(
+            get_contract_address(deposits[1].from, 0),
+            address("0xb528D11cC114E026F138fE568744c6D45ce6Da7A")
+        );
+        assert_eq!(
+            hex::encode(&deposits[2].data),
+            "3659cfe600000000000000000000000007dbe8500fc591d1852b76fee44d5a05e13097ff"
+        );
+        assert_eq!(
+            hex::encode(&deposits[3].data),
+            "3659cfe6000000000000000000000000b528d11cc114e026f138fe568744c6d45ce6da7a"
+        );
+        assert_eq!(
+            get_contract_address(deposits[4].from, 0),
+            address("0x000F3df6D732807Ef1319fB7B8bB8522d0Beac02")
+        );
+        assert_eq!(hex::encode(&deposits[5].data), "22b908b3");
+    }
+
+    #[test]
+    fn test_fjord_upgrade_deposits() {
+        let deposits = fjord_upgrade_deposits();
+        let source_hashes = deposits
+            .iter()
+            .map(UpgradeDeposit::source_hash)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            source_hashes,
+            vec![
+                hash("0x86122c533fdcb89b16d8713174625e44578a89751d96c098ec19ab40a51a8ea3"),
+                hash("0x1e6bb0c28bfab3dc9b36ffb0f721f00d6937f33577606325692db0965a7d58c6"),
+                hash("0xbac7bb0d5961cad209a345408b0280a0d4686b1b20665e1b0f9cdafd73b19b6b"),
+            ]
+        );
+
+        assert_eq!(
+            get_contract_address(deposits[0].from, 0),
+            address("0xa919894851548179A0750865e7974DA599C0Fac7")
+        );
+        assert_eq!(
+            hex::encode(&deposits[1].data),
+            "3659cfe6000000000000000000000000a919894851548179a0750865e7974da599c0fac7"
+        );
+        assert_eq!(hex::encode(&deposits[2].data), "8e98b106");
+    }
+
+    #[test]
+    fn test_upgrade_deposits_activation_block() {
+        let chain = ChainConfig::optimism();
+
+        assert!(
+            upgrade_deposits(&chain, chain.ecotone_time - chain.blocktime)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            upgrade_deposits(&chain, chain.ecotone_time + chain.blocktime)
+                .unwrap()
+                .is_empty()
+        );
+
+        // the implementation creation code is not bundled, so the activation blocks must not
+        // be derived with a partial set of upgrade deposits
+        assert!(upgrade_deposits(&chain, chain.ecotone_time).is_err());
+        assert!(upgrade_deposits(&chain, chain.fjord_time).is_err());
+    }
+}
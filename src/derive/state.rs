@@ -1,6 +1,7 @@
 use std::{collections::BTreeMap, sync::Arc};
 
 use ethers::types::H256;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     common::{BlockInfo, Epoch},
@@ -8,9 +9,15 @@ use crate::{
     l1::L1Info,
 };
 
+/// The hash of a speculatively derived L2 block, until the engine has executed it
+pub const UNCONFIRMED_HASH: H256 = H256::zero();
+
 pub struct State {
     l1_info: BTreeMap<H256, L1Info>,
     l1_hashes: BTreeMap<u64, H256>,
+    l2_refs: BTreeMap<u64, (BlockInfo, Epoch)>,
+    /// The hashes of the non-deposit transactions of L2 blocks by timestamp, if known
+    l2_transactions: BTreeMap<u64, Vec<H256>>,
     pub safe_head: BlockInfo,
     pub safe_epoch: Epoch,
     pub current_epoch_num: u64,
@@ -19,9 +26,14 @@ pub struct State {
 
 impl State {
     pub fn new(safe_head: BlockInfo, safe_epoch: Epoch, config: Arc<Config>) -> Self {
+        let mut l2_refs = BTreeMap::new();
+        l2_refs.insert(safe_head.timestamp, (safe_head, safe_epoch));
+
         Self {
             l1_info: BTreeMap::new(),
             l1_hashes: BTreeMap::new(),
+            l2_refs,
+            l2_transactions: BTreeMap::new(),
             safe_head,
             safe_epoch,
             current_epoch_num: 0,
@@ -55,6 +67,37 @@ impl State {
         })
     }
 
+    pub fn l2_info_by_timestamp(&self, timestamp: u64) -> Option<&(BlockInfo, Epoch)> {
+        self.l2_refs.get(&timestamp)
+    }
+
+    /// Records a derived L2 block and its origin, such as one fetched from the L2 node
+    pub fn set_l2_info(&mut self, block: BlockInfo, epoch: Epoch) {
+        self.l2_refs.insert(block.timestamp, (block, epoch));
+    }
+
+    /// Returns the hashes of the non-deposit transactions of the L2 block with the
+    /// given timestamp, if they are known
+    pub fn l2_transactions_by_timestamp(&self, timestamp: u64) -> Option<&[H256]> {
+        self.l2_transactions.get(&timestamp).map(Vec::as_slice)
+    }
+
+    /// Records the hashes of the non-deposit transactions of an L2 block, so span
+    /// batches overlapping with it can be checked
+    pub fn set_l2_transactions(&mut self, timestamp: u64, hashes: Vec<H256>) {
+        self.l2_transactions.insert(timestamp, hashes);
+    }
+
+    /// Returns the last executed L2 block whose L1 origin is at or before the given
+    /// L1 block, as far back as the L2 references go
+    pub fn l2_info_by_origin(&self, l1_block: u64) -> Option<(BlockInfo, Epoch)> {
+        self.l2_refs
+            .values()
+            .rev()
+            .find(|(block, epoch)| epoch.number <= l1_block && block.hash != UNCONFIRMED_HASH)
+            .copied()
+    }
+
     pub fn update_l1_info(&mut self, l1_info: L1Info) {
         self.current_epoch_num = l1_info.block_info.number;
 
@@ -69,16 +112,102 @@ impl State {
         self.current_epoch_num = 0;
         self.l1_info.clear();
         self.l1_hashes.clear();
+        self.l2_refs.clear();
+        self.l2_transactions.clear();
+        self.l2_refs
+            .insert(safe_head.timestamp, (safe_head, safe_epoch));
 
         self.safe_head = safe_head;
         self.safe_epoch = safe_epoch;
     }
 
     pub fn update_safe_head(&mut self, safe_head: BlockInfo, safe_epoch: Epoch) {
+        self.l2_refs
+            .insert(safe_head.timestamp, (safe_head, safe_epoch));
+
         self.safe_head = safe_head;
         self.safe_epoch = safe_epoch;
     }
 
+    /// Advances the safe head to a block derived from attributes that the engine has
+    /// not executed yet. Its hash stays [UNCONFIRMED_HASH] until it is confirmed.
+    pub fn speculate_safe_head(&mut self, timestamp: u64, safe_epoch: Epoch) {
+        let safe_head = BlockInfo {
+            hash: UNCONFIRMED_HASH,
+            number: self.safe_head.number + 1,
+            parent_hash: self.safe_head.hash,
+            timestamp,
+        };
+
+        self.update_safe_head(safe_head, safe_epoch);
+    }
+
+    /// Records a block executed by the engine. If it is the current safe head, the
+    /// safe head is replaced, which makes its hash known to derivation.
+    pub fn confirm_safe_head(&mut self, safe_head: BlockInfo, safe_epoch: Epoch) {
+        self.l2_refs
+            .insert(safe_head.timestamp, (safe_head, safe_epoch));
+
+        if self.safe_head.number == safe_head.number {
+            self.safe_head = safe_head;
+            self.safe_epoch = safe_epoch;
+        }
+    }
+
+    /// Returns true if the engine has executed the safe head
+    pub fn is_safe_head_confirmed(&self) -> bool {
+        self.safe_head.hash != UNCONFIRMED_HASH
+    }
+
+    /// Returns the L1 info and L2 references needed to resume derivation. The
+    /// batcher transactions are left out, as the pipeline has already read them.
+    pub fn snapshot(&self) -> StateSnapshot {
+        let l1_info = self
+            .l1_info
+            .values()
+            .map(|info| L1Info {
+                block_info: info.block_info.clone(),
+                system_config: info.system_config,
+                user_deposits: info.user_deposits.clone(),
+                batcher_transactions: Vec::new(),
+                finalized: info.finalized,
+            })
+            .collect();
+
+        StateSnapshot {
+            l1_info,
+            l2_refs: self.l2_refs.values().copied().collect(),
+            l2_transactions: self.l2_transactions.clone().into_iter().collect(),
+            safe_head: self.safe_head,
+            safe_epoch: self.safe_epoch,
+            current_epoch_num: self.current_epoch_num,
+        }
+    }
+
+    /// Replaces the state with a snapshot
+    pub fn restore(&mut self, snapshot: StateSnapshot) {
+        self.l1_hashes = snapshot
+            .l1_info
+            .iter()
+            .map(|info| (info.block_info.number, info.block_info.hash))
+            .collect();
+        self.l1_info = snapshot
+            .l1_info
+            .into_iter()
+            .map(|info| (info.block_info.hash, info))
+            .collect();
+        self.l2_refs = snapshot
+            .l2_refs
+            .into_iter()
+            .map(|(block, epoch)| (block.timestamp, (block, epoch)))
+            .collect();
+        self.l2_transactions = snapshot.l2_transactions.into_iter().collect();
+
+        self.safe_head = snapshot.safe_head;
+        self.safe_epoch = snapshot.safe_epoch;
+        self.current_epoch_num = snapshot.current_epoch_num;
+    }
+
     fn prune(&mut self) {
         let prune_until = self
             .safe_epoch
@@ -93,5 +222,39 @@ impl State {
             self.l1_info.remove(block_hash);
             self.l1_hashes.pop_first();
         }
+
+        while let Some((_, (_, epoch))) = self.l2_refs.first_key_value() {
+            if epoch.number >= prune_until {
+                break;
+            }
+
+            self.l2_refs.pop_first();
+        }
+
+        if let Some(first_timestamp) = self.l2_refs.keys().next() {
+            self.l2_transactions = self.l2_transactions.split_off(first_timestamp);
+        }
+    }
+}
+
+/// The serializable part of [State]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateSnapshot {
+    pub l1_info: Vec<L1Info>,
+    pub l2_refs: Vec<(BlockInfo, Epoch)>,
+    #[serde(default)]
+    pub l2_transactions: Vec<(u64, Vec<H256>)>,
+    pub safe_head: BlockInfo,
+    pub safe_epoch: Epoch,
+    pub current_epoch_num: u64,
+}
+
+impl StateSnapshot {
+    /// Returns the L1 info of the most recent L1 block pushed into the pipeline
+    pub fn l1_head(&self) -> Option<&L1Info> {
+        self.l1_info
+            .iter()
+            .find(|info| info.block_info.number == self.current_epoch_num)
     }
 }
@@ -0,0 +1,198 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use serde::{Serialize, Serializer};
+
+/// The number of derivation events kept in the trace
+const TRACE_CAPACITY: usize = 1024;
+
+lazy_static! {
+    /// The global derivation trace, shared by all pipeline stages and the RPC server
+    pub static ref DERIVATION_TRACE: DerivationTrace = DerivationTrace::new(TRACE_CAPACITY);
+}
+
+/// Records a derivation event in the global trace
+pub fn record(event: DerivationEvent) {
+    DERIVATION_TRACE.record(event);
+}
+
+/// The kind of data a derivation event concerns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventTarget {
+    BatcherTransaction,
+    Frame,
+    Channel,
+    Batch,
+}
+
+/// Why derivation dropped a batcher transaction, frame, channel or batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DropReason {
+    /// The batch timestamp does not follow the safe head
+    Timestamp,
+    /// The batch does not build on the safe head
+    ParentHash,
+    /// The batch was included after the sequencing window of its epoch
+    InclusionWindow,
+    /// The batch L1 origin is unknown, skipped, outdated or has the wrong hash
+    Origin,
+    /// The batch exceeds the max sequencer drift
+    Drift,
+    /// The span batch does not match the L2 blocks it overlaps with
+    Overlap,
+    /// The batch contains an invalid transaction
+    InvalidTransaction,
+    /// The data could not be decoded
+    Decode,
+    /// The frame conflicts with frames already in its channel
+    InvalidFrame,
+    /// The channel was not completed within the channel timeout
+    ChannelTimeout,
+    /// The channel was pruned to keep the channel bank within its max size
+    ChannelBankFull,
+}
+
+/// A dropped batcher transaction, frame, channel or batch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DerivationEvent {
+    pub target: EventTarget,
+    pub reason: DropReason,
+    /// The L1 block the dropped data was included in
+    pub l1_inclusion_block: u64,
+    /// The channel the dropped data belongs to, if known
+    #[serde(serialize_with = "serialize_channel_id")]
+    pub channel_id: Option<u128>,
+    /// Human readable details
+    pub message: String,
+}
+
+impl DerivationEvent {
+    pub fn new(
+        target: EventTarget,
+        reason: DropReason,
+        l1_inclusion_block: u64,
+        message: impl Into<String>,
+    ) -> Self {
+        Self {
+            target,
+            reason,
+            l1_inclusion_block,
+            channel_id: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn with_channel_id(mut self, channel_id: u128) -> Self {
+        self.channel_id = Some(channel_id);
+        self
+    }
+}
+
+/// A bounded ring buffer of the most recent derivation events
+#[derive(Debug)]
+pub struct DerivationTrace {
+    events: Mutex<VecDeque<DerivationEvent>>,
+    capacity: usize,
+}
+
+impl DerivationTrace {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            events: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    /// Logs the event as JSON and appends it to the trace, evicting the oldest
+    /// event once the trace is full
+    pub fn record(&self, event: DerivationEvent) {
+        let json = serde_json::to_string(&event).unwrap_or_default();
+        tracing::warn!(target: "derivation_trace", event = %json, "{}", event.message);
+
+        let mut events = self.events.lock().unwrap();
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+
+    /// Returns up to `limit` of the most recent events, oldest first
+    pub fn recent(&self, limit: usize) -> Vec<DerivationEvent> {
+        let events = self.events.lock().unwrap();
+        let skip = events.len().saturating_sub(limit);
+        events.iter().skip(skip).cloned().collect()
+    }
+}
+
+fn serialize_channel_id<S: Serializer>(
+    channel_id: &Option<u128>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match channel_id {
+        Some(id) => serializer.serialize_str(&format!("0x{:032x}", id)),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(l1_inclusion_block: u64) -> DerivationEvent {
+        DerivationEvent::new(
+            EventTarget::Batch,
+            DropReason::ParentHash,
+            l1_inclusion_block,
+            "invalid parent hash",
+        )
+    }
+
+    #[test]
+    fn test_trace_is_bounded() {
+        let trace = DerivationTrace::new(3);
+        for block in 0..5 {
+            trace.record(event(block));
+        }
+
+        let blocks = trace
+            .recent(10)
+            .iter()
+            .map(|e| e.l1_inclusion_block)
+            .collect::<Vec<_>>();
+        assert_eq!(blocks, vec![2, 3, 4]);
+
+        let blocks = trace
+            .recent(2)
+            .iter()
+            .map(|e| e.l1_inclusion_block)
+            .collect::<Vec<_>>();
+        assert_eq!(blocks, vec![3, 4]);
+    }
+
+    #[test]
+    fn test_event_json() {
+        let event = DerivationEvent::new(
+            EventTarget::Channel,
+            DropReason::ChannelTimeout,
+            100,
+            "channel timed out",
+        )
+        .with_channel_id(0xff);
+
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "target": "channel",
+                "reason": "channel_timeout",
+                "l1InclusionBlock": 100,
+                "channelId": "0x000000000000000000000000000000ff",
+                "message": "channel timed out",
+            })
+        );
+    }
+}
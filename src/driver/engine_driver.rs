@@ -5,6 +5,8 @@ use ethers::{
     utils::keccak256,
 };
 use eyre::Result;
+use serde::Serialize;
+use std::fmt::Debug;
 use std::sync::Arc;
 use thiserror::Error;
 use tokio::sync::RwLock;
@@ -13,7 +15,10 @@ use tokio::time::{sleep, Duration};
 use crate::{
     common::{BlockInfo, Epoch},
     config::Config,
-    engine::{Engine, EngineApi, ExecutionPayload, ForkchoiceState, PayloadAttributes, Status},
+    engine::{
+        Engine, EngineApi, EngineForks, ExecutionPayload, ForkchoiceState, PayloadAttributes,
+        Status,
+    },
 };
 
 use super::HeadInfo;
@@ -68,6 +73,19 @@ pub enum EngineDriverError {
     Other(#[from] eyre::Error),
 }
 
+impl EngineDriverError {
+    /// Returns true if the engine rejected the payload built from the attributes, as
+    /// opposed to failing to process it
+    pub fn is_invalid_payload(&self) -> bool {
+        matches!(self, Self::Other(err) if err.downcast_ref::<InvalidPayload>().is_some())
+    }
+}
+
+/// Returned when the engine marks a payload or its attributes as invalid
+#[derive(Debug, Error)]
+#[error("{0}")]
+pub struct InvalidPayload(pub String);
+
 /// Handles the given attributes.
 /// Functionally equivalent to [EngineDriver<E>::handle_attributes], but manages
 /// the engine driver RW lock by acquiring the write lock only as necessary.
@@ -182,7 +200,11 @@ async fn build_payload<E: Engine>(
     tracing::info!(
         "building payload: ts={} #txs={} epoch={}",
         attrs.timestamp,
-        attrs.transactions.unwrap_or_default().len(),
+        attrs
+            .transactions
+            .as_ref()
+            .map(Vec::len)
+            .unwrap_or_default(),
         new_epoch.number
     );
     // If we're including transaction from txpool, wait for the blocktime to pass.
@@ -194,7 +216,7 @@ async fn build_payload<E: Engine>(
     // Validate chain head consistency again (probably non-essential, but avoids unnecessary work).
     validate_head_consistency(&engine_driver, target, "finalize_payload".to_string())?;
     // Finalize payload building.
-    let new_head = engine_driver.finalize_payload_building(id).await?;
+    let new_head = engine_driver.finalize_payload_building(id, &attrs).await?;
     Ok((new_head, new_epoch))
 }
 
@@ -260,7 +282,15 @@ impl<E: Engine> EngineDriver<E> {
 
         if update.payload_status.status != Status::Valid {
             let err = update.payload_status.validation_error.unwrap_or_default();
-            eyre::bail!(format!("invalid payload attributes: {}", err));
+            let err = format!("invalid payload attributes: {}", err);
+            if matches!(
+                update.payload_status.status,
+                Status::Invalid | Status::InvalidBlockHash
+            ) {
+                return Err(InvalidPayload(err).into());
+            }
+
+            eyre::bail!(err);
         }
 
         update
@@ -270,8 +300,12 @@ impl<E: Engine> EngineDriver<E> {
 
     /// Finalizes the payload building process via Engine API calls to
     /// `get_payload` and `new_payload`. Returns the resulting new head.
-    pub async fn finalize_payload_building(&self, id: U64) -> Result<BlockInfo> {
-        let payload = self.engine.get_payload(id).await?;
+    pub async fn finalize_payload_building(
+        &self,
+        id: U64,
+        attributes: &PayloadAttributes,
+    ) -> Result<BlockInfo> {
+        let payload = self.get_payload(id, attributes).await?;
         tracing::info!(
             "built payload: block#={} hash={} ts={} #txs={}",
             payload.block_number,
@@ -311,11 +345,12 @@ impl<E: Engine> EngineDriver<E> {
         self.finalized_epoch = epoch;
     }
 
-    pub fn reorg(&mut self) {
-        self.unsafe_head = self.finalized_head;
-        self.unsafe_epoch = self.finalized_epoch;
-        self.safe_head = self.finalized_head;
-        self.safe_epoch = self.finalized_epoch;
+    /// Rolls the safe and unsafe heads back to a block at or after the finalized head
+    pub fn rollback(&mut self, head: BlockInfo, epoch: Epoch) {
+        self.unsafe_head = head;
+        self.unsafe_epoch = epoch;
+        self.safe_head = head;
+        self.safe_epoch = epoch;
     }
 
     pub async fn engine_ready(&self) -> bool {
@@ -383,14 +418,37 @@ impl<E: Engine> EngineDriver<E> {
     }
 
     async fn build_payload(&self, attributes: PayloadAttributes) -> Result<ExecutionPayload> {
-        let id = self.start_payload_building(attributes).await?;
-        self.engine.get_payload(id).await
+        let id = self.start_payload_building(attributes.clone()).await?;
+        self.get_payload(id, &attributes).await
+    }
+
+    /// Retrieves the payload built from `attributes`. The parent beacon block root is taken from
+    /// the attributes if the engine did not return it.
+    async fn get_payload(
+        &self,
+        id: U64,
+        attributes: &PayloadAttributes,
+    ) -> Result<ExecutionPayload> {
+        let mut payload = self
+            .engine
+            .get_payload(id, attributes.timestamp.as_u64())
+            .await?;
+
+        if payload.parent_beacon_block_root.is_none() {
+            payload.parent_beacon_block_root = attributes.parent_beacon_block_root;
+        }
+
+        Ok(payload)
     }
 
     pub async fn push_payload(&self, payload: ExecutionPayload) -> Result<()> {
         let status = self.engine.new_payload(payload).await?;
-        if status.status != Status::Valid && status.status != Status::Accepted {
-            eyre::bail!("invalid execution payload");
+        match status.status {
+            Status::Valid | Status::Accepted => (),
+            Status::Invalid | Status::InvalidBlockHash => {
+                return Err(InvalidPayload("invalid execution payload".to_string()).into())
+            }
+            Status::Syncing => eyre::bail!("engine is syncing"),
         }
 
         Ok(())
@@ -445,29 +503,116 @@ fn should_skip(block: &Block<Transaction>, attributes: &PayloadAttributes) -> Re
     tracing::debug!("block: {:?}", block);
     tracing::debug!("attributes: {:?}", attributes);
 
+    Ok(compare_attributes(block, attributes).is_empty())
+}
+
+/// A field of derived attributes that differs from an existing block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldMismatch {
+    /// The name of the field
+    pub field: String,
+    /// The value in the existing block
+    pub block: String,
+    /// The value in the derived attributes
+    pub attributes: String,
+}
+
+/// Compares the attributes field by field against an existing block, returning all
+/// fields that differ. Transactions are compared by hash.
+pub fn compare_attributes(
+    block: &Block<Transaction>,
+    attributes: &PayloadAttributes,
+) -> Vec<FieldMismatch> {
+    let mut mismatches = Vec::new();
+
     let attributes_hashes = attributes
         .transactions
-        .as_ref()
-        .unwrap()
         .iter()
+        .flatten()
         .map(|tx| H256(keccak256(&tx.0)))
         .collect::<Vec<_>>();
 
     let block_hashes = block
         .transactions
         .iter()
-        .map(|tx| tx.hash())
+        .map(|tx| tx.hash)
         .collect::<Vec<_>>();
 
     tracing::debug!("attribute hashes: {:?}", attributes_hashes);
 
-    let is_same = attributes_hashes == block_hashes
-        && attributes.timestamp.as_u64() == block.timestamp.as_u64()
-        && attributes.prev_randao == block.mix_hash.unwrap()
-        && attributes.suggested_fee_recipient == block.author.unwrap()
-        && attributes.gas_limit.as_u64() == block.gas_limit.as_u64();
+    compare_field(
+        &mut mismatches,
+        "transactions.len",
+        block_hashes.len(),
+        attributes_hashes.len(),
+    );
+
+    if let Some(index) = block_hashes
+        .iter()
+        .zip(&attributes_hashes)
+        .position(|(block_hash, attributes_hash)| block_hash != attributes_hash)
+    {
+        compare_field(
+            &mut mismatches,
+            &format!("transactions[{}]", index),
+            block_hashes[index],
+            attributes_hashes[index],
+        );
+    }
+
+    compare_field(
+        &mut mismatches,
+        "timestamp",
+        block.timestamp.as_u64(),
+        attributes.timestamp.as_u64(),
+    );
+    compare_field(
+        &mut mismatches,
+        "prev_randao",
+        block.mix_hash,
+        Some(attributes.prev_randao),
+    );
+    compare_field(
+        &mut mismatches,
+        "suggested_fee_recipient",
+        block.author,
+        Some(attributes.suggested_fee_recipient),
+    );
+    compare_field(
+        &mut mismatches,
+        "gas_limit",
+        block.gas_limit.as_u64(),
+        attributes.gas_limit.as_u64(),
+    );
+    compare_field(
+        &mut mismatches,
+        "withdrawals.len",
+        block.withdrawals.as_ref().map(Vec::len),
+        attributes.withdrawals.as_ref().map(Vec::len),
+    );
+    compare_field(
+        &mut mismatches,
+        "parent_beacon_block_root",
+        block.parent_beacon_block_root,
+        attributes.parent_beacon_block_root,
+    );
+
+    mismatches
+}
 
-    Ok(is_same)
+fn compare_field<T: PartialEq + Debug>(
+    mismatches: &mut Vec<FieldMismatch>,
+    field: &str,
+    block: T,
+    attributes: T,
+) {
+    if block != attributes {
+        mismatches.push(FieldMismatch {
+            field: field.to_string(),
+            block: format!("{:?}", block),
+            attributes: format!("{:?}", attributes),
+        });
+    }
 }
 
 impl EngineDriver<EngineApi> {
@@ -478,7 +623,10 @@ impl EngineDriver<EngineApi> {
         provider: Provider<Http>,
         config: &Arc<Config>,
     ) -> Result<Self> {
-        let engine = Arc::new(EngineApi::new(&config.l2_engine_url, &config.jwt_secret));
+        let engine = Arc::new(
+            EngineApi::new(&config.l2_engine_url, &config.jwt_secret)
+                .with_forks(EngineForks::from(&config.chain)),
+        );
 
         Ok(Self {
             engine,
@@ -493,3 +641,90 @@ impl EngineDriver<EngineApi> {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::{Address, Block, Transaction, H256, U256, U64};
+    use ethers::utils::keccak256;
+
+    use crate::{common::RawTransaction, engine::PayloadAttributes};
+
+    use super::{compare_attributes, should_skip, EngineDriverError, InvalidPayload};
+
+    fn attributes() -> PayloadAttributes {
+        PayloadAttributes {
+            timestamp: U64::from(1000),
+            prev_randao: H256::repeat_byte(1),
+            suggested_fee_recipient: Address::repeat_byte(2),
+            transactions: Some(vec![RawTransaction(vec![0x7E, 0x01])]),
+            no_tx_pool: true,
+            gas_limit: U64::from(30_000_000),
+            withdrawals: None,
+            parent_beacon_block_root: None,
+            epoch: None,
+            l1_inclusion_block: None,
+            seq_number: None,
+        }
+    }
+
+    fn block(attributes: &PayloadAttributes) -> Block<Transaction> {
+        let transactions = attributes
+            .transactions
+            .iter()
+            .flatten()
+            .map(|tx| Transaction {
+                hash: H256(keccak256(&tx.0)),
+                ..Default::default()
+            })
+            .collect();
+
+        Block {
+            timestamp: U256::from(attributes.timestamp.as_u64()),
+            mix_hash: Some(attributes.prev_randao),
+            author: Some(attributes.suggested_fee_recipient),
+            gas_limit: U256::from(attributes.gas_limit.as_u64()),
+            transactions,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_matching_attributes() {
+        let attributes = attributes();
+        let block = block(&attributes);
+
+        assert!(compare_attributes(&block, &attributes).is_empty());
+        assert!(should_skip(&block, &attributes).unwrap());
+    }
+
+    #[test]
+    fn test_mismatching_attributes() {
+        let attributes = attributes();
+        let mut block = block(&attributes);
+        block.gas_limit = U256::from(1);
+        block.transactions[0].hash = H256::zero();
+        block.parent_beacon_block_root = Some(H256::repeat_byte(3));
+
+        let fields = compare_attributes(&block, &attributes)
+            .into_iter()
+            .map(|mismatch| mismatch.field)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            fields,
+            vec!["transactions[0]", "gas_limit", "parent_beacon_block_root"]
+        );
+        assert!(!should_skip(&block, &attributes).unwrap());
+    }
+
+    #[test]
+    fn test_invalid_payload_error() {
+        let rejected = EngineDriverError::from(eyre::Report::new(InvalidPayload(
+            "invalid execution payload".to_string(),
+        )));
+        assert!(rejected.is_invalid_payload());
+
+        let failed = EngineDriverError::from(eyre::eyre!("connection refused"));
+        assert!(!failed.is_invalid_payload());
+    }
+}
@@ -60,7 +60,6 @@ impl HeadInfoQuery {
 mod test_utils {
     use super::*;
     use crate::common::{BlockInfo, Epoch};
-    use crate::config::{ChainConfig, Config};
     use ethers::types::H256;
     use std::str::FromStr;
 
@@ -127,21 +126,6 @@ mod test_utils {
         serde_json::from_str(raw_block).ok()
     }
 
-    pub fn optimism_config() -> Config {
-        Config {
-            l1_rpc_url: Default::default(),
-            l2_rpc_url: Default::default(),
-            l2_engine_url: Default::default(),
-            chain: ChainConfig::optimism(),
-            jwt_secret: Default::default(),
-            checkpoint_sync_url: Default::default(),
-            rpc_port: Default::default(),
-            devnet: false,
-            local_sequencer: Default::default(),
-            watcher_delay: Default::default(),
-        }
-    }
-
     #[async_trait::async_trait]
     impl InnerProvider for MockProvider {
         async fn get_block_with_txs(
@@ -160,7 +144,7 @@ mod tests {
     #[tokio::test]
     async fn test_get_head_info_fails() {
         let provider = test_utils::mock_provider(None);
-        let config = test_utils::optimism_config();
+        let config = Config::default_for_tests();
         let head_info =
             HeadInfoQuery::get_head_info(&provider, &config, BlockNumber::Finalized).await;
         assert_eq!(test_utils::default_head_info(), head_info);
@@ -169,7 +153,7 @@ mod tests {
     #[tokio::test]
     async fn test_get_head_info_empty_block() {
         let provider = test_utils::mock_provider(Some(Block::default()));
-        let config = test_utils::optimism_config();
+        let config = Config::default_for_tests();
         let head_info =
             HeadInfoQuery::get_head_info(&provider, &config, BlockNumber::Finalized).await;
         assert_eq!(test_utils::default_head_info(), head_info);
@@ -178,7 +162,7 @@ mod tests {
     #[tokio::test]
     async fn test_get_head_info_valid_block() {
         let provider = test_utils::mock_provider(test_utils::valid_block());
-        let config = test_utils::optimism_config();
+        let config = Config::default_for_tests();
         let head_info =
             HeadInfoQuery::get_head_info(&provider, &config, BlockNumber::Finalized).await;
         assert_eq!(test_utils::default_head_info(), head_info);
@@ -1,17 +1,19 @@
 use std::{
     process,
-    sync::{mpsc::Receiver, Arc, RwLock},
+    sync::{Arc, RwLock},
     time::Duration,
 };
 
 use ethers::{
-    providers::{Http, Provider},
+    providers::{Http, Middleware, Provider},
     types::{Address, BlockNumber},
 };
 use eyre::Result;
 use reqwest::Url;
 use tokio::{
+    select,
     sync::{
+        mpsc::{self, UnboundedReceiver},
         watch::{self, Sender},
         RwLock as TokioRwLock,
     },
@@ -19,11 +21,16 @@ use tokio::{
 };
 
 use crate::{
-    common::{BlockInfo, Epoch},
+    common::{BlockInfo, Epoch, RawTransaction},
     config::Config,
-    derive::{async_iterator::AsyncIterator, state::State, Pipeline},
-    engine::{Engine, EngineApi, ExecutionPayload},
-    l1::{BlockUpdate, ChainWatcher},
+    derive::{
+        snapshot::PipelineSnapshot, speculative::SpeculativePipeline, state::State, Pipeline,
+    },
+    engine::{Engine, EngineApi, ExecutionPayload, PayloadAttributes},
+    l1::{
+        provider::generate_l1_provider, system_config::initial_system_config, BlockUpdate,
+        ChainWatcher,
+    },
     network::{handlers::block_handler::BlockHandler, service::Service},
     rpc, specular,
     telemetry::metrics,
@@ -35,13 +42,21 @@ pub mod engine_driver;
 mod info;
 pub mod sequencing;
 mod types;
+pub mod verifier;
 pub use types::*;
 
+/// The maximum number of attributes the pipeline derives ahead of the engine
+const MAX_QUEUED_ATTRIBUTES: usize = 64;
+
+/// The maximum number of consecutive derivation restarts from the same safe head
+/// before the driver gives up
+const MAX_ATTRIBUTES_RESETS: u32 = 8;
+
 /// Driver is responsible for advancing the execution node by feeding
 /// the derived chain into the engine API
 pub struct Driver<E: Engine> {
-    /// The derivation pipeline
-    pipeline: Pipeline,
+    /// The derivation pipeline, running ahead of the engine
+    pipeline: SpeculativePipeline,
     /// The engine driver
     pub engine_driver: Arc<TokioRwLock<EngineDriver<E>>>,
     /// List of unfinalized L2 blocks with their epochs, L1 inclusions, and sequence numbers
@@ -57,13 +72,19 @@ pub struct Driver<E: Engine> {
     /// Channel to receive the shutdown signal from
     shutdown_recv: watch::Receiver<bool>,
     /// Channel to receive unsafe block from
-    unsafe_block_recv: Receiver<ExecutionPayload>,
+    unsafe_block_recv: UnboundedReceiver<ExecutionPayload>,
+    /// Channel to receive the L1 blocks the pipeline must be reset to before
+    reset_request_recv: UnboundedReceiver<u64>,
     /// Channel to send unsafe signer updated to block handler
     unsafe_block_signer_sender: Sender<Address>,
     /// Networking service
     network_service: Option<Service>,
     /// Channel timeout length
     channel_timeout: u64,
+    /// Timestamp of the holocene hardfork, whose derivation rules are not implemented
+    holocene_time: u64,
+    /// Number of consecutive derivation restarts after failing to apply attributes
+    attributes_resets: u32,
 }
 
 impl Driver<EngineApi> {
@@ -75,31 +96,21 @@ impl Driver<EngineApi> {
         let http = Http::new_with_client(Url::parse(&config.l2_rpc_url)?, client);
         let provider = Provider::new(http);
 
-        // TODO: cleanup
-        macro_rules! get_head_info {
-            ($bn:expr, $fb:expr) => {
-                if config.chain.meta.enable_deposited_txs {
-                    info::HeadInfoQuery::get_head_info(
-                        &info::HeadInfoFetcher::from(&provider),
-                        &config,
-                        $bn,
-                    )
-                    .await
-                } else {
-                    specular::info::HeadInfoQuery::get_head_info(
-                        &specular::info::HeadInfoFetcher::from(&provider),
-                        &config,
-                        $bn,
-                        $fb,
-                    )
-                    .await
-                }
-            };
-        }
-
-        let finalized_head = get_head_info!(BlockNumber::Finalized, None);
-        let safe_head = get_head_info!(BlockNumber::Safe, Some(finalized_head.clone()));
-        let latest_head = get_head_info!(BlockNumber::Latest, Some(safe_head.clone()));
+        let finalized_head = get_head_info(&provider, &config, BlockNumber::Finalized, None).await;
+        let safe_head = get_head_info(
+            &provider,
+            &config,
+            BlockNumber::Safe,
+            Some(finalized_head.clone()),
+        )
+        .await;
+        let latest_head = get_head_info(
+            &provider,
+            &config,
+            BlockNumber::Latest,
+            Some(safe_head.clone()),
+        )
+        .await;
 
         tracing::info!(
             "starting from fc: finalized {:?}, safe {:?}, latest {:?}",
@@ -112,12 +123,40 @@ impl Driver<EngineApi> {
         let finalized_epoch = finalized_head.l1_epoch;
         let finalized_seq = finalized_head.sequence_number;
 
-        let l1_start_block =
-            get_l1_start_block(finalized_epoch.number, config.chain.channel_timeout);
-
+        let snapshot = load_snapshot(&config, &safe_head).await;
         let config = Arc::new(config);
-        let chain_watcher =
-            ChainWatcher::new(l1_start_block, finalized_l2_block.number, config.clone())?;
+
+        let (chain_watcher, system_config) = match snapshot.as_ref().and_then(|s| s.state.l1_head())
+        {
+            Some(l1_head) => {
+                tracing::info!(
+                    "resuming from pipeline snapshot at L1 block {}",
+                    l1_head.block_info.number
+                );
+
+                let l1_block = l1_head.block_info.number;
+                let chain_watcher = ChainWatcher::new(
+                    l1_block + 1,
+                    safe_head.l2_block_info.number,
+                    config.clone(),
+                )?
+                .with_system_config(l1_block, l1_head.system_config);
+
+                (chain_watcher, l1_head.system_config)
+            }
+            None => {
+                let l1_start_block =
+                    get_l1_start_block(finalized_epoch.number, config.chain.channel_timeout);
+                let system_config =
+                    initial_system_config(&config, l1_start_block, finalized_l2_block.number).await;
+
+                let chain_watcher =
+                    ChainWatcher::new(l1_start_block, finalized_l2_block.number, config.clone())?
+                        .with_system_config(l1_start_block, system_config);
+
+                (chain_watcher, system_config)
+            }
+        };
 
         let state = Arc::new(RwLock::new(State::new(
             safe_head.l2_block_info,
@@ -127,12 +166,24 @@ impl Driver<EngineApi> {
 
         let engine_driver =
             EngineDriver::new(finalized_head, safe_head, latest_head, provider, &config)?;
-        let pipeline = Pipeline::new(state.clone(), config.clone(), finalized_seq)?;
+        let mut pipeline = Pipeline::new(state.clone(), config.clone(), finalized_seq)?;
+        if let Some(snapshot) = snapshot {
+            pipeline.restore(snapshot)?;
+        }
+
+        let (reset_request_sender, reset_request_recv) = mpsc::unbounded_channel();
+        let pipeline = SpeculativePipeline::spawn(
+            pipeline,
+            state.clone(),
+            MAX_QUEUED_ATTRIBUTES,
+            config.pipeline_snapshot.clone(),
+            reset_request_sender,
+        );
 
         let _addr = rpc::run_server(config.clone()).await?;
 
         let (unsafe_block_signer_sender, unsafe_block_signer_recv) =
-            watch::channel(config.chain.system_config.unsafe_block_signer);
+            watch::channel(system_config.unsafe_block_signer);
 
         let (block_handler, unsafe_block_recv) =
             BlockHandler::new(config.chain.l2_chain_id, unsafe_block_signer_recv);
@@ -152,15 +203,38 @@ impl Driver<EngineApi> {
             chain_watcher,
             shutdown_recv,
             unsafe_block_recv,
+            reset_request_recv,
             unsafe_block_signer_sender,
             network_service: Some(service),
             channel_timeout: config.chain.channel_timeout,
+            holocene_time: config.chain.holocene_time,
+            attributes_resets: 0,
         })
     }
 }
 
+/// An event that wakes up the driver
+enum DriverEvent {
+    /// An update from the L1 chain watcher
+    BlockUpdate(BlockUpdate),
+    /// Attributes derived by the pipeline
+    Attributes(Box<PayloadAttributes>),
+    /// An unsafe block received on the p2p network
+    UnsafePayload(Box<ExecutionPayload>),
+    /// The blocks derived from this L1 block on are invalid
+    ResetRequest(u64),
+    /// The pipeline could not derive the attributes of a batch
+    DerivationHalted(eyre::Report),
+    /// The shutdown signal changed
+    Shutdown,
+    /// Every event source has closed
+    Closed,
+}
+
 impl<E: Engine> Driver<E> {
-    /// Runs the Driver
+    /// Runs the Driver. Between events the driver is idle: it waits on the L1
+    /// updates, the derived attributes, the unsafe blocks and the shutdown signal
+    /// together.
     pub async fn start(&mut self) -> Result<()> {
         tracing::trace!("starting chain watcher...");
         self.chain_watcher.start()?;
@@ -168,8 +242,6 @@ impl<E: Engine> Driver<E> {
         self.await_engine_ready().await;
         self.engine_driver.read().await.update_forkchoice().await?;
         loop {
-            self.check_shutdown().await;
-
             if let Err(err) = self.advance().await {
                 tracing::error!("fatal error: {:?}", err);
                 self.shutdown().await;
@@ -196,163 +268,252 @@ impl<E: Engine> Driver<E> {
         }
     }
 
-    /// Attempts to advance the execution node forward using either L1 info our
-    /// blocks received on the p2p network.
+    /// Waits for the next event and attempts to advance the execution node forward
+    /// using either L1 info or blocks received on the p2p network.
     async fn advance(&mut self) -> Result<()> {
-        // TODO: `await_engine_ready` was moved from `start` to here.
-        // This is a hack, due to possible lock contention bug (to be reverted).
-        self.await_engine_ready().await;
-        self.advance_safe_head().await?;
-        self.advance_unsafe_head().await?;
-        self.update_finalized().await;
+        match self.next_event().await {
+            DriverEvent::BlockUpdate(update) => {
+                // TODO: `await_engine_ready` was moved from `start` to here.
+                // This is a hack, due to possible lock contention bug (to be reverted).
+                self.await_engine_ready().await;
+                self.handle_block_update(update).await?;
+                self.update_finalized().await;
+            }
+            DriverEvent::Attributes(attributes) => {
+                self.await_engine_ready().await;
+                self.advance_safe_head(*attributes).await?;
+                self.update_finalized().await;
+            }
+            DriverEvent::UnsafePayload(payload) => {
+                self.await_engine_ready().await;
+                self.future_unsafe_blocks.push(*payload);
+                self.advance_unsafe_head().await?;
+            }
+            DriverEvent::ResetRequest(l1_block) => {
+                tracing::warn!("blocks derived from L1 block {} are invalid", l1_block);
+                self.await_engine_ready().await;
+                self.rollback(l1_block.checked_sub(1)).await?;
+            }
+            DriverEvent::DerivationHalted(err) => {
+                return Err(err.wrap_err("derivation halted"));
+            }
+            DriverEvent::Shutdown => self.check_shutdown().await,
+            DriverEvent::Closed => eyre::bail!("all driver event sources closed"),
+        }
+
         self.update_metrics().await;
         self.try_start_networking()?;
 
         Ok(())
     }
 
-    /// Attempts to advance the execution node forward one L1 block using derived
-    /// L1 data. Errors if the most recent PayloadAttributes from the pipeline
-    /// does not successfully advance the node
-    async fn advance_safe_head(&mut self) -> Result<()> {
-        self.handle_next_block_update().await?;
-        self.update_state_head().await?;
-
-        while let Some(next_attributes) = self.pipeline.next().await {
-            let l1_inclusion_block = next_attributes
-                .l1_inclusion_block
-                .ok_or(eyre::eyre!("attributes without inclusion block"))?;
-
-            let seq_number = next_attributes
-                .seq_number
-                .ok_or(eyre::eyre!("attributes without seq number"))?;
-
-            handle_attributes(
-                next_attributes,
-                &ChainHeadType::Safe,
-                self.engine_driver.clone(),
-            )
-            .await?;
+    /// Waits until an L1 update, derived attributes, an unsafe block or the shutdown
+    /// signal arrives. Sources that have closed are skipped.
+    async fn next_event(&mut self) -> DriverEvent {
+        select! {
+            Some(update) = self.chain_watcher.recv_from_channel() => DriverEvent::BlockUpdate(update),
+            Some(attributes) = self.pipeline.recv() => match attributes {
+                Ok(attributes) => DriverEvent::Attributes(Box::new(attributes)),
+                Err(err) => DriverEvent::DerivationHalted(err),
+            },
+            Some(payload) = self.unsafe_block_recv.recv() => DriverEvent::UnsafePayload(Box::new(payload)),
+            Some(l1_block) = self.reset_request_recv.recv() => DriverEvent::ResetRequest(l1_block),
+            Ok(()) = self.shutdown_recv.changed() => DriverEvent::Shutdown,
+            else => DriverEvent::Closed,
+        }
+    }
 
-            let engine_driver = self.engine_driver.read().await;
-            tracing::trace!(
-                "safe head updated: {} {}",
-                engine_driver.safe_head.number,
-                engine_driver.safe_head.hash,
+    /// Advances the execution node with attributes derived by the pipeline and
+    /// confirms the new safe head to the pipeline. If the engine rejects them, the
+    /// batch is dropped and only the deposits are applied; a rejected deposit-only
+    /// block is fatal. On any other failure the queued attributes are invalidated
+    /// and derivation restarts from the current safe head, a bounded number of
+    /// times in a row.
+    async fn advance_safe_head(&mut self, attributes: PayloadAttributes) -> Result<()> {
+        let l1_inclusion_block = attributes
+            .l1_inclusion_block
+            .ok_or(eyre::eyre!("attributes without inclusion block"))?;
+
+        let seq_number = attributes
+            .seq_number
+            .ok_or(eyre::eyre!("attributes without seq number"))?;
+
+        if attributes.timestamp.as_u64() >= self.holocene_time {
+            eyre::bail!(
+                "refusing to derive past holocene at {}: the holocene derivation rules are not supported",
+                self.holocene_time
             );
+        }
 
-            let new_safe_head = engine_driver.safe_head;
-            let new_safe_epoch = engine_driver.safe_epoch;
+        let res = match handle_attributes(
+            attributes.clone(),
+            &ChainHeadType::Safe,
+            self.engine_driver.clone(),
+        )
+        .await
+        {
+            Err(err) if err.is_invalid_payload() && !is_deposit_only(&attributes) => {
+                tracing::warn!("dropping the batch, engine rejected the block: {}", err);
+
+                let timestamp = attributes.timestamp.as_u64();
+                let res = handle_attributes(
+                    deposit_only(attributes),
+                    &ChainHeadType::Safe,
+                    self.engine_driver.clone(),
+                )
+                .await;
+
+                if res.is_ok() {
+                    self.state
+                        .write()
+                        .map_err(|_| eyre::eyre!("lock poisoned"))?
+                        .set_l2_transactions(timestamp, Vec::new());
+                }
 
-            self.state
-                .write()
-                .map_err(|_| eyre::eyre!("lock poisoned"))?
-                .update_safe_head(new_safe_head, new_safe_epoch);
-            let unfinalized_entry = (
-                new_safe_head,
-                new_safe_epoch,
-                l1_inclusion_block,
-                seq_number,
+                res
+            }
+            res => res,
+        };
+
+        let engine_driver = self.engine_driver.read().await;
+        let new_safe_head = engine_driver.safe_head;
+        let new_safe_epoch = engine_driver.safe_epoch;
+        drop(engine_driver);
+
+        if let Err(err) = res {
+            if err.is_invalid_payload() {
+                eyre::bail!("engine rejected a deposit-only block: {}", err);
+            }
+
+            self.attributes_resets += 1;
+            if self.attributes_resets > MAX_ATTRIBUTES_RESETS {
+                eyre::bail!(
+                    "failed to apply derived attributes {} times in a row: {}",
+                    self.attributes_resets,
+                    err
+                );
+            }
+
+            tracing::error!("failed to apply derived attributes: {}", err);
+            tracing::warn!(
+                "restarting derivation from safe head {}",
+                new_safe_head.number
             );
 
-            self.unfinalized_blocks.push(unfinalized_entry);
+            let l1_start_block = get_l1_start_block(new_safe_epoch.number, self.channel_timeout);
+            self.chain_watcher
+                .restart(l1_start_block, new_safe_head.number)?;
+            self.pipeline.reset(new_safe_head, new_safe_epoch)?;
+
+            return Ok(());
+        }
+
+        self.attributes_resets = 0;
+
+        tracing::trace!(
+            "safe head updated: {} {}",
+            new_safe_head.number,
+            new_safe_head.hash,
+        );
+
+        self.pipeline.confirm(new_safe_head, new_safe_epoch)?;
+        let unfinalized_entry = (
+            new_safe_head,
+            new_safe_epoch,
+            l1_inclusion_block,
+            seq_number,
+        );
+
+        self.unfinalized_blocks.push(unfinalized_entry);
+
+        if self.pipeline.queued() == 0 {
+            self.pipeline.snapshot()?;
         }
 
         Ok(())
     }
 
+    /// Applies the buffered unsafe blocks that extend the unsafe head, in order
     async fn advance_unsafe_head(&mut self) -> Result<()> {
-        while let Ok(payload) = self.unsafe_block_recv.try_recv() {
-            self.future_unsafe_blocks.push(payload);
-        }
+        loop {
+            let engine_driver = self.engine_driver.read().await;
+            self.future_unsafe_blocks.retain(|payload| {
+                let unsafe_block_num = payload.block_number.as_u64();
+                let synced_block_num = engine_driver.unsafe_head.number;
 
-        let engine_driver = self.engine_driver.read().await;
-        self.future_unsafe_blocks.retain(|payload| {
-            let unsafe_block_num = payload.block_number.as_u64();
-            let synced_block_num = engine_driver.unsafe_head.number;
+                unsafe_block_num > synced_block_num && unsafe_block_num - synced_block_num < 1024
+            });
 
-            unsafe_block_num > synced_block_num && unsafe_block_num - synced_block_num < 1024
-        });
+            let next_unsafe_payload = self
+                .future_unsafe_blocks
+                .iter()
+                .find(|p| p.parent_hash == engine_driver.unsafe_head.hash);
 
-        let next_unsafe_payload = self
-            .future_unsafe_blocks
-            .iter()
-            .find(|p| p.parent_hash == engine_driver.unsafe_head.hash);
+            let Some(payload) = next_unsafe_payload else {
+                return Ok(());
+            };
 
-        if let Some(payload) = next_unsafe_payload {
             engine_driver.push_payload(payload.clone()).await?;
             drop(engine_driver);
             // TODO: update epoch of unsafe head.
             self.engine_driver.write().await.unsafe_head = payload.into();
             self.engine_driver.read().await.update_forkchoice().await?;
         }
-
-        Ok(())
-    }
-
-    async fn update_state_head(&self) -> Result<()> {
-        let engine_driver = self.engine_driver.read().await;
-        let mut state = self
-            .state
-            .write()
-            .map_err(|_| eyre::eyre!("lock poisoned"))?;
-        state.update_safe_head(engine_driver.safe_head, engine_driver.safe_epoch);
-
-        Ok(())
     }
 
-    /// Ingests the next update from the block update channel
-    async fn handle_next_block_update(&mut self) -> Result<()> {
-        let next = self.chain_watcher.try_recv_from_channel();
+    /// Ingests an update from the block update channel
+    async fn handle_block_update(&mut self, update: BlockUpdate) -> Result<()> {
+        match update {
+            BlockUpdate::NewBlock(l1_info) => {
+                let num = l1_info.block_info.number;
 
-        if let Ok(update) = next {
-            match update {
-                BlockUpdate::NewBlock(l1_info) => {
-                    let num = l1_info.block_info.number;
+                self.unsafe_block_signer_sender
+                    .send(l1_info.system_config.unsafe_block_signer)?;
 
-                    self.unsafe_block_signer_sender
-                        .send(l1_info.system_config.unsafe_block_signer)?;
+                tracing::info!(
+                    "pushing into pipeline: l1_block#={} #batcher_txs={}",
+                    num,
+                    l1_info.batcher_transactions.len()
+                );
 
-                    tracing::info!(
-                        "pushing into pipeline: l1_block#={} #batcher_txs={}",
-                        num,
-                        l1_info.batcher_transactions.len()
-                    );
-                    self.pipeline
-                        .push_batcher_transactions(l1_info.batcher_transactions.clone(), num)?;
-
-                    self.state
-                        .write()
-                        .map_err(|_| eyre::eyre!("lock poisoned"))?
-                        .update_l1_info(*l1_info);
-                }
-                BlockUpdate::Reorg => {
-                    tracing::warn!("reorg detected, purging pipeline");
+                self.pipeline.push_l1_block(l1_info)?;
+                self.pipeline.snapshot()?;
+            }
+            BlockUpdate::Reorg(ancestor) => {
+                tracing::warn!("reorg detected");
+                self.rollback(ancestor.map(|ancestor| ancestor.number))
+                    .await?;
+            }
+            BlockUpdate::FinalityUpdate(num) => {
+                self.finalized_l1_block_number = num;
+            }
+        }
 
-                    self.unfinalized_blocks.clear();
+        Ok(())
+    }
 
-                    let mut engine_driver = self.engine_driver.write().await;
-                    let l1_start_block = get_l1_start_block(
-                        engine_driver.finalized_epoch.number,
-                        self.channel_timeout,
-                    );
+    /// Rolls back to the last L2 block derived entirely from L1 blocks up to the given
+    /// one, or to the finalized head if there is none, and restarts derivation from it
+    async fn rollback(&mut self, l1_block: Option<u64>) -> Result<()> {
+        let mut engine_driver = self.engine_driver.write().await;
+        let (head, epoch) = l1_block
+            .and_then(|l1_block| rollback_target(&self.unfinalized_blocks, l1_block))
+            .unwrap_or((engine_driver.finalized_head, engine_driver.finalized_epoch));
+
+        tracing::warn!(
+            "rolling back to L2 block {} with L1 origin {}",
+            head.number,
+            epoch.number
+        );
 
-                    self.chain_watcher
-                        .restart(l1_start_block, engine_driver.finalized_head.number)?;
+        self.unfinalized_blocks
+            .retain(|(block, _, _, _)| block.number <= head.number);
 
-                    self.state
-                        .write()
-                        .map_err(|_| eyre::eyre!("lock poisoned"))?
-                        .purge(engine_driver.finalized_head, engine_driver.finalized_epoch);
+        let l1_start_block = get_l1_start_block(epoch.number, self.channel_timeout);
+        self.chain_watcher.restart(l1_start_block, head.number)?;
 
-                    self.pipeline.purge().await?;
-                    engine_driver.reorg();
-                }
-                BlockUpdate::FinalityUpdate(num) => {
-                    self.finalized_l1_block_number = num;
-                }
-            }
-        }
+        self.pipeline.reset(head, epoch)?;
+        engine_driver.rollback(head, epoch);
 
         Ok(())
     }
@@ -361,10 +522,9 @@ impl<E: Engine> Driver<E> {
         let new_finalized = self
             .unfinalized_blocks
             .iter()
-            .filter(|(_, _, inclusion, seq)| {
+            .rfind(|(_, _, inclusion, seq)| {
                 *inclusion <= self.finalized_l1_block_number && *seq == 0
-            })
-            .last();
+            });
 
         if let Some((head, epoch, _, _)) = new_finalized {
             tracing::info!("updating finalized head: {:?}", head.number);
@@ -393,6 +553,7 @@ impl<E: Engine> Driver<E> {
         metrics::FINALIZED_HEAD.set(engine_driver.finalized_head.number as i64);
         metrics::SAFE_HEAD.set(engine_driver.safe_head.number as i64);
         metrics::SYNCED.set(self.synced() as i64);
+        metrics::QUEUED_ATTRIBUTES.set(self.pipeline.queued() as i64);
     }
 
     fn synced(&self) -> bool {
@@ -400,6 +561,108 @@ impl<E: Engine> Driver<E> {
     }
 }
 
+/// Retrieves the head info of the L2 block, using the specular head info query for
+/// chains without deposited transactions
+async fn get_head_info(
+    provider: &Provider<Http>,
+    config: &Config,
+    block_number: BlockNumber,
+    fallback: Option<HeadInfo>,
+) -> HeadInfo {
+    if config.chain.meta.enable_deposited_txs {
+        info::HeadInfoQuery::get_head_info(
+            &info::HeadInfoFetcher::from(provider),
+            config,
+            block_number,
+        )
+        .await
+    } else {
+        specular::info::HeadInfoQuery::get_head_info(
+            &specular::info::HeadInfoFetcher::from(provider),
+            config,
+            block_number,
+            fallback,
+        )
+        .await
+    }
+}
+
+/// Loads the pipeline snapshot, if one is configured. The snapshot is only used if
+/// it was taken at the current safe head and its last L1 block is still canonical.
+async fn load_snapshot(config: &Config, safe_head: &HeadInfo) -> Option<PipelineSnapshot> {
+    let path = config.pipeline_snapshot.as_ref()?;
+    let snapshot = match PipelineSnapshot::load(path) {
+        Ok(snapshot) => snapshot?,
+        Err(err) => {
+            tracing::warn!("failed to read pipeline snapshot {:?}: {}", path, err);
+            return None;
+        }
+    };
+
+    if snapshot.state.safe_head != safe_head.l2_block_info {
+        tracing::info!(
+            "discarding pipeline snapshot taken at safe head {}",
+            snapshot.state.safe_head.number
+        );
+        return None;
+    }
+
+    let l1_head = snapshot.state.l1_head()?.block_info.clone();
+    if config.l1_fixture.is_none() {
+        let provider = generate_l1_provider(config);
+        let canonical = match provider.get_block(l1_head.number).await {
+            Ok(block) => block.and_then(|block| block.hash) == Some(l1_head.hash),
+            Err(err) => {
+                tracing::warn!("failed to check the pipeline snapshot L1 block: {}", err);
+                false
+            }
+        };
+
+        if !canonical {
+            tracing::info!(
+                "discarding pipeline snapshot at reorged L1 block {}",
+                l1_head.number
+            );
+            return None;
+        }
+    }
+
+    Some(snapshot)
+}
+
+/// Returns the last unfinalized L2 block whose L1 origin and batch inclusion block
+/// are both at or before the given L1 block, so it survives a reorg of later blocks
+fn rollback_target(
+    unfinalized_blocks: &[(BlockInfo, Epoch, u64, u64)],
+    l1_block: u64,
+) -> Option<(BlockInfo, Epoch)> {
+    unfinalized_blocks
+        .iter()
+        .rfind(|(_, epoch, inclusion, _)| epoch.number <= l1_block && *inclusion <= l1_block)
+        .map(|(block, epoch, _, _)| (*block, *epoch))
+}
+
+/// Returns true if the attributes carry no transactions besides deposits
+fn is_deposit_only(attributes: &PayloadAttributes) -> bool {
+    attributes
+        .transactions
+        .iter()
+        .flatten()
+        .all(RawTransaction::is_deposit)
+}
+
+/// Strips the batch transactions from the attributes, keeping only the deposits
+fn deposit_only(attributes: PayloadAttributes) -> PayloadAttributes {
+    let transactions = attributes
+        .transactions
+        .map(|txs| txs.into_iter().filter(RawTransaction::is_deposit).collect());
+
+    PayloadAttributes {
+        transactions,
+        ..attributes
+    }
+}
+
 /// Retrieves the L1 start block number.
 /// If an overflow occurs during subtraction, the function returns the genesis block #0.
 fn get_l1_start_block(epoch_number: u64, channel_timeout: u64) -> u64 {
@@ -429,6 +692,10 @@ mod tests {
             let l2_rpc = std::env::var("L2_TEST_RPC_URL")?;
             let cli_config = CliConfig {
                 l1_rpc_url: Some(rpc.to_owned()),
+                l1_fallback_rpc_urls: Vec::new(),
+                l1_rpc_quorum: None,
+                l1_beacon_url: None,
+                l1_ws_url: None,
                 l2_rpc_url: Some(l2_rpc.to_owned()),
                 l2_engine_url: None,
                 jwt_secret: Some(
@@ -439,6 +706,9 @@ mod tests {
                 devnet: false,
                 local_sequencer: None,
                 watcher_delay: None,
+                l1_prefetch_window: None,
+                l1_fixture: None,
+                pipeline_snapshot: None,
             };
             let config = Config::new(&config_path, cli_config, ChainConfig::optimism_goerli());
             let (_shutdown_sender, shutdown_recv) = channel(false);
@@ -456,4 +726,44 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test]
+    fn test_rollback_target() {
+        // L2 blocks with their epoch, inclusion block and sequence number
+        let unfinalized_blocks = [(1, 100, 101), (2, 100, 102), (3, 101, 102), (4, 101, 104)].map(
+            |(number, epoch, inclusion)| {
+                let block = BlockInfo {
+                    number,
+                    ..Default::default()
+                };
+                let epoch = Epoch {
+                    number: epoch,
+                    ..Default::default()
+                };
+                (block, epoch, inclusion, number - 1)
+            },
+        );
+
+        let target = |l1_block| rollback_target(&unfinalized_blocks, l1_block);
+
+        assert_eq!(target(104).unwrap().0.number, 4);
+        assert_eq!(target(103).unwrap().0.number, 3);
+        assert_eq!(target(101).unwrap().0.number, 1);
+        assert_eq!(target(100), None);
+    }
+
+    #[test]
+    fn test_deposit_only() {
+        let deposit = RawTransaction(vec![0x7E, 0x01]);
+        let batch_tx = RawTransaction(vec![0x02, 0x01]);
+        let attributes = PayloadAttributes {
+            transactions: Some(vec![deposit.clone(), batch_tx]),
+            ..Default::default()
+        };
+        assert!(!is_deposit_only(&attributes));
+
+        let attributes = deposit_only(attributes);
+        assert_eq!(attributes.transactions, Some(vec![deposit]));
+        assert!(is_deposit_only(&attributes));
+    }
 }
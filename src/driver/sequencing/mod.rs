@@ -10,7 +10,7 @@ use crate::{
     common::{BlockInfo, Epoch},
     derive::state::State,
     engine::PayloadAttributes,
-    l1::{utils::get_l1_block_info, L1BlockInfo},
+    l1::{block_cache::L1_BLOCK_CACHE, L1BlockInfo},
 };
 
 pub mod driver;
@@ -70,16 +70,18 @@ impl<T: SequencingPolicy, U: JsonRpcClient> SequencingSource for Source<T, U> {
                     .map(|i| i.block_info.clone()),
             )
         };
-        // Get l1 epoch info from provider if it doesn't exist in state.
-        // TODO: consider using caching e.g. with the cached crate.
+        // Get l1 epoch info from the L1 block cache or provider if it doesn't exist in state.
         let (parent_l1_epoch, next_l1_epoch) = join!(
             match parent_l1_epoch {
                 Some(info) => Either::Left(async { Ok(info) }),
-                None => Either::Right(get_l1_block_info(parent_epoch.hash, &self.provider)),
+                None =>
+                    Either::Right(L1_BLOCK_CACHE.get_or_fetch(parent_epoch.hash, &self.provider)),
             },
             match next_l1_epoch {
                 Some(info) => Either::Left(async { Ok(info) }),
-                None => Either::Right(get_l1_block_info(parent_epoch.number + 1, &self.provider)),
+                None => Either::Right(
+                    L1_BLOCK_CACHE.get_or_fetch(parent_epoch.number + 1, &self.provider)
+                ),
             },
         );
         // TODO: handle recoverable errors, if any.
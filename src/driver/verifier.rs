@@ -0,0 +1,309 @@
+use std::{
+    process,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use ethers::{
+    providers::{Http, Middleware, Provider},
+    types::{Block, BlockNumber, Transaction, H256},
+};
+use eyre::Result;
+use reqwest::Url;
+use serde::Serialize;
+use tokio::{sync::watch, time::sleep};
+
+use crate::{
+    common::{BlockInfo, Epoch},
+    config::Config,
+    derive::{async_iterator::AsyncIterator, state::State, Pipeline},
+    engine::PayloadAttributes,
+    l1::{alt_da::ExpiredChallenge, BlockUpdate, ChainWatcher},
+    rpc,
+    telemetry::metrics,
+};
+
+use super::{
+    engine_driver::{compare_attributes, FieldMismatch},
+    get_head_info, get_l1_start_block, HeadInfo,
+};
+
+/// Verifier derives the L2 chain from L1 and checks it against the blocks of an
+/// existing L2 node. Unlike the [Driver](super::Driver) it never calls the engine
+/// API, so it can run next to a node that is driven by another rollup node.
+pub struct Verifier {
+    /// The derivation pipeline
+    pipeline: Pipeline,
+    /// State struct to keep track of global state
+    state: Arc<RwLock<State>>,
+    /// L1 chain watcher
+    chain_watcher: ChainWatcher,
+    /// Provider for the L2 node that is verified
+    provider: Provider<Http>,
+    /// Channel to receive the shutdown signal from
+    shutdown_recv: watch::Receiver<bool>,
+    /// Channel timeout length
+    channel_timeout: u64,
+    config: Arc<Config>,
+}
+
+/// A block of the L2 node that differs from the derived attributes
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MismatchReport {
+    pub block_number: u64,
+    pub block_hash: H256,
+    /// The L1 origin of the derived attributes
+    pub derived_epoch: Epoch,
+    /// The L1 block the derived attributes were included in
+    pub l1_inclusion_block: u64,
+    pub mismatches: Vec<FieldMismatch>,
+}
+
+impl Verifier {
+    pub async fn from_config(config: Config, shutdown_recv: watch::Receiver<bool>) -> Result<Self> {
+        let client = reqwest::ClientBuilder::new()
+            .timeout(Duration::from_secs(5))
+            .build()?;
+
+        let http = Http::new_with_client(Url::parse(&config.l2_rpc_url)?, client);
+        let provider = Provider::new(http);
+
+        let finalized_head = get_head_info(&provider, &config, BlockNumber::Finalized, None).await;
+
+        tracing::info!(
+            "verifying from finalized block {} {:?}",
+            finalized_head.l2_block_info.number,
+            finalized_head.l2_block_info.hash
+        );
+
+        let l1_start_block =
+            get_l1_start_block(finalized_head.l1_epoch.number, config.chain.channel_timeout);
+
+        let config = Arc::new(config);
+        let chain_watcher = ChainWatcher::new(
+            l1_start_block,
+            finalized_head.l2_block_info.number,
+            config.clone(),
+        )?;
+
+        let state = Arc::new(RwLock::new(State::new(
+            finalized_head.l2_block_info,
+            finalized_head.l1_epoch,
+            config.clone(),
+        )));
+
+        let pipeline = Pipeline::new(
+            state.clone(),
+            config.clone(),
+            finalized_head.sequence_number,
+        )?;
+
+        let _addr = rpc::run_server(config.clone()).await?;
+
+        Ok(Self {
+            pipeline,
+            state,
+            chain_watcher,
+            provider,
+            shutdown_recv,
+            channel_timeout: config.chain.channel_timeout,
+            config,
+        })
+    }
+
+    /// Runs the Verifier
+    pub async fn start(&mut self) -> Result<()> {
+        self.chain_watcher.start()?;
+
+        loop {
+            self.check_shutdown();
+
+            if let Err(err) = self.advance().await {
+                tracing::error!("fatal error: {:?}", err);
+                process::exit(1);
+            }
+        }
+    }
+
+    fn check_shutdown(&self) {
+        if *self.shutdown_recv.borrow() {
+            process::exit(0);
+        }
+    }
+
+    /// Ingests the next L1 block and verifies all attributes derived from it
+    async fn advance(&mut self) -> Result<()> {
+        self.handle_next_block_update().await?;
+
+        while let Some(attributes) = self.pipeline.next().await {
+            self.verify(attributes).await?;
+        }
+
+        match self.pipeline.take_critical_error() {
+            Some(err) => Err(err.wrap_err("derivation halted")),
+            None => Ok(()),
+        }
+    }
+
+    async fn handle_next_block_update(&mut self) -> Result<()> {
+        let update = match self.chain_watcher.try_recv_from_channel() {
+            Ok(update) => update,
+            Err(_) => {
+                sleep(Duration::from_millis(250)).await;
+                return Ok(());
+            }
+        };
+
+        match update {
+            BlockUpdate::NewBlock(l1_info) => {
+                while let Err(err) = self.pipeline.push_l1_block(&l1_info).await {
+                    if let Some(expired) = err.downcast_ref::<ExpiredChallenge>() {
+                        tracing::warn!("{}, verifying again from the finalized head", expired);
+                        let finalized = get_head_info(
+                            &self.provider,
+                            &self.config,
+                            BlockNumber::Finalized,
+                            None,
+                        )
+                        .await;
+
+                        return self
+                            .reset(finalized.l2_block_info, finalized.l1_epoch)
+                            .await;
+                    }
+
+                    tracing::warn!("failed to read batcher data, retrying: {}", err);
+                    self.check_shutdown();
+                    sleep(Duration::from_secs(1)).await;
+                }
+
+                self.state
+                    .write()
+                    .map_err(|_| eyre::eyre!("lock poisoned"))?
+                    .update_l1_info(*l1_info);
+            }
+            BlockUpdate::Reorg(ancestor) => {
+                let (safe_head, safe_epoch) = {
+                    let state = self
+                        .state
+                        .read()
+                        .map_err(|_| eyre::eyre!("lock poisoned"))?;
+
+                    ancestor
+                        .and_then(|ancestor| state.l2_info_by_origin(ancestor.number))
+                        .unwrap_or((state.safe_head, state.safe_epoch))
+                };
+
+                tracing::warn!("reorg detected");
+                self.reset(safe_head, safe_epoch).await?;
+            }
+            BlockUpdate::FinalityUpdate(_) => (),
+        }
+
+        Ok(())
+    }
+
+    /// Restarts derivation and verification from the given safe head
+    async fn reset(&mut self, safe_head: BlockInfo, safe_epoch: Epoch) -> Result<()> {
+        tracing::warn!("verifying again from L2 block {}", safe_head.number);
+
+        let l1_start_block = get_l1_start_block(safe_epoch.number, self.channel_timeout);
+        self.chain_watcher
+            .restart(l1_start_block, safe_head.number)?;
+
+        self.state
+            .write()
+            .map_err(|_| eyre::eyre!("lock poisoned"))?
+            .purge(safe_head, safe_epoch);
+
+        self.pipeline.purge().await
+    }
+
+    /// Compares the attributes against the block of the L2 node at the same height,
+    /// then continues derivation on top of that block
+    async fn verify(&mut self, attributes: PayloadAttributes) -> Result<()> {
+        let safe_head = self
+            .state
+            .read()
+            .map_err(|_| eyre::eyre!("lock poisoned"))?
+            .safe_head;
+
+        let block = self.wait_for_block(safe_head.number + 1).await?;
+        let mismatches = compare_attributes(&block, &attributes);
+
+        // batches hold no deposits, so they are left out for the span batch checks
+        let batch_transactions = block
+            .transactions
+            .iter()
+            .filter(|tx| tx.transaction_type != Some(0x7E.into()))
+            .map(|tx| tx.hash)
+            .collect();
+
+        let derived_epoch = attributes
+            .epoch
+            .ok_or(eyre::eyre!("attributes without epoch"))?;
+
+        let head_info = if mismatches.is_empty() {
+            HeadInfo {
+                l2_block_info: BlockInfo::try_from(block)?,
+                l1_epoch: derived_epoch,
+                sequence_number: attributes.seq_number.unwrap_or_default(),
+            }
+        } else {
+            let report = MismatchReport {
+                block_number: safe_head.number + 1,
+                block_hash: block.hash.unwrap_or_default(),
+                derived_epoch,
+                l1_inclusion_block: attributes.l1_inclusion_block.unwrap_or_default(),
+                mismatches,
+            };
+
+            tracing::error!(
+                target: "verifier",
+                report = %serde_json::to_string(&report)?,
+                "block {} does not match the derived attributes",
+                report.block_number
+            );
+            metrics::VERIFIER_MISMATCHES.inc();
+
+            // keep following the chain of the L2 node, so later blocks are checked
+            // against the origin the node actually used
+            HeadInfo::try_from(block.clone()).unwrap_or(HeadInfo {
+                l2_block_info: BlockInfo::try_from(block)?,
+                l1_epoch: derived_epoch,
+                sequence_number: attributes.seq_number.unwrap_or_default(),
+            })
+        };
+
+        tracing::info!(
+            "verified block {} {:?}",
+            head_info.l2_block_info.number,
+            head_info.l2_block_info.hash
+        );
+        metrics::VERIFIED_HEAD.set(head_info.l2_block_info.number as i64);
+
+        let mut state = self
+            .state
+            .write()
+            .map_err(|_| eyre::eyre!("lock poisoned"))?;
+        state.update_safe_head(head_info.l2_block_info, head_info.l1_epoch);
+        state.set_l2_transactions(head_info.l2_block_info.timestamp, batch_transactions);
+
+        Ok(())
+    }
+
+    /// Waits until the L2 node has the block with the given number
+    async fn wait_for_block(&self, number: u64) -> Result<Block<Transaction>> {
+        loop {
+            match self.provider.get_block_with_txs(number).await {
+                Ok(Some(block)) => return Ok(block),
+                Ok(None) => tracing::debug!("waiting for the L2 node to reach block {}", number),
+                Err(err) => tracing::warn!("failed to fetch L2 block {}: {}", number, err),
+            }
+
+            self.check_shutdown();
+            sleep(Duration::from_secs(1)).await;
+        }
+    }
+}
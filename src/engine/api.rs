@@ -10,12 +10,17 @@ use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use ethers::types::H256;
+
+use crate::config::ChainConfig;
 use crate::engine::DEFAULT_AUTH_PORT;
-use crate::engine::ENGINE_GET_PAYLOAD_V1;
+use crate::engine::{ENGINE_GET_PAYLOAD_V1, ENGINE_GET_PAYLOAD_V2, ENGINE_GET_PAYLOAD_V3};
 
 use super::{
     Engine, ExecutionPayload, ForkChoiceUpdate, ForkchoiceState, JwtSecret, PayloadAttributes,
-    PayloadId, PayloadStatus, ENGINE_FORKCHOICE_UPDATED_V1, ENGINE_NEW_PAYLOAD_V1,
+    PayloadId, PayloadStatus, ENGINE_FORKCHOICE_UPDATED_V1, ENGINE_FORKCHOICE_UPDATED_V2,
+    ENGINE_FORKCHOICE_UPDATED_V3, ENGINE_NEW_PAYLOAD_V1, ENGINE_NEW_PAYLOAD_V2,
+    ENGINE_NEW_PAYLOAD_V3,
 };
 
 use super::{JSONRPC_VERSION, STATIC_ID};
@@ -31,6 +36,59 @@ pub struct EngineApi {
     pub client: Option<Client>,
     /// A [crate::engine::JwtSecret] used to authenticate with the engine api
     secret: JwtSecret,
+    /// Hardfork activation times used to select the method versions
+    pub forks: EngineForks,
+}
+
+/// Engine API method version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EngineApiVersion {
+    /// Bellatrix methods, used before Canyon
+    V1,
+    /// Shanghai methods with withdrawals, used from Canyon on
+    V2,
+    /// Cancun methods with blob gas and the parent beacon block root, used from Ecotone on
+    V3,
+}
+
+/// The hardfork activation times which determine the Engine API method versions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EngineForks {
+    /// Canyon activation timestamp
+    pub canyon_time: u64,
+    /// Ecotone activation timestamp
+    pub ecotone_time: u64,
+}
+
+impl Default for EngineForks {
+    fn default() -> Self {
+        Self {
+            canyon_time: u64::MAX,
+            ecotone_time: u64::MAX,
+        }
+    }
+}
+
+impl From<&ChainConfig> for EngineForks {
+    fn from(chain: &ChainConfig) -> Self {
+        Self {
+            canyon_time: chain.canyon_time,
+            ecotone_time: chain.ecotone_time,
+        }
+    }
+}
+
+impl EngineForks {
+    /// Returns the method version to use for a payload with the given timestamp
+    pub fn version(&self, timestamp: u64) -> EngineApiVersion {
+        if timestamp >= self.ecotone_time {
+            EngineApiVersion::V3
+        } else if timestamp >= self.canyon_time {
+            EngineApiVersion::V2
+        } else {
+            EngineApiVersion::V1
+        }
+    }
 }
 
 impl EngineApi {
@@ -65,9 +123,16 @@ impl EngineApi {
             port,
             client: Some(client),
             secret,
+            forks: EngineForks::default(),
         }
     }
 
+    /// Sets the hardfork activation times used to select the method versions
+    pub fn with_forks(mut self, forks: EngineForks) -> Self {
+        self.forks = forks;
+        self
+    }
+
     /// Constructs the base engine api url for the given address
     pub fn auth_url_from_addr(addr: &str, port: Option<u16>) -> String {
         let stripped = addr.strip_prefix("http://").unwrap_or(addr);
@@ -193,6 +258,17 @@ pub struct EngineApiResponse<P> {
     error: Option<EngineApiErrorPayload>,
 }
 
+/// The `engine_getPayloadV2` and `engine_getPayloadV3` response
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExecutionPayloadEnvelope {
+    /// The built payload
+    execution_payload: ExecutionPayload,
+    /// The parent beacon block root the payload was built with (Ecotone)
+    #[serde(default)]
+    parent_beacon_block_root: Option<H256>,
+}
+
 /// Engine API error payload
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct EngineApiErrorPayload {
@@ -211,28 +287,81 @@ impl Engine for EngineApi {
         forkchoice_state: ForkchoiceState,
         payload_attributes: Option<PayloadAttributes>,
     ) -> Result<ForkChoiceUpdate> {
+        // without attributes no payload is built, so V1 is accepted at any timestamp
+        let version = payload_attributes
+            .as_ref()
+            .map(|attributes| self.forks.version(attributes.timestamp.as_u64()))
+            .unwrap_or(EngineApiVersion::V1);
+
+        let method = match version {
+            EngineApiVersion::V1 => ENGINE_FORKCHOICE_UPDATED_V1,
+            EngineApiVersion::V2 => ENGINE_FORKCHOICE_UPDATED_V2,
+            EngineApiVersion::V3 => ENGINE_FORKCHOICE_UPDATED_V3,
+        };
+
         let payload_attributes_param = match payload_attributes {
             Some(payload_attributes) => serde_json::to_value(payload_attributes)?,
             None => Value::Null,
         };
         let forkchoice_state_param = serde_json::to_value(forkchoice_state)?;
         let params = vec![forkchoice_state_param, payload_attributes_param];
-        let res = self.post(ENGINE_FORKCHOICE_UPDATED_V1, params).await?;
+        let res = self.post(method, params).await?;
         Ok(res)
     }
 
     async fn new_payload(&self, execution_payload: ExecutionPayload) -> Result<PayloadStatus> {
-        let params = vec![serde_json::to_value(execution_payload)?];
-        let res = self.post(ENGINE_NEW_PAYLOAD_V1, params).await?;
+        let version = self.forks.version(execution_payload.timestamp.as_u64());
+
+        let res = match version {
+            EngineApiVersion::V1 => {
+                let params = vec![serde_json::to_value(execution_payload)?];
+                self.post(ENGINE_NEW_PAYLOAD_V1, params).await?
+            }
+            EngineApiVersion::V2 => {
+                let params = vec![serde_json::to_value(execution_payload)?];
+                self.post(ENGINE_NEW_PAYLOAD_V2, params).await?
+            }
+            EngineApiVersion::V3 => {
+                let parent_beacon_block_root = execution_payload
+                    .parent_beacon_block_root
+                    .ok_or(eyre::eyre!("payload missing parent beacon block root"))?;
+
+                // L2 blocks never carry blob transactions
+                let params = vec![
+                    serde_json::to_value(execution_payload)?,
+                    Value::Array(vec![]),
+                    serde_json::to_value(parent_beacon_block_root)?,
+                ];
+                self.post(ENGINE_NEW_PAYLOAD_V3, params).await?
+            }
+        };
+
         Ok(res)
     }
 
-    async fn get_payload(&self, payload_id: PayloadId) -> Result<ExecutionPayload> {
+    async fn get_payload(&self, payload_id: PayloadId, timestamp: u64) -> Result<ExecutionPayload> {
         let encoded = format!("{:x}", payload_id);
         let padded = format!("0x{:0>16}", encoded);
         let params = vec![Value::String(padded)];
-        let res = self.post(ENGINE_GET_PAYLOAD_V1, params).await?;
-        Ok(res)
+
+        let payload = match self.forks.version(timestamp) {
+            EngineApiVersion::V1 => self.post(ENGINE_GET_PAYLOAD_V1, params).await?,
+            EngineApiVersion::V2 => {
+                let envelope: ExecutionPayloadEnvelope =
+                    self.post(ENGINE_GET_PAYLOAD_V2, params).await?;
+                envelope.execution_payload
+            }
+            EngineApiVersion::V3 => {
+                let envelope: ExecutionPayloadEnvelope =
+                    self.post(ENGINE_GET_PAYLOAD_V3, params).await?;
+                ExecutionPayload {
+                    parent_beacon_block_root: envelope.parent_beacon_block_root,
+                    ..envelope.execution_payload
+                }
+            }
+        };
+
+        Ok(payload)
     }
 }
 
@@ -291,4 +420,45 @@ mod tests {
         // server.stop().unwrap();
         // server.stopped().await;
     }
+
+    #[test]
+    fn test_engine_api_version() {
+        let forks = EngineForks {
+            canyon_time: 10,
+            ecotone_time: 20,
+        };
+
+        assert_eq!(forks.version(9), EngineApiVersion::V1);
+        assert_eq!(forks.version(10), EngineApiVersion::V2);
+        assert_eq!(forks.version(19), EngineApiVersion::V2);
+        assert_eq!(forks.version(20), EngineApiVersion::V3);
+        assert_eq!(
+            EngineForks::default().version(u64::MAX - 1),
+            EngineApiVersion::V1
+        );
+    }
+
+    #[test]
+    fn test_execution_payload_envelope() {
+        let root = H256::repeat_byte(7);
+        let payload = ExecutionPayload {
+            timestamp: 20.into(),
+            withdrawals: Some(Vec::new()),
+            blob_gas_used: Some(0.into()),
+            excess_blob_gas: Some(0.into()),
+            ..Default::default()
+        };
+
+        let response = serde_json::json!({
+            "executionPayload": payload,
+            "blockValue": "0x0",
+            "blobsBundle": { "commitments": [], "proofs": [], "blobs": [] },
+            "shouldOverrideBuilder": false,
+            "parentBeaconBlockRoot": root,
+        });
+
+        let envelope: ExecutionPayloadEnvelope = serde_json::from_value(response).unwrap();
+        assert_eq!(envelope.execution_payload, payload);
+        assert_eq!(envelope.parent_beacon_block_root, Some(root));
+    }
 }
@@ -37,7 +37,11 @@ impl Engine for MockEngine {
         Ok(self.new_payload_res.clone())
     }
 
-    async fn get_payload(&self, _payload_id: PayloadId) -> Result<ExecutionPayload> {
+    async fn get_payload(
+        &self,
+        _payload_id: PayloadId,
+        _timestamp: u64,
+    ) -> Result<ExecutionPayload> {
         Ok(self.get_payload_res.clone())
     }
 }
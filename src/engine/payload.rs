@@ -1,4 +1,4 @@
-use ethers::types::{Block, Bytes, Transaction, H160, H256, U64};
+use ethers::types::{Block, Bytes, Transaction, Withdrawal, H160, H256, U64};
 use eyre::Result;
 use serde::{Deserialize, Serialize};
 
@@ -39,6 +39,20 @@ pub struct ExecutionPayload {
     pub block_hash: H256,
     /// An array of transaction objects where each object is a byte list
     pub transactions: Vec<RawTransaction>,
+    /// An array of withdrawals, always empty on L2. Required from Canyon on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub withdrawals: Option<Vec<Withdrawal>>,
+    /// A 64 bit value for the blob gas used. Required from Ecotone on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blob_gas_used: Option<U64>,
+    /// A 64 bit value for the excess blob gas. Required from Ecotone on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub excess_blob_gas: Option<U64>,
+    /// The 32 byte parent beacon block root. This value is not part of the payload object, it is
+    /// passed as a separate parameter to `engine_newPayloadV3` and is skipped during
+    /// serialization and deserialization.
+    #[serde(skip)]
+    pub parent_beacon_block_root: Option<H256>,
 }
 
 impl TryFrom<Block<Transaction>> for ExecutionPayload {
@@ -71,6 +85,10 @@ impl TryFrom<Block<Transaction>> for ExecutionPayload {
                 .into(),
             block_hash: value.hash.unwrap(),
             transactions: encoded_txs,
+            withdrawals: value.withdrawals,
+            blob_gas_used: value.blob_gas_used.map(|gas| gas.as_u64().into()),
+            excess_blob_gas: value.excess_blob_gas.map(|gas| gas.as_u64().into()),
+            parent_beacon_block_root: value.parent_beacon_block_root,
         })
     }
 }
@@ -97,6 +115,13 @@ pub struct PayloadAttributes {
     /// This field overrides the gas limit used during block-building.
     /// If not specified as rollup, a STATUS_INVALID is returned.
     pub gas_limit: U64,
+    /// Array of withdrawals, always empty on L2. Must be set from Canyon on and omitted before.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub withdrawals: Option<Vec<Withdrawal>>,
+    /// The parent beacon block root, taken from the L1 origin. Must be set from Ecotone on and
+    /// omitted before.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_beacon_block_root: Option<H256>,
     /// The batch epoch number from derivation. This value is not expected by the engine is skipped
     /// during serialization and deserialization.
     #[serde(skip)]
@@ -152,11 +177,73 @@ mod tests {
 
     use ethers::{
         providers::{Http, Middleware, Provider},
-        types::H256,
+        types::{Block, Transaction, H256},
     };
     use eyre::Result;
+    use serde_json::json;
+
+    use crate::engine::{ExecutionPayload, PayloadAttributes};
+
+    #[test]
+    fn test_execution_payload_fork_fields() -> Result<()> {
+        let block: Block<Transaction> = serde_json::from_value(json!({
+            "hash": H256::repeat_byte(1),
+            "parentHash": H256::repeat_byte(2),
+            "sha3Uncles": H256::zero(),
+            "miner": "0x4200000000000000000000000000000000000011",
+            "stateRoot": H256::zero(),
+            "transactionsRoot": H256::zero(),
+            "receiptsRoot": H256::zero(),
+            "logsBloom": format!("0x{}", "00".repeat(256)),
+            "difficulty": "0x0",
+            "number": "0x10",
+            "gasLimit": "0x1c9c380",
+            "gasUsed": "0x0",
+            "timestamp": "0x65f23e01",
+            "extraData": "0x",
+            "mixHash": H256::repeat_byte(3),
+            "nonce": "0x0000000000000000",
+            "baseFeePerGas": "0x1",
+            "withdrawalsRoot": H256::zero(),
+            "withdrawals": [],
+            "blobGasUsed": "0x0",
+            "excessBlobGas": "0x0",
+            "parentBeaconBlockRoot": H256::repeat_byte(4),
+            "uncles": [],
+            "transactions": [],
+        }))?;
+
+        let payload = ExecutionPayload::try_from(block)?;
+        assert_eq!(payload.withdrawals, Some(Vec::new()));
+        assert_eq!(payload.blob_gas_used, Some(0.into()));
+        assert_eq!(payload.excess_blob_gas, Some(0.into()));
+        assert_eq!(payload.parent_beacon_block_root, Some(H256::repeat_byte(4)));
+
+        let value = serde_json::to_value(&payload)?;
+        assert_eq!(value["withdrawals"], json!([]));
+        assert_eq!(value["blobGasUsed"], json!("0x0"));
+        assert!(value.get("parentBeaconBlockRoot").is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_payload_attributes_fork_fields() -> Result<()> {
+        let value = serde_json::to_value(PayloadAttributes::default())?;
+        assert!(value.get("withdrawals").is_none());
+        assert!(value.get("parentBeaconBlockRoot").is_none());
 
-    use crate::engine::ExecutionPayload;
+        let attributes = PayloadAttributes {
+            withdrawals: Some(Vec::new()),
+            parent_beacon_block_root: Some(H256::repeat_byte(4)),
+            ..Default::default()
+        };
+        let value = serde_json::to_value(attributes)?;
+        assert_eq!(value["withdrawals"], json!([]));
+        assert_eq!(value["parentBeaconBlockRoot"], json!(H256::repeat_byte(4)));
+
+        Ok(())
+    }
 
     #[tokio::test]
     async fn test_from_block_hash_to_execution_paylaod() -> Result<()> {
@@ -19,9 +19,12 @@ pub trait Engine: Send + Sync + 'static {
     /// for L2. This updates which L2 blocks the engine considers to be canonical ([ForkchoiceState] argument),
     /// and optionally initiates block production ([PayloadAttributes] argument).
     ///
+    /// The method version is selected by the [PayloadAttributes] timestamp: V2 from Canyon on,
+    /// which adds withdrawals, and V3 from Ecotone on, which adds the parent beacon block root.
+    ///
     /// ### Specification
     ///
-    /// method: engine_forkchoiceUpdatedV1
+    /// method: engine_forkchoiceUpdatedV1, engine_forkchoiceUpdatedV2, engine_forkchoiceUpdatedV3
     /// params:
     /// - [ForkchoiceState]
     /// - [PayloadAttributes]
@@ -45,11 +48,16 @@ pub trait Engine: Send + Sync + 'static {
     /// No modifications to [`engine_newPayloadV1`](https://github.com/ethereum/execution-apis/blob/main/src/engine/paris.md#engine_newpayloadv1)
     /// were made for L2. Applies a L2 block to the engine state.
     ///
+    /// The method version is selected by the [ExecutionPayload] timestamp. V3 additionally takes
+    /// an empty list of blob versioned hashes and the payload's parent beacon block root.
+    ///
     /// ### Specification
     ///
-    /// method: engine_newPayloadV1
+    /// method: engine_newPayloadV1, engine_newPayloadV2, engine_newPayloadV3
     /// params:
     /// - [ExecutionPayload]
+    /// - expected blob versioned hashes (V3 only)
+    /// - parent beacon block root (V3 only)
     /// timeout: 8s
     /// returns:
     /// - [PayloadStatus]
@@ -67,9 +75,12 @@ pub trait Engine: Send + Sync + 'static {
     /// were made for L2. Retrieves a payload by ID, prepared by [engine_forkchoiceUpdatedV1](EngineApi::engine_forkchoiceUpdatedV1)
     /// when called with [PayloadAttributes].
     ///
+    /// The method version is selected by the `timestamp` of the payload being built. The V2 and V3
+    /// responses wrap the payload in an envelope, which for V3 also carries the parent beacon block root.
+    ///
     /// ### Specification
     ///
-    /// method: engine_getPayloadV1
+    /// method: engine_getPayloadV1, engine_getPayloadV2, engine_getPayloadV3
     /// params:
     /// - [PayloadId]: DATA, 8 Bytes - Identifier of the payload build process
     /// timeout: 1s
@@ -81,5 +92,5 @@ pub trait Engine: Send + Sync + 'static {
     /// ### Reference
     ///
     /// See more details in the [Optimism Specs](https://github.com/ethereum-optimism/optimism/blob/develop/specs/exec-engine.md#engine_getPayloadv1).
-    async fn get_payload(&self, payload_id: PayloadId) -> Result<ExecutionPayload>;
+    async fn get_payload(&self, payload_id: PayloadId, timestamp: u64) -> Result<ExecutionPayload>;
 }
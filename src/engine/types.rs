@@ -11,21 +11,30 @@ pub const JSONRPC_VERSION: &str = "2.0";
 
 /// The new payload method string
 pub const ENGINE_NEW_PAYLOAD_V1: &str = "engine_newPayloadV1";
-// pub const ENGINE_NEW_PAYLOAD_V2: &str = "engine_newPayloadV2";
+/// The new payload method string, with withdrawals (Canyon)
+pub const ENGINE_NEW_PAYLOAD_V2: &str = "engine_newPayloadV2";
+/// The new payload method string, with blob gas and beacon root (Ecotone)
+pub const ENGINE_NEW_PAYLOAD_V3: &str = "engine_newPayloadV3";
 
 /// The new payload timeout
 pub const ENGINE_NEW_PAYLOAD_TIMEOUT: Duration = Duration::from_secs(8);
 
 /// The get payload method string
 pub const ENGINE_GET_PAYLOAD_V1: &str = "engine_getPayloadV1";
-// pub const ENGINE_GET_PAYLOAD_V2: &str = "engine_getPayloadV2";
+/// The get payload method string, with withdrawals (Canyon)
+pub const ENGINE_GET_PAYLOAD_V2: &str = "engine_getPayloadV2";
+/// The get payload method string, with blob gas and beacon root (Ecotone)
+pub const ENGINE_GET_PAYLOAD_V3: &str = "engine_getPayloadV3";
 
 /// The get payload timeout
 pub const ENGINE_GET_PAYLOAD_TIMEOUT: Duration = Duration::from_secs(2);
 
 /// The forkchoice updated method string
 pub const ENGINE_FORKCHOICE_UPDATED_V1: &str = "engine_forkchoiceUpdatedV1";
-// pub const ENGINE_FORKCHOICE_UPDATED_V2: &str = "engine_forkchoiceUpdatedV2";
+/// The forkchoice updated method string, with withdrawals (Canyon)
+pub const ENGINE_FORKCHOICE_UPDATED_V2: &str = "engine_forkchoiceUpdatedV2";
+/// The forkchoice updated method string, with beacon root (Ecotone)
+pub const ENGINE_FORKCHOICE_UPDATED_V3: &str = "engine_forkchoiceUpdatedV3";
 
 /// The forkchoice updated timeout
 pub const ENGINE_FORKCHOICE_UPDATED_TIMEOUT: Duration = Duration::from_secs(8);
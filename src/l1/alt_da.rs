@@ -0,0 +1,539 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use ethers::{
+    abi::{decode, parse_abi_str, ParamType, Token},
+    prelude::BaseContract,
+    providers::Middleware,
+    types::{Address, Bytes, Filter, Log, H256, U256},
+    utils::keccak256,
+};
+use eyre::Result;
+use once_cell::sync::Lazy;
+use thiserror::Error;
+
+use super::provider::L1Provider;
+
+static CHALLENGE_STATUS_CHANGED_TOPIC: Lazy<H256> =
+    Lazy::new(|| H256::from_slice(&keccak256("ChallengeStatusChanged(uint256,bytes,uint8)")));
+
+const DATA_AVAILABILITY_CHALLENGE_ABI: &str = r#"[
+    function resolve(uint256 challengedBlockNumber, bytes challengedCommitment, bytes resolveData) external
+]"#;
+
+/// Maximum number of L1 blocks queried for challenge events at once
+const MAX_LOG_RANGE: u64 = 1000;
+
+/// Challenge status emitted when a commitment is challenged
+const CHALLENGE_STATUS_ACTIVE: u8 = 1;
+
+/// Challenge status emitted when a challenge is resolved
+const CHALLENGE_STATUS_RESOLVED: u8 = 2;
+
+/// The type of an alt-DA commitment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CommitmentType {
+    /// The keccak256 hash of the input
+    Keccak256,
+    /// An opaque commitment understood by the DA server only
+    Generic,
+}
+
+/// An alt-DA commitment to a batcher data input
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Commitment {
+    pub commitment_type: CommitmentType,
+    pub data: Vec<u8>,
+}
+
+impl Commitment {
+    /// Decodes a commitment from batcher data following the alt-DA version byte
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let (commitment_type, data) = data.split_first().ok_or(eyre::eyre!("empty commitment"))?;
+
+        let commitment_type = match commitment_type {
+            0 if data.len() == 32 => CommitmentType::Keccak256,
+            0 => eyre::bail!("invalid keccak256 commitment length: {}", data.len()),
+            1 if !data.is_empty() => CommitmentType::Generic,
+            1 => eyre::bail!("empty generic commitment"),
+            _ => eyre::bail!("unknown commitment type: {}", commitment_type),
+        };
+
+        Ok(Self {
+            commitment_type,
+            data: data.to_vec(),
+        })
+    }
+
+    /// Encodes the commitment with its type byte
+    pub fn encode(&self) -> Vec<u8> {
+        let commitment_type = match self.commitment_type {
+            CommitmentType::Keccak256 => 0,
+            CommitmentType::Generic => 1,
+        };
+
+        [&[commitment_type], self.data.as_slice()].concat()
+    }
+
+    /// Checks that the input matches the commitment. Generic commitments cannot
+    /// be verified locally and always match.
+    pub fn verify(&self, input: &[u8]) -> Result<()> {
+        if self.commitment_type == CommitmentType::Keccak256 && keccak256(input) != *self.data {
+            eyre::bail!("input does not match keccak256 commitment");
+        }
+
+        Ok(())
+    }
+}
+
+/// Fetches commitment inputs from an alt-DA server
+pub struct DaClient {
+    client: reqwest::Client,
+    server_url: String,
+}
+
+impl DaClient {
+    pub fn new(server_url: &str) -> Result<Self> {
+        let client = reqwest::ClientBuilder::new()
+            .timeout(Duration::from_secs(5))
+            .build()?;
+
+        Ok(Self {
+            client,
+            server_url: server_url.trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// Fetches and verifies the input of a commitment, returning `None` if the
+    /// server does not have it
+    pub async fn get_input(&self, commitment: &Commitment) -> Result<Option<Vec<u8>>> {
+        let url = format!(
+            "{}/get/0x{}",
+            self.server_url,
+            hex::encode(commitment.encode())
+        );
+
+        let res = self.client.get(url).send().await?;
+        if res.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let input = res.error_for_status()?.bytes().await?.to_vec();
+        commitment.verify(&input)?;
+
+        Ok(Some(input))
+    }
+}
+
+/// The challenge status of a commitment as seen from the current L1 head
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChallengeStatus {
+    /// Not challenged, and the challenge window has passed
+    Unchallenged,
+    /// Not challenged, but the challenge window is still open
+    Open,
+    /// Challenged, and the resolve window is still open
+    Active,
+    /// Resolved on L1 with the given input
+    Resolved(Vec<u8>),
+    /// Challenged but not resolved in time, so the input must not be used
+    Expired,
+}
+
+/// Returned when the challenge of a commitment expires after its input was used. The
+/// blocks derived from it are invalid, so derivation must be reset to before the L1
+/// block that included the commitment.
+#[derive(Debug, Error)]
+#[error("challenge of used commitment from l1 block {l1_block} expired")]
+pub struct ExpiredChallenge {
+    /// The L1 block that included the commitment
+    pub l1_block: u64,
+}
+
+/// A challenge of a commitment
+#[derive(Debug, Clone)]
+struct Challenge {
+    /// The L1 block the challenge was made in
+    start: u64,
+    /// The input revealed by the resolve transaction
+    input: Option<Vec<u8>>,
+}
+
+/// Tracks challenges of alt-DA commitments from the events of the L1
+/// DataAvailabilityChallenge contract
+pub struct ChallengeTracker {
+    provider: Arc<L1Provider>,
+    contract: Address,
+    /// Number of L1 blocks after inclusion in which a commitment can be challenged
+    challenge_window: u64,
+    /// Number of L1 blocks after a challenge in which it can be resolved
+    resolve_window: u64,
+    /// Challenges by challenged block number and encoded commitment
+    challenges: HashMap<(u64, Vec<u8>), Challenge>,
+    /// The L1 block events have been indexed up to
+    synced_to: Option<u64>,
+    /// The most recent L1 block synced for derivation
+    head: u64,
+    /// Commitments that were used while they could still be challenged
+    pending: Vec<(u64, Commitment)>,
+}
+
+impl ChallengeTracker {
+    pub fn new(
+        provider: Arc<L1Provider>,
+        contract: Address,
+        challenge_window: u64,
+        resolve_window: u64,
+    ) -> Self {
+        Self {
+            provider,
+            contract,
+            challenge_window,
+            resolve_window,
+            challenges: HashMap::new(),
+            synced_to: None,
+            head: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Indexes the challenge events up to the L1 block being derived, starting at the
+    /// block itself on the first sync. Errors with [ExpiredChallenge] if the challenge of
+    /// a used commitment expired by that block.
+    pub async fn sync(&mut self, l1_block: u64) -> Result<()> {
+        let mut start = self.synced_to.map(|block| block + 1).unwrap_or(l1_block);
+
+        while start <= l1_block {
+            let end = l1_block.min(start + MAX_LOG_RANGE - 1);
+            let filter = Filter::new()
+                .address(self.contract)
+                .topic0(*CHALLENGE_STATUS_CHANGED_TOPIC)
+                .from_block(start)
+                .to_block(end);
+
+            for log in self.provider.get_logs(&filter).await? {
+                self.ingest_log(log).await?;
+            }
+
+            self.synced_to = Some(end);
+            start = end + 1;
+        }
+
+        // blocks are derived again after a reset, but expired challenges stay expired
+        self.head = self.head.max(l1_block);
+        self.check_pending()
+    }
+
+    /// Returns the challenge status of a commitment included in the L1 block
+    pub fn status(&self, block: u64, commitment: &Commitment) -> ChallengeStatus {
+        // only keccak256 commitments can be challenged
+        if commitment.commitment_type != CommitmentType::Keccak256 {
+            return ChallengeStatus::Unchallenged;
+        }
+
+        match self.challenges.get(&(block, commitment.encode())) {
+            Some(Challenge {
+                input: Some(input), ..
+            }) => ChallengeStatus::Resolved(input.clone()),
+            Some(challenge) if self.head > challenge.start + self.resolve_window => {
+                ChallengeStatus::Expired
+            }
+            Some(_) => ChallengeStatus::Active,
+            None if self.head > block + self.challenge_window => ChallengeStatus::Unchallenged,
+            None => ChallengeStatus::Open,
+        }
+    }
+
+    /// Records a commitment whose input was used before its challenge could expire
+    pub fn track(&mut self, block: u64, commitment: Commitment) {
+        self.pending.push((block, commitment));
+    }
+
+    /// Drops tracked commitments that can no longer be invalidated. Errors with the
+    /// earliest block whose commitment challenge expired after its input was used. The
+    /// commitments from that block on are dropped, as they are used again once the
+    /// blocks are derived again.
+    fn check_pending(&mut self) -> Result<()> {
+        let pending = std::mem::take(&mut self.pending);
+        let mut expired = None;
+
+        for (block, commitment) in pending {
+            match self.status(block, &commitment) {
+                ChallengeStatus::Open | ChallengeStatus::Active => {
+                    self.pending.push((block, commitment))
+                }
+                ChallengeStatus::Expired => {
+                    tracing::error!(
+                        "challenge of used commitment 0x{} from block {} expired",
+                        hex::encode(commitment.encode()),
+                        block
+                    );
+
+                    expired = Some(expired.map_or(block, |expired: u64| expired.min(block)));
+                }
+                ChallengeStatus::Unchallenged | ChallengeStatus::Resolved(_) => (),
+            }
+        }
+
+        match expired {
+            Some(l1_block) => {
+                self.pending.retain(|(block, _)| *block < l1_block);
+                Err(ExpiredChallenge { l1_block }.into())
+            }
+            None => Ok(()),
+        }
+    }
+
+    async fn ingest_log(&mut self, log: Log) -> Result<()> {
+        let (block, commitment, status) = decode_challenge_log(&log)?;
+        let log_block = log
+            .block_number
+            .ok_or(eyre::eyre!("log not included"))?
+            .as_u64();
+
+        match status {
+            CHALLENGE_STATUS_ACTIVE => {
+                tracing::info!("commitment challenged in block {}", log_block);
+                self.challenges.insert(
+                    (block, commitment),
+                    Challenge {
+                        start: log_block,
+                        input: None,
+                    },
+                );
+            }
+            CHALLENGE_STATUS_RESOLVED => {
+                let tx_hash = log
+                    .transaction_hash
+                    .ok_or(eyre::eyre!("log not included"))?;
+                let tx = self
+                    .provider
+                    .get_transaction(tx_hash)
+                    .await?
+                    .ok_or(eyre::eyre!("resolve transaction not found"))?;
+
+                let input = decode_resolve_input(tx.input)?;
+                Commitment::decode(&commitment)?.verify(&input)?;
+
+                self.record_resolved(block, commitment, log_block, input);
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    fn record_resolved(&mut self, block: u64, commitment: Vec<u8>, log_block: u64, input: Vec<u8>) {
+        let challenge = self
+            .challenges
+            .entry((block, commitment))
+            .or_insert(Challenge {
+                start: log_block,
+                input: None,
+            });
+
+        challenge.input = Some(input);
+    }
+}
+
+/// Decodes the challenged block, commitment and status of a `ChallengeStatusChanged` event
+fn decode_challenge_log(log: &Log) -> Result<(u64, Vec<u8>, u8)> {
+    let block = log
+        .topics
+        .get(1)
+        .ok_or(eyre::eyre!("missing challenged block number"))?;
+    let block = U256::from_big_endian(block.as_bytes()).as_u64();
+
+    let tokens = decode(&[ParamType::Bytes, ParamType::Uint(8)], &log.data)?;
+    match tokens.as_slice() {
+        [Token::Bytes(commitment), Token::Uint(status)] => {
+            Ok((block, commitment.clone(), status.as_u32() as u8))
+        }
+        _ => eyre::bail!("invalid challenge event data"),
+    }
+}
+
+/// Decodes the input revealed by a `resolve` call
+fn decode_resolve_input(calldata: Bytes) -> Result<Vec<u8>> {
+    let abi = BaseContract::from(parse_abi_str(DATA_AVAILABILITY_CHALLENGE_ABI)?);
+    let (_, _, input): (U256, Bytes, Bytes) = abi.decode("resolve", calldata)?;
+    Ok(input.to_vec())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use ethers::abi::encode;
+
+    use ethers::providers::Provider;
+
+    use crate::l1::{
+        mock_server::{self, Response},
+        provider::L1Client,
+    };
+
+    use super::*;
+
+    fn keccak_commitment(input: &[u8]) -> Commitment {
+        Commitment {
+            commitment_type: CommitmentType::Keccak256,
+            data: keccak256(input).to_vec(),
+        }
+    }
+
+    /// Serves the given inputs by encoded commitment, as an alt-DA server does
+    pub(crate) async fn mock_da_server(inputs: Vec<(Vec<u8>, Vec<u8>)>) -> String {
+        mock_server::serve(move |request| {
+            inputs
+                .iter()
+                .find(|(commitment, _)| {
+                    request.path == format!("/get/0x{}", hex::encode(commitment))
+                })
+                .map(|(_, input)| Response::ok(input.clone()))
+                .unwrap_or_else(Response::not_found)
+        })
+        .await
+    }
+
+    #[test]
+    fn test_commitment_decode() {
+        let commitment = keccak_commitment(b"input");
+        let encoded = commitment.encode();
+
+        assert_eq!(encoded[0], 0);
+        assert_eq!(Commitment::decode(&encoded).unwrap(), commitment);
+        assert!(commitment.verify(b"input").is_ok());
+        assert!(commitment.verify(b"other input").is_err());
+
+        let generic = Commitment::decode(&[1, 0xAB, 0xCD]).unwrap();
+        assert_eq!(generic.commitment_type, CommitmentType::Generic);
+        assert!(generic.verify(b"anything").is_ok());
+
+        assert!(Commitment::decode(&[]).is_err());
+        assert!(Commitment::decode(&[0, 1, 2]).is_err());
+        assert!(Commitment::decode(&[2, 1, 2]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_da_client() {
+        let valid = keccak_commitment(b"batcher data");
+        let invalid = keccak_commitment(b"other data");
+        let missing = keccak_commitment(b"missing data");
+
+        let url = mock_da_server(vec![
+            (valid.encode(), b"batcher data".to_vec()),
+            (invalid.encode(), b"batcher data".to_vec()),
+        ])
+        .await;
+
+        let client = DaClient::new(&url).unwrap();
+        assert_eq!(
+            client.get_input(&valid).await.unwrap(),
+            Some(b"batcher data".to_vec())
+        );
+        assert!(client.get_input(&invalid).await.is_err());
+        assert_eq!(client.get_input(&missing).await.unwrap(), None);
+    }
+
+    #[test]
+    fn test_challenge_status() {
+        let urls = ["http://127.0.0.1:8545".to_string()];
+        let provider = Arc::new(Provider::new(L1Client::from_urls(&urls, 1).unwrap()));
+        let mut tracker = ChallengeTracker::new(provider, Address::zero(), 10, 5);
+        let resolved = keccak_commitment(b"resolved");
+        let challenged = keccak_commitment(b"challenged");
+        let unchallenged = keccak_commitment(b"unchallenged");
+
+        tracker.challenges.insert(
+            (100, challenged.encode()),
+            Challenge {
+                start: 105,
+                input: None,
+            },
+        );
+        tracker.record_resolved(100, resolved.encode(), 106, b"resolved".to_vec());
+
+        tracker.head = 108;
+        assert_eq!(tracker.status(100, &unchallenged), ChallengeStatus::Open);
+        assert_eq!(tracker.status(100, &challenged), ChallengeStatus::Active);
+        assert_eq!(
+            tracker.status(100, &resolved),
+            ChallengeStatus::Resolved(b"resolved".to_vec())
+        );
+
+        tracker.head = 111;
+        assert_eq!(
+            tracker.status(100, &unchallenged),
+            ChallengeStatus::Unchallenged
+        );
+        assert_eq!(tracker.status(100, &challenged), ChallengeStatus::Expired);
+
+        let generic = Commitment::decode(&[1, 0xAB]).unwrap();
+        assert_eq!(tracker.status(100, &generic), ChallengeStatus::Unchallenged);
+    }
+
+    #[test]
+    fn test_expired_challenge_of_used_commitment() {
+        let urls = ["http://127.0.0.1:8545".to_string()];
+        let provider = Arc::new(Provider::new(L1Client::from_urls(&urls, 1).unwrap()));
+        let mut tracker = ChallengeTracker::new(provider, Address::zero(), 10, 5);
+        let challenged = keccak_commitment(b"challenged");
+        let later = keccak_commitment(b"later");
+
+        tracker.challenges.insert(
+            (100, challenged.encode()),
+            Challenge {
+                start: 105,
+                input: None,
+            },
+        );
+        tracker.track(100, challenged.clone());
+        tracker.track(102, later.clone());
+
+        tracker.head = 108;
+        assert!(tracker.check_pending().is_ok());
+        assert_eq!(tracker.pending.len(), 2);
+
+        tracker.head = 111;
+        let err = tracker.check_pending().unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ExpiredChallenge>().unwrap().l1_block,
+            100
+        );
+        assert!(tracker.pending.is_empty());
+
+        // the commitment is skipped when its block is derived again
+        assert_eq!(tracker.status(100, &challenged), ChallengeStatus::Expired);
+    }
+
+    #[test]
+    fn test_decode_challenge_events() {
+        let commitment = keccak_commitment(b"input").encode();
+        let log = Log {
+            topics: vec![*CHALLENGE_STATUS_CHANGED_TOPIC, H256::from_low_u64_be(100)],
+            data: encode(&[
+                Token::Bytes(commitment.clone()),
+                Token::Uint(CHALLENGE_STATUS_ACTIVE.into()),
+            ])
+            .into(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            decode_challenge_log(&log).unwrap(),
+            (100, commitment.clone(), CHALLENGE_STATUS_ACTIVE)
+        );
+
+        let abi = BaseContract::from(parse_abi_str(DATA_AVAILABILITY_CHALLENGE_ABI).unwrap());
+        let calldata = abi
+            .encode(
+                "resolve",
+                (
+                    U256::from(100),
+                    Bytes::from(commitment),
+                    Bytes::from(b"input".to_vec()),
+                ),
+            )
+            .unwrap();
+
+        assert_eq!(decode_resolve_input(calldata).unwrap(), b"input".to_vec());
+    }
+}
@@ -0,0 +1,209 @@
+use eyre::Result;
+
+/// Size of an EIP-4844 blob in bytes
+pub const BLOB_SIZE: usize = 4096 * 32;
+
+/// Maximum number of data bytes a single blob can carry
+pub const MAX_BLOB_DATA_SIZE: usize = (4 * 31 + 3) * 1024 - 4;
+
+/// The blob encoding version supported by the rollup
+const ENCODING_VERSION: u8 = 0;
+
+/// Number of 4 field element rounds in a blob
+const ROUNDS: usize = 1024;
+
+/// Decodes the rollup data from a blob. Each field element carries 31 full
+/// bytes plus 6 bits in its first byte, so every round of 4 field elements
+/// yields 127 bytes of data. The first field element also carries the
+/// encoding version and a 3 byte big-endian data length.
+pub fn decode_blob_data(blob: &[u8]) -> Result<Vec<u8>> {
+    if blob.len() != BLOB_SIZE {
+        eyre::bail!("invalid blob size: {}", blob.len());
+    }
+
+    if blob[1] != ENCODING_VERSION {
+        eyre::bail!("invalid blob encoding version: {}", blob[1]);
+    }
+
+    let output_len = u32::from_be_bytes([0, blob[2], blob[3], blob[4]]) as usize;
+    if output_len > MAX_BLOB_DATA_SIZE {
+        eyre::bail!("invalid blob data length: {}", output_len);
+    }
+
+    // the first round has the version and length occupying 4 bytes of the
+    // first field element, so only the remaining 27 bytes are copied
+    let mut output = vec![0u8; MAX_BLOB_DATA_SIZE];
+    output[..27].copy_from_slice(&blob[5..32]);
+
+    let mut opos = 28;
+    let mut ipos = 32;
+    let mut encoded = [0u8; 4];
+
+    encoded[0] = blob[0];
+    for byte in encoded.iter_mut().skip(1) {
+        *byte = decode_field_element(blob, &mut opos, &mut ipos, &mut output)?;
+    }
+    reassemble_bytes(&mut opos, &encoded, &mut output);
+
+    for _ in 1..ROUNDS {
+        if opos >= output_len {
+            break;
+        }
+
+        for byte in encoded.iter_mut() {
+            *byte = decode_field_element(blob, &mut opos, &mut ipos, &mut output)?;
+        }
+        reassemble_bytes(&mut opos, &encoded, &mut output);
+    }
+
+    if output[output_len..].iter().any(|b| *b != 0) {
+        eyre::bail!("non-zero data past the blob data length");
+    }
+
+    if blob[ipos..].iter().any(|b| *b != 0) {
+        eyre::bail!("non-zero data past the end of the blob data");
+    }
+
+    output.truncate(output_len);
+    Ok(output)
+}
+
+/// Copies the 31 trailing bytes of a field element to the output, returning
+/// its first byte for later reassembly
+fn decode_field_element(
+    blob: &[u8],
+    opos: &mut usize,
+    ipos: &mut usize,
+    output: &mut [u8],
+) -> Result<u8> {
+    let first = blob[*ipos];
+    if first & 0b1100_0000 != 0 {
+        eyre::bail!("invalid field element at blob offset {}", ipos);
+    }
+
+    let end = (*opos + 31).min(output.len());
+    output[*opos..end].copy_from_slice(&blob[*ipos + 1..*ipos + 1 + end - *opos]);
+
+    *opos += 32;
+    *ipos += 32;
+
+    Ok(first)
+}
+
+/// Rebuilds the three bytes spread across the 6 bit chunks of a round
+fn reassemble_bytes(opos: &mut usize, encoded: &[u8; 4], output: &mut [u8]) {
+    // the round does not output a 128th byte
+    *opos -= 1;
+
+    let x = (encoded[0] & 0b0011_1111) | ((encoded[1] & 0b0011_0000) << 2);
+    let y = (encoded[1] & 0b0000_1111) | ((encoded[3] & 0b0000_1111) << 4);
+    let z = (encoded[2] & 0b0011_1111) | ((encoded[3] & 0b0011_0000) << 2);
+
+    output[*opos - 32] = z;
+    output[*opos - 32 * 2] = y;
+    output[*opos - 32 * 3] = x;
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Encodes data into a blob, mirroring the batcher's encoding
+    pub(crate) fn encode_blob_data(data: &[u8]) -> Vec<u8> {
+        assert!(data.len() <= MAX_BLOB_DATA_SIZE);
+
+        let mut blob = vec![0u8; BLOB_SIZE];
+        let mut read = 0;
+        let mut write = 0;
+
+        let read1 = |read: &mut usize| {
+            let byte = data.get(*read).copied().unwrap_or_default();
+            *read += 1;
+            byte
+        };
+
+        let read31 = |read: &mut usize| {
+            let mut buf = [0u8; 31];
+            let end = (*read + 31).min(data.len()).max(*read);
+            if *read < data.len() {
+                buf[..end - *read].copy_from_slice(&data[*read..end]);
+            }
+            *read = end;
+            buf
+        };
+
+        let mut write_fe = |write: &mut usize, first: u8, rest: [u8; 31]| {
+            blob[*write] = first;
+            blob[*write + 1..*write + 32].copy_from_slice(&rest);
+            *write += 32;
+        };
+
+        for round in 0..ROUNDS {
+            if read >= data.len() {
+                break;
+            }
+
+            let buf = if round == 0 {
+                let mut buf = [0u8; 31];
+                buf[0] = ENCODING_VERSION;
+                buf[1..4].copy_from_slice(&(data.len() as u32).to_be_bytes()[1..]);
+                let n = data.len().min(27);
+                buf[4..4 + n].copy_from_slice(&data[..n]);
+                read += n;
+                buf
+            } else {
+                read31(&mut read)
+            };
+
+            let x = read1(&mut read);
+            write_fe(&mut write, x & 0b0011_1111, buf);
+
+            let buf = read31(&mut read);
+            let y = read1(&mut read);
+            write_fe(
+                &mut write,
+                (y & 0b0000_1111) | ((x & 0b1100_0000) >> 2),
+                buf,
+            );
+
+            let buf = read31(&mut read);
+            let z = read1(&mut read);
+            write_fe(&mut write, z & 0b0011_1111, buf);
+
+            let buf = read31(&mut read);
+            write_fe(
+                &mut write,
+                ((z & 0b1100_0000) >> 2) | ((y & 0b1111_0000) >> 4),
+                buf,
+            );
+        }
+
+        blob
+    }
+
+    #[test]
+    fn test_blob_roundtrip() {
+        for len in [0, 1, 27, 28, 123, 124, 1000, MAX_BLOB_DATA_SIZE] {
+            let data = (0..len).map(|i| (i * 7 + 3) as u8).collect::<Vec<_>>();
+            let blob = encode_blob_data(&data);
+
+            assert_eq!(decode_blob_data(&blob).unwrap(), data, "length {}", len);
+        }
+    }
+
+    #[test]
+    fn test_blob_invalid_version() {
+        let mut blob = encode_blob_data(b"hello");
+        blob[1] = 1;
+
+        assert!(decode_blob_data(&blob).is_err());
+    }
+
+    #[test]
+    fn test_blob_trailing_data() {
+        let mut blob = encode_blob_data(b"hello");
+        blob[BLOB_SIZE - 1] = 1;
+
+        assert!(decode_blob_data(&blob).is_err());
+    }
+}
@@ -0,0 +1,318 @@
+use std::{collections::HashMap, time::Duration};
+
+use c_kzg::{Blob, Bytes48, KzgProof};
+use ethers::types::{Transaction, H256};
+use eyre::Result;
+use serde::{de::DeserializeOwned, Deserialize};
+use sha2::{Digest, Sha256};
+
+use super::blob_encoding::decode_blob_data;
+
+/// Transaction type of EIP-4844 blob transactions
+const BLOB_TX_TYPE: u64 = 3;
+
+/// Version byte of KZG commitment versioned hashes
+const VERSIONED_HASH_VERSION_KZG: u8 = 1;
+
+/// Fetches blob sidecars from a beacon node and decodes their rollup data
+pub struct BlobFetcher {
+    /// Beacon node HTTP client
+    client: reqwest::Client,
+    /// Beacon node base URL
+    beacon_url: String,
+    /// Beacon genesis time and seconds per slot, queried on first use
+    slot_config: Option<(u64, u64)>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BeaconResponse<T> {
+    data: T,
+}
+
+#[derive(Debug, Deserialize)]
+struct GenesisData {
+    #[serde(deserialize_with = "deserialize_u64_str")]
+    genesis_time: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct SpecData {
+    #[serde(rename = "SECONDS_PER_SLOT", deserialize_with = "deserialize_u64_str")]
+    seconds_per_slot: u64,
+}
+
+/// A blob sidecar as returned by the beacon API
+#[derive(Debug, Deserialize)]
+struct BlobSidecar {
+    blob: ethers::types::Bytes,
+    kzg_commitment: ethers::types::Bytes,
+    kzg_proof: ethers::types::Bytes,
+}
+
+impl BlobFetcher {
+    /// Creates a new fetcher for the given beacon node URL
+    pub fn new(beacon_url: &str) -> Result<Self> {
+        let client = reqwest::ClientBuilder::new()
+            .timeout(Duration::from_secs(5))
+            .build()?;
+
+        Ok(Self {
+            client,
+            beacon_url: beacon_url.trim_end_matches('/').to_string(),
+            slot_config: None,
+        })
+    }
+
+    /// Fetches the blobs with the given versioned hashes from the beacon block
+    /// at the L1 block timestamp. The blobs are verified against their KZG
+    /// commitments and decoded into batcher transaction data.
+    pub async fn get_blob_data(
+        &mut self,
+        block_timestamp: u64,
+        hashes: &[H256],
+    ) -> Result<HashMap<H256, Vec<u8>>> {
+        if hashes.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let slot = self.get_slot(block_timestamp).await?;
+        let sidecars: Vec<BlobSidecar> = self
+            .get(&format!("eth/v1/beacon/blob_sidecars/{}", slot))
+            .await?;
+
+        let mut blobs = HashMap::new();
+        for sidecar in sidecars {
+            let hash = kzg_to_versioned_hash(&sidecar.kzg_commitment);
+            if !hashes.contains(&hash) {
+                continue;
+            }
+
+            verify_sidecar(&sidecar)?;
+            blobs.insert(hash, decode_blob_data(&sidecar.blob)?);
+        }
+
+        if let Some(missing) = hashes.iter().find(|hash| !blobs.contains_key(hash)) {
+            eyre::bail!("blob sidecar not found for versioned hash {:?}", missing);
+        }
+
+        Ok(blobs)
+    }
+
+    async fn get_slot(&mut self, timestamp: u64) -> Result<u64> {
+        let (genesis_time, seconds_per_slot) = match self.slot_config {
+            Some(slot_config) => slot_config,
+            None => {
+                let genesis: GenesisData = self.get("eth/v1/beacon/genesis").await?;
+                let spec: SpecData = self.get("eth/v1/config/spec").await?;
+                let slot_config = (genesis.genesis_time, spec.seconds_per_slot);
+                self.slot_config = Some(slot_config);
+                slot_config
+            }
+        };
+
+        let elapsed = timestamp
+            .checked_sub(genesis_time)
+            .ok_or(eyre::eyre!("block timestamp before beacon genesis"))?;
+
+        Ok(elapsed / seconds_per_slot)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let url = format!("{}/{}", self.beacon_url, path);
+        let res = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<BeaconResponse<T>>()
+            .await?;
+
+        Ok(res.data)
+    }
+}
+
+/// Returns the blob versioned hashes of a transaction, which are empty for
+/// non blob transactions
+pub fn blob_versioned_hashes(tx: &Transaction) -> Vec<H256> {
+    if tx.transaction_type.map(|t| t.as_u64()) != Some(BLOB_TX_TYPE) {
+        return Vec::new();
+    }
+
+    tx.other
+        .get_deserialized::<Vec<H256>>("blobVersionedHashes")
+        .and_then(|hashes| hashes.ok())
+        .unwrap_or_default()
+}
+
+/// Computes the versioned hash of a KZG commitment
+fn kzg_to_versioned_hash(commitment: &[u8]) -> H256 {
+    let mut hash: [u8; 32] = Sha256::digest(commitment).into();
+    hash[0] = VERSIONED_HASH_VERSION_KZG;
+    H256(hash)
+}
+
+fn verify_sidecar(sidecar: &BlobSidecar) -> Result<()> {
+    let blob = Blob::from_bytes(&sidecar.blob).map_err(|err| eyre::eyre!("{:?}", err))?;
+    let commitment =
+        Bytes48::from_bytes(&sidecar.kzg_commitment).map_err(|err| eyre::eyre!("{:?}", err))?;
+    let proof = Bytes48::from_bytes(&sidecar.kzg_proof).map_err(|err| eyre::eyre!("{:?}", err))?;
+
+    let valid =
+        KzgProof::verify_blob_kzg_proof(&blob, &commitment, &proof, c_kzg::ethereum_kzg_settings())
+            .map_err(|err| eyre::eyre!("{:?}", err))?;
+
+    if !valid {
+        eyre::bail!("invalid blob kzg proof");
+    }
+
+    Ok(())
+}
+
+fn deserialize_u64_str<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.parse().map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use c_kzg::KzgCommitment;
+    use serde_json::json;
+
+    use super::*;
+    use crate::l1::{
+        blob_encoding::tests::encode_blob_data,
+        mock_server::{self, Response},
+    };
+
+    const GENESIS_TIME: u64 = 1000;
+
+    struct TestBlob {
+        data: Vec<u8>,
+        hash: H256,
+        sidecar: serde_json::Value,
+    }
+
+    fn test_blob(data: &[u8]) -> TestBlob {
+        let encoded = encode_blob_data(data);
+        let blob = Blob::from_bytes(&encoded).unwrap();
+        let settings = c_kzg::ethereum_kzg_settings();
+
+        let commitment = KzgCommitment::blob_to_kzg_commitment(&blob, settings).unwrap();
+        let commitment = commitment.to_bytes();
+        let proof = KzgProof::compute_blob_kzg_proof(&blob, &commitment, settings)
+            .unwrap()
+            .to_bytes();
+
+        TestBlob {
+            data: data.to_vec(),
+            hash: kzg_to_versioned_hash(commitment.as_ref()),
+            sidecar: json!({
+                "index": "0",
+                "blob": format!("0x{}", hex::encode(&encoded)),
+                "kzg_commitment": format!("0x{}", hex::encode(commitment.as_ref())),
+                "kzg_proof": format!("0x{}", hex::encode(proof.as_ref())),
+            }),
+        }
+    }
+
+    /// Serves the beacon genesis, spec and blob sidecar endpoints for slot 5
+    async fn mock_beacon(sidecars: Vec<serde_json::Value>) -> String {
+        mock_server::serve(move |request| {
+            let body = match request.path.as_str() {
+                "/eth/v1/beacon/genesis" => {
+                    json!({ "data": { "genesis_time": GENESIS_TIME.to_string() } })
+                }
+                "/eth/v1/config/spec" => json!({ "data": { "SECONDS_PER_SLOT": "12" } }),
+                "/eth/v1/beacon/blob_sidecars/5" => json!({ "data": sidecars }),
+                _ => return Response::not_found(),
+            };
+
+            Response::ok(body.to_string())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_get_blob_data() {
+        let first = test_blob(b"first batcher frame");
+        let second = test_blob(b"second batcher frame");
+        let other = test_blob(b"not a batcher blob");
+
+        let url = mock_beacon(vec![
+            first.sidecar.clone(),
+            other.sidecar.clone(),
+            second.sidecar.clone(),
+        ])
+        .await;
+
+        let mut fetcher = BlobFetcher::new(&url).unwrap();
+        let blobs = fetcher
+            .get_blob_data(GENESIS_TIME + 5 * 12, &[first.hash, second.hash])
+            .await
+            .unwrap();
+
+        assert_eq!(blobs.len(), 2);
+        assert_eq!(blobs[&first.hash], first.data);
+        assert_eq!(blobs[&second.hash], second.data);
+    }
+
+    #[tokio::test]
+    async fn test_get_blob_data_missing() {
+        let first = test_blob(b"first batcher frame");
+        let url = mock_beacon(vec![first.sidecar.clone()]).await;
+
+        let mut fetcher = BlobFetcher::new(&url).unwrap();
+        let res = fetcher
+            .get_blob_data(GENESIS_TIME + 5 * 12, &[H256::repeat_byte(1)])
+            .await;
+
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_get_blob_data_invalid_proof() {
+        let mut first = test_blob(b"first batcher frame");
+        let second = test_blob(b"second batcher frame");
+        first.sidecar["kzg_proof"] = second.sidecar["kzg_proof"].clone();
+
+        let url = mock_beacon(vec![first.sidecar.clone()]).await;
+
+        let mut fetcher = BlobFetcher::new(&url).unwrap();
+        let res = fetcher
+            .get_blob_data(GENESIS_TIME + 5 * 12, &[first.hash])
+            .await;
+
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_blob_versioned_hashes() {
+        let tx: Transaction = serde_json::from_value(json!({
+            "hash": H256::zero(),
+            "nonce": "0x0",
+            "blockHash": null,
+            "blockNumber": null,
+            "transactionIndex": null,
+            "from": ethers::types::Address::zero(),
+            "to": ethers::types::Address::zero(),
+            "value": "0x0",
+            "gas": "0x0",
+            "input": "0x",
+            "v": "0x0",
+            "r": "0x0",
+            "s": "0x0",
+            "type": "0x3",
+            "blobVersionedHashes": [H256::repeat_byte(1), H256::repeat_byte(2)],
+        }))
+        .unwrap();
+
+        assert_eq!(
+            blob_versioned_hashes(&tx),
+            vec![H256::repeat_byte(1), H256::repeat_byte(2)]
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+
+use ethers::{
+    providers::{JsonRpcClient, Provider},
+    types::{BlockId, BlockNumber, H256},
+};
+use eyre::Result;
+use lazy_static::lazy_static;
+
+use crate::telemetry::metrics;
+
+use super::{utils::get_l1_block_info, L1BlockInfo};
+
+/// The number of L1 blocks kept in the cache, enough to cover the sequencing window
+const L1_BLOCK_CACHE_CAPACITY: usize = 4096;
+
+lazy_static! {
+    /// The L1 block cache shared by the chain watcher, the sequencer and the RPC server
+    pub static ref L1_BLOCK_CACHE: L1BlockCache = L1BlockCache::new(L1_BLOCK_CACHE_CAPACITY);
+}
+
+/// Caches the info of recent L1 blocks by hash and by number. The info of a hash
+/// never changes, but the block at a number does on reorgs, so the cache keeps a
+/// separate canonical chain view that the chain watcher updates as it ingests
+/// blocks and detects reorgs. The oldest blocks are evicted once the cache is full.
+pub struct L1BlockCache {
+    inner: Mutex<Inner>,
+    capacity: usize,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// The cached blocks, ordered by number for eviction
+    blocks: BTreeMap<(u64, H256), L1BlockInfo>,
+    /// The number of each cached block
+    numbers: HashMap<H256, u64>,
+    /// The hash of the canonical block at each number, as far as known
+    canonical: BTreeMap<u64, H256>,
+}
+
+impl L1BlockCache {
+    /// Creates a cache that holds at most `capacity` blocks
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(Inner::default()),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Returns the block with the given hash, canonical or not
+    pub fn by_hash(&self, hash: H256) -> Option<L1BlockInfo> {
+        let inner = self.inner.lock().unwrap();
+        let info = inner
+            .numbers
+            .get(&hash)
+            .and_then(|number| inner.blocks.get(&(*number, hash)))
+            .cloned();
+
+        record_lookup(info.is_some());
+        info
+    }
+
+    /// Returns the canonical block with the given number
+    pub fn by_number(&self, number: u64) -> Option<L1BlockInfo> {
+        let inner = self.inner.lock().unwrap();
+        let info = inner
+            .canonical
+            .get(&number)
+            .and_then(|hash| inner.blocks.get(&(number, *hash)))
+            .cloned();
+
+        record_lookup(info.is_some());
+        info
+    }
+
+    /// Caches a block without making it canonical, for blocks looked up by hash
+    pub fn insert(&self, info: L1BlockInfo) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.numbers.insert(info.hash, info.number);
+        inner.blocks.insert((info.number, info.hash), info);
+        self.evict(&mut inner);
+    }
+
+    /// Caches a block of the canonical chain. If it replaces another block at the
+    /// same number, the canonical blocks after it are forgotten, as they were built
+    /// on the replaced block.
+    pub fn insert_canonical(&self, info: L1BlockInfo) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner
+            .canonical
+            .get(&info.number)
+            .is_some_and(|hash| *hash != info.hash)
+        {
+            inner.canonical.split_off(&info.number);
+        }
+
+        inner.canonical.insert(info.number, info.hash);
+        inner.numbers.insert(info.hash, info.number);
+        inner.blocks.insert((info.number, info.hash), info);
+        self.evict(&mut inner);
+    }
+
+    /// Forgets the canonical blocks after the given number, after a reorg replaced
+    /// them. They can still be found by hash.
+    pub fn rollback(&self, number: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.canonical.split_off(&(number + 1));
+    }
+
+    /// Returns the block from the cache, or fetches it from the provider and caches
+    /// it. Blocks fetched by number or tag are taken as canonical.
+    pub async fn get_or_fetch<T, U>(
+        &self,
+        block_id: T,
+        provider: &Provider<U>,
+    ) -> Result<L1BlockInfo>
+    where
+        T: Into<BlockId> + Send + Sync,
+        U: JsonRpcClient,
+    {
+        let block_id = block_id.into();
+        let cached = match block_id {
+            BlockId::Hash(hash) => self.by_hash(hash),
+            BlockId::Number(BlockNumber::Number(number)) => self.by_number(number.as_u64()),
+            BlockId::Number(_) => None,
+        };
+
+        if let Some(info) = cached {
+            return Ok(info);
+        }
+
+        let info = get_l1_block_info(block_id, provider).await?;
+        match block_id {
+            BlockId::Hash(_) => self.insert(info.clone()),
+            BlockId::Number(_) => self.insert_canonical(info.clone()),
+        }
+
+        Ok(info)
+    }
+
+    /// The number of cached blocks
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().blocks.len()
+    }
+
+    /// Returns true if no block is cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn evict(&self, inner: &mut Inner) {
+        while inner.blocks.len() > self.capacity {
+            let Some(((number, hash), _)) = inner.blocks.pop_first() else {
+                break;
+            };
+
+            inner.numbers.remove(&hash);
+            if inner.canonical.get(&number) == Some(&hash) {
+                inner.canonical.remove(&number);
+            }
+        }
+    }
+}
+
+fn record_lookup(hit: bool) {
+    if hit {
+        metrics::L1_BLOCK_CACHE_HITS.inc();
+    } else {
+        metrics::L1_BLOCK_CACHE_MISSES.inc();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        providers::Provider,
+        types::{Block, H256, U256, U64},
+    };
+
+    use crate::l1::L1BlockInfo;
+
+    use super::L1BlockCache;
+
+    fn info(number: u64, hash: H256) -> L1BlockInfo {
+        L1BlockInfo {
+            number,
+            hash,
+            timestamp: number * 12,
+            base_fee: U256::from(1),
+            mix_hash: H256::zero(),
+            state_root: H256::zero(),
+            parent_beacon_root: None,
+            excess_blob_gas: None,
+            blob_base_fee: U256::from(1),
+        }
+    }
+
+    fn hash(number: u64) -> H256 {
+        H256::from_low_u64_be(number)
+    }
+
+    #[test]
+    fn test_reorg_replaces_canonical_blocks() {
+        let cache = L1BlockCache::new(100);
+        for number in 10..15 {
+            cache.insert_canonical(info(number, hash(number)));
+        }
+
+        let replacement = H256::repeat_byte(0xaa);
+        cache.insert_canonical(info(12, replacement));
+
+        assert_eq!(cache.by_number(11).unwrap().hash, hash(11));
+        assert_eq!(cache.by_number(12).unwrap().hash, replacement);
+        assert!(cache.by_number(13).is_none());
+
+        // replaced blocks are still known by hash
+        assert_eq!(cache.by_hash(hash(13)).unwrap().number, 13);
+        assert_eq!(cache.len(), 6);
+    }
+
+    #[test]
+    fn test_rollback() {
+        let cache = L1BlockCache::new(100);
+        for number in 10..15 {
+            cache.insert_canonical(info(number, hash(number)));
+        }
+
+        cache.rollback(11);
+
+        assert!(cache.by_number(11).is_some());
+        assert!(cache.by_number(12).is_none());
+        assert!(cache.by_hash(hash(12)).is_some());
+    }
+
+    #[test]
+    fn test_evicts_oldest_blocks() {
+        let cache = L1BlockCache::new(3);
+        for number in 10..15 {
+            cache.insert_canonical(info(number, hash(number)));
+        }
+
+        assert_eq!(cache.len(), 3);
+        assert!(cache.by_number(11).is_none());
+        assert!(cache.by_hash(hash(11)).is_none());
+        assert!(cache.by_number(12).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_get_or_fetch() {
+        let cache = L1BlockCache::new(100);
+        let (provider, mock) = Provider::mocked();
+        let block = Block::<H256> {
+            number: Some(U64::from(20)),
+            hash: Some(hash(20)),
+            base_fee_per_gas: Some(U256::from(7)),
+            mix_hash: Some(H256::zero()),
+            ..Default::default()
+        };
+        mock.push::<Block<H256>, _>(block).unwrap();
+
+        let fetched = cache.get_or_fetch(20, &provider).await.unwrap();
+        assert_eq!(fetched.base_fee, U256::from(7));
+
+        // served from the cache, the mock has no responses left
+        let cached = cache.get_or_fetch(hash(20), &provider).await.unwrap();
+        assert_eq!(cached.number, 20);
+        assert_eq!(cache.by_number(20).unwrap().hash, hash(20));
+    }
+}
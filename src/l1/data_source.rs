@@ -0,0 +1,453 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use eyre::Result;
+use tokio::sync::Mutex;
+
+use crate::config::{Config, DataSourceConfig};
+
+use super::{
+    alt_da::{ChallengeStatus, ChallengeTracker, Commitment, DaClient},
+    blob_fetcher::BlobFetcher,
+    provider::generate_l1_provider,
+    L1Info,
+};
+
+/// Version byte of batcher data that holds an alt-DA commitment
+const ALT_DA_TX_DATA_VERSION: u8 = 1;
+
+/// Provides the batcher data of each L1 block to the derivation pipeline
+#[async_trait]
+pub trait DataSource: Send + Sync {
+    /// Returns the batcher data of the L1 block, in the order it was submitted
+    async fn open_data(&self, l1_info: &L1Info) -> Result<Vec<Vec<u8>>>;
+}
+
+/// Builds the data source selected by the chain config. Multiple sources are
+/// read in the configured order. Errors if more than one source reads the batcher
+/// transactions, as their data would be returned twice.
+pub fn from_config(config: &Config) -> Result<Box<dyn DataSource>> {
+    let batcher_sources = config
+        .chain
+        .data_sources
+        .iter()
+        .filter(|source| !matches!(source, DataSourceConfig::Files { .. }))
+        .collect::<Vec<_>>();
+
+    if let [first, second, ..] = batcher_sources.as_slice() {
+        eyre::bail!(
+            "data sources {:?} and {:?} both read the batcher transactions",
+            first,
+            second
+        );
+    }
+
+    let beacon_url = config.l1_beacon_url.as_deref();
+    let mut sources = config
+        .chain
+        .data_sources
+        .iter()
+        .map(|source| -> Result<Box<dyn DataSource>> {
+            Ok(match source {
+                DataSourceConfig::Calldata => Box::new(CalldataSource),
+                DataSourceConfig::Blobs => Box::new(BlobSource::new(beacon_url)?),
+                DataSourceConfig::Files { dir } => Box::new(FileSource::new(dir.clone())),
+                DataSourceConfig::AltDa {
+                    server_url,
+                    challenge_contract,
+                    challenge_window,
+                    resolve_window,
+                } => {
+                    let inner = Box::new(BlobSource::new(beacon_url)?);
+                    let mut source = AltDaSource::new(inner, server_url)?;
+
+                    if let Some(contract) = challenge_contract {
+                        let provider = generate_l1_provider(config);
+                        source = source.with_challenge_tracker(ChallengeTracker::new(
+                            provider,
+                            *contract,
+                            *challenge_window,
+                            *resolve_window,
+                        ));
+                    }
+
+                    Box::new(source)
+                }
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if sources.len() == 1 {
+        Ok(sources.remove(0))
+    } else {
+        Ok(Box::new(CompositeSource::new(sources)))
+    }
+}
+
+/// Reads the calldata of batcher transactions. Blob transactions are skipped.
+pub struct CalldataSource;
+
+#[async_trait]
+impl DataSource for CalldataSource {
+    async fn open_data(&self, l1_info: &L1Info) -> Result<Vec<Vec<u8>>> {
+        Ok(l1_info
+            .batcher_transactions
+            .iter()
+            .filter(|tx| tx.blob_hashes.is_empty())
+            .map(|tx| tx.calldata.clone())
+            .collect())
+    }
+}
+
+/// Reads the calldata of batcher transactions and the blobs of batcher blob
+/// transactions, fetched from a beacon node
+pub struct BlobSource {
+    /// Beacon node blob fetcher, if a beacon URL is configured
+    fetcher: Option<Mutex<BlobFetcher>>,
+}
+
+impl BlobSource {
+    pub fn new(beacon_url: Option<&str>) -> Result<Self> {
+        let fetcher = beacon_url.map(BlobFetcher::new).transpose()?;
+
+        Ok(Self {
+            fetcher: fetcher.map(Mutex::new),
+        })
+    }
+}
+
+#[async_trait]
+impl DataSource for BlobSource {
+    async fn open_data(&self, l1_info: &L1Info) -> Result<Vec<Vec<u8>>> {
+        let hashes = l1_info
+            .batcher_transactions
+            .iter()
+            .flat_map(|tx| tx.blob_hashes.iter().copied())
+            .collect::<Vec<_>>();
+
+        if hashes.is_empty() {
+            return CalldataSource.open_data(l1_info).await;
+        }
+
+        let fetcher = self.fetcher.as_ref().ok_or(eyre::eyre!(
+            "block contains batcher blobs but no l1 beacon url is configured"
+        ))?;
+
+        let blobs = fetcher
+            .lock()
+            .await
+            .get_blob_data(l1_info.block_info.timestamp, &hashes)
+            .await?;
+
+        let mut data = Vec::new();
+        for tx in &l1_info.batcher_transactions {
+            if tx.blob_hashes.is_empty() {
+                data.push(tx.calldata.clone());
+                continue;
+            }
+
+            for hash in &tx.blob_hashes {
+                let blob = blobs
+                    .get(hash)
+                    .ok_or(eyre::eyre!("missing blob data for {:?}", hash))?;
+
+                data.push(blob.clone());
+            }
+        }
+
+        Ok(data)
+    }
+}
+
+/// Reads batcher data from local files. The data of each L1 block is stored at
+/// `<dir>/<block number>.json` as a JSON array of hex strings. Blocks without a
+/// file have no batcher data.
+pub struct FileSource {
+    dir: PathBuf,
+}
+
+impl FileSource {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+#[async_trait]
+impl DataSource for FileSource {
+    async fn open_data(&self, l1_info: &L1Info) -> Result<Vec<Vec<u8>>> {
+        let path = self.dir.join(format!("{}.json", l1_info.block_info.number));
+
+        let contents = match tokio::fs::read(&path).await {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        serde_json::from_slice::<Vec<String>>(&contents)?
+            .iter()
+            .map(|data| Ok(hex::decode(data.strip_prefix("0x").unwrap_or(data))?))
+            .collect()
+    }
+}
+
+/// Resolves alt-DA commitments in the data of an inner source through an alt-DA
+/// server. Data that is not a commitment is passed through unchanged.
+pub struct AltDaSource {
+    inner: Box<dyn DataSource>,
+    client: DaClient,
+    /// Challenge tracker, if the chain has a DataAvailabilityChallenge contract
+    tracker: Option<Mutex<ChallengeTracker>>,
+}
+
+impl AltDaSource {
+    pub fn new(inner: Box<dyn DataSource>, server_url: &str) -> Result<Self> {
+        Ok(Self {
+            inner,
+            client: DaClient::new(server_url)?,
+            tracker: None,
+        })
+    }
+
+    /// Checks the challenge status of each commitment before using its input
+    pub fn with_challenge_tracker(mut self, tracker: ChallengeTracker) -> Self {
+        self.tracker = Some(Mutex::new(tracker));
+        self
+    }
+}
+
+#[async_trait]
+impl DataSource for AltDaSource {
+    async fn open_data(&self, l1_info: &L1Info) -> Result<Vec<Vec<u8>>> {
+        let block = l1_info.block_info.number;
+        let mut tracker = match &self.tracker {
+            Some(tracker) => Some(tracker.lock().await),
+            None => None,
+        };
+
+        if let Some(tracker) = tracker.as_mut() {
+            tracker.sync(block).await?;
+        }
+
+        let mut data = Vec::new();
+        for tx_data in self.inner.open_data(l1_info).await? {
+            let commitment = match tx_data.split_first() {
+                Some((&ALT_DA_TX_DATA_VERSION, commitment)) => commitment,
+                _ => {
+                    data.push(tx_data);
+                    continue;
+                }
+            };
+
+            let commitment = match Commitment::decode(commitment) {
+                Ok(commitment) => commitment,
+                Err(err) => {
+                    tracing::warn!("skipping invalid commitment in block {}: {}", block, err);
+                    continue;
+                }
+            };
+
+            let status = tracker
+                .as_ref()
+                .map(|tracker| tracker.status(block, &commitment))
+                .unwrap_or(ChallengeStatus::Unchallenged);
+
+            match status {
+                ChallengeStatus::Expired => {
+                    tracing::warn!(
+                        "skipping commitment with expired challenge in block {}",
+                        block
+                    );
+                }
+                ChallengeStatus::Resolved(input) => data.push(input),
+                status => match self.client.get_input(&commitment).await? {
+                    Some(input) => {
+                        if let Some(tracker) = tracker.as_mut() {
+                            if status != ChallengeStatus::Unchallenged {
+                                tracker.track(block, commitment);
+                            }
+                        }
+
+                        data.push(input);
+                    }
+                    None if status == ChallengeStatus::Unchallenged => eyre::bail!(
+                        "input of commitment 0x{} not found",
+                        hex::encode(commitment.encode())
+                    ),
+                    None => eyre::bail!(
+                        "input of commitment 0x{} not found, waiting for its challenge",
+                        hex::encode(commitment.encode())
+                    ),
+                },
+            }
+        }
+
+        Ok(data)
+    }
+}
+
+/// Reads batcher data from multiple sources, in order
+pub struct CompositeSource {
+    sources: Vec<Box<dyn DataSource>>,
+}
+
+impl CompositeSource {
+    pub fn new(sources: Vec<Box<dyn DataSource>>) -> Self {
+        Self { sources }
+    }
+}
+
+#[async_trait]
+impl DataSource for CompositeSource {
+    async fn open_data(&self, l1_info: &L1Info) -> Result<Vec<Vec<u8>>> {
+        let mut data = Vec::new();
+        for source in &self.sources {
+            data.append(&mut source.open_data(l1_info).await?);
+        }
+
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        types::{H256, U256},
+        utils::keccak256,
+    };
+
+    use crate::{
+        config::ChainConfig,
+        l1::{
+            alt_da::tests::mock_da_server, alt_da::CommitmentType, BatcherTransactionData,
+            L1BlockInfo,
+        },
+    };
+
+    use super::*;
+
+    fn l1_info(batcher_transactions: Vec<BatcherTransactionData>) -> L1Info {
+        L1Info {
+            block_info: L1BlockInfo {
+                number: 100,
+                hash: H256::zero(),
+                timestamp: 1000,
+                base_fee: U256::zero(),
+                mix_hash: H256::zero(),
+                state_root: H256::zero(),
+                parent_beacon_root: None,
+                excess_blob_gas: None,
+                blob_base_fee: U256::one(),
+            },
+            system_config: ChainConfig::optimism().system_config,
+            user_deposits: Vec::new(),
+            batcher_transactions,
+            finalized: false,
+        }
+    }
+
+    fn calldata_tx(calldata: &[u8]) -> BatcherTransactionData {
+        BatcherTransactionData {
+            calldata: calldata.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_calldata_source() {
+        let blob_tx = BatcherTransactionData {
+            blob_hashes: vec![H256::repeat_byte(1)],
+            ..Default::default()
+        };
+        let info = l1_info(vec![calldata_tx(&[0, 1]), blob_tx, calldata_tx(&[0, 2])]);
+
+        let data = CalldataSource.open_data(&info).await.unwrap();
+        assert_eq!(data, vec![vec![0, 1], vec![0, 2]]);
+
+        // blobs can only be read with a beacon node
+        assert!(BlobSource::new(None)
+            .unwrap()
+            .open_data(&info)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_file_source() {
+        let dir = std::env::temp_dir().join(format!("magi-data-source-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("100.json"), r#"["0x0001", "0002"]"#).unwrap();
+
+        let source = CompositeSource::new(vec![
+            Box::new(FileSource::new(dir.clone())),
+            Box::new(CalldataSource),
+        ]);
+        let data = source
+            .open_data(&l1_info(vec![calldata_tx(&[0, 3])]))
+            .await
+            .unwrap();
+
+        assert_eq!(data, vec![vec![0, 1], vec![0, 2], vec![0, 3]]);
+
+        let mut info = l1_info(Vec::new());
+        info.block_info.number = 101;
+        assert!(FileSource::new(dir.clone())
+            .open_data(&info)
+            .await
+            .unwrap()
+            .is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_duplicate_batcher_sources() {
+        let config = |data_sources| Config {
+            chain: ChainConfig {
+                data_sources,
+                ..ChainConfig::optimism()
+            },
+            ..Config::default_for_tests()
+        };
+
+        // the blob source also reads the calldata of batcher transactions
+        let sources = vec![DataSourceConfig::Calldata, DataSourceConfig::Blobs];
+        assert!(from_config(&config(sources)).is_err());
+
+        let sources = vec![
+            DataSourceConfig::Files {
+                dir: PathBuf::from("batches"),
+            },
+            DataSourceConfig::Blobs,
+        ];
+        assert!(from_config(&config(sources)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_alt_da_source() {
+        let input = b"batcher frames".to_vec();
+        let commitment = Commitment {
+            commitment_type: CommitmentType::Keccak256,
+            data: keccak256(&input).to_vec(),
+        };
+        let url = mock_da_server(vec![(commitment.encode(), input.clone())]).await;
+
+        let commitment_tx =
+            calldata_tx(&[[ALT_DA_TX_DATA_VERSION].as_slice(), &commitment.encode()].concat());
+        let invalid_tx = calldata_tx(&[ALT_DA_TX_DATA_VERSION, 2, 1]);
+        let info = l1_info(vec![calldata_tx(&[0, 1]), commitment_tx, invalid_tx]);
+
+        let source = AltDaSource::new(Box::new(CalldataSource), &url).unwrap();
+        let data = source.open_data(&info).await.unwrap();
+        assert_eq!(data, vec![vec![0, 1], input]);
+
+        let missing = [ALT_DA_TX_DATA_VERSION, 0]
+            .iter()
+            .copied()
+            .chain([1; 32])
+            .collect::<Vec<_>>();
+        assert!(source
+            .open_data(&l1_info(vec![calldata_tx(&missing)]))
+            .await
+            .is_err());
+    }
+}
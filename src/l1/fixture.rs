@@ -0,0 +1,232 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use eyre::Result;
+use tokio::{
+    fs,
+    io::{AsyncBufReadExt, BufReader},
+    spawn,
+    sync::mpsc,
+    task::JoinHandle,
+};
+
+use crate::config::Config;
+
+use super::{data_source, BlockUpdate, ChainWatcher};
+
+/// Records the L1 block updates of a block range to a fixture file, which can be
+/// replayed by setting `l1_fixture` in the config. The fixture holds one JSON
+/// encoded [BlockUpdate] per line.
+pub struct FixtureRecorder {
+    /// Global config
+    config: Arc<Config>,
+    /// Directory to write the resolved batcher data of each block to
+    data_dir: Option<PathBuf>,
+}
+
+impl FixtureRecorder {
+    pub fn new(config: Arc<Config>) -> Self {
+        Self {
+            config,
+            data_dir: None,
+        }
+    }
+
+    /// Also writes the batcher data of each block, as returned by the configured
+    /// data sources, to the directory in the layout of the `files` data source.
+    /// This allows replaying data that is not part of the L1 blocks, like blobs.
+    pub fn with_data_dir(mut self, data_dir: PathBuf) -> Self {
+        self.data_dir = Some(data_dir);
+        self
+    }
+
+    /// Records the block updates from `l1_start_block` through `l1_end_block`. The
+    /// range should be finalized, as the recording is aborted on reorgs.
+    pub async fn record(
+        &self,
+        path: &Path,
+        l1_start_block: u64,
+        l1_end_block: u64,
+        l2_start_block: u64,
+    ) -> Result<()> {
+        let mut watcher = ChainWatcher::new(l1_start_block, l2_start_block, self.config.clone())?;
+        watcher.start()?;
+
+        let data_source = self
+            .data_dir
+            .as_ref()
+            .map(|_| data_source::from_config(&self.config))
+            .transpose()?;
+
+        if let Some(data_dir) = &self.data_dir {
+            std::fs::create_dir_all(data_dir)?;
+        }
+
+        let mut file = BufWriter::new(File::create(path)?);
+
+        loop {
+            let update = watcher
+                .recv_from_channel()
+                .await
+                .ok_or(eyre::eyre!("chain watcher stopped"))?;
+
+            let mut done = false;
+            match &update {
+                BlockUpdate::NewBlock(l1_info) => {
+                    let number = l1_info.block_info.number;
+                    tracing::info!("recording L1 block {}", number);
+
+                    if let (Some(data_dir), Some(data_source)) = (&self.data_dir, &data_source) {
+                        let data = data_source
+                            .open_data(l1_info)
+                            .await?
+                            .iter()
+                            .map(|data| format!("0x{}", hex::encode(data)))
+                            .collect::<Vec<_>>();
+
+                        std::fs::write(
+                            data_dir.join(format!("{}.json", number)),
+                            serde_json::to_vec(&data)?,
+                        )?;
+                    }
+
+                    done = number >= l1_end_block;
+                }
+                BlockUpdate::Reorg(_) => {
+                    eyre::bail!("reorg while recording, use a finalized range")
+                }
+                BlockUpdate::FinalityUpdate(_) => (),
+            }
+
+            serde_json::to_writer(&mut file, &update)?;
+            writeln!(file)?;
+
+            if done {
+                break;
+            }
+        }
+
+        file.flush()?;
+        Ok(())
+    }
+}
+
+/// Replays the block updates of a recorded fixture, starting at the given L1
+/// block. Finality updates are always replayed. The channel is closed once the
+/// end of the fixture is reached.
+pub(super) fn start_replay(
+    path: PathBuf,
+    l1_start_block: u64,
+) -> (JoinHandle<()>, mpsc::Receiver<BlockUpdate>) {
+    let (block_update_sender, block_update_receiver) = mpsc::channel(1000);
+
+    let handle = spawn(async move {
+        if let Err(err) = replay(&path, l1_start_block, block_update_sender).await {
+            tracing::error!("failed to replay L1 fixture {:?}: {}", path, err);
+        }
+    });
+
+    (handle, block_update_receiver)
+}
+
+async fn replay(
+    path: &Path,
+    l1_start_block: u64,
+    block_update_sender: mpsc::Sender<BlockUpdate>,
+) -> Result<()> {
+    let file = fs::File::open(path).await?;
+    let mut lines = BufReader::new(file).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let update: BlockUpdate = serde_json::from_str(&line)?;
+        if let BlockUpdate::NewBlock(l1_info) = &update {
+            if l1_info.block_info.number < l1_start_block {
+                continue;
+            }
+        }
+
+        block_update_sender.send(update).await?;
+    }
+
+    tracing::info!("reached the end of L1 fixture {:?}", path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use ethers::types::H256;
+
+    use crate::{
+        config::Config,
+        l1::{BlockUpdate, ChainWatcher, L1BlockInfo, L1Info},
+    };
+
+    fn l1_info(number: u64, config: &Config) -> L1Info {
+        L1Info {
+            block_info: L1BlockInfo {
+                number,
+                hash: H256::from_low_u64_be(number),
+                timestamp: number * 12,
+                base_fee: 1.into(),
+                mix_hash: H256::zero(),
+                state_root: H256::zero(),
+                parent_beacon_root: None,
+                excess_blob_gas: None,
+                blob_base_fee: 1.into(),
+            },
+            system_config: config.chain.system_config,
+            user_deposits: Vec::new(),
+            batcher_transactions: Vec::new(),
+            finalized: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_fixture() {
+        let path = std::env::temp_dir().join(format!("magi-fixture-{}.jsonl", std::process::id()));
+        let config = Config {
+            l1_fixture: Some(path.clone()),
+            ..Config::default_for_tests()
+        };
+
+        let updates = [
+            BlockUpdate::NewBlock(Box::new(l1_info(100, &config))),
+            BlockUpdate::FinalityUpdate(99),
+            BlockUpdate::NewBlock(Box::new(l1_info(101, &config))),
+            BlockUpdate::NewBlock(Box::new(l1_info(102, &config))),
+        ];
+        let fixture = updates
+            .iter()
+            .map(|update| serde_json::to_string(update).unwrap())
+            .collect::<Vec<_>>()
+            .join("\n");
+        std::fs::write(&path, fixture).unwrap();
+
+        let mut watcher =
+            ChainWatcher::new(101, config.chain.l2_genesis.number, Arc::new(config)).unwrap();
+        watcher.start().unwrap();
+
+        let mut replayed = Vec::new();
+        while let Some(update) = watcher.recv_from_channel().await {
+            replayed.push(match update {
+                BlockUpdate::NewBlock(l1_info) => format!("block {}", l1_info.block_info.number),
+                BlockUpdate::FinalityUpdate(number) => format!("finalized {}", number),
+                BlockUpdate::Reorg(_) => "reorg".to_string(),
+            });
+        }
+
+        assert_eq!(replayed, vec!["finalized 99", "block 101", "block 102"]);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
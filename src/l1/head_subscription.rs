@@ -0,0 +1,159 @@
+use std::time::Duration;
+
+use ethers::providers::{Middleware, Provider, StreamExt, Ws};
+use eyre::Result;
+use tokio::{
+    spawn,
+    sync::watch,
+    task::JoinHandle,
+    time::{sleep, timeout},
+};
+
+/// How long to wait before resubscribing after the subscription dropped
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+
+/// How long the subscription may go without a new head before it is considered stale
+const HEAD_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Follows the L1 head through an `eth_subscribe("newHeads")` subscription over
+/// WebSocket, resubscribing whenever the connection drops or goes quiet. While
+/// there is no subscription, the head is `None` and the chain watcher falls back
+/// to polling. The subscription task is aborted on drop.
+pub struct HeadSubscription {
+    heads: watch::Receiver<Option<u64>>,
+    handle: JoinHandle<()>,
+}
+
+impl HeadSubscription {
+    /// Spawns the subscription task for the given WebSocket URL
+    pub fn spawn(url: String) -> Self {
+        let (sender, heads) = watch::channel(None);
+        let handle = spawn(async move {
+            loop {
+                match follow_heads(&url, &sender).await {
+                    Ok(()) => tracing::warn!("L1 head subscription closed, resubscribing"),
+                    Err(err) => tracing::warn!("L1 head subscription failed: {}", err),
+                }
+
+                sender.send_replace(None);
+                sleep(RESUBSCRIBE_DELAY).await;
+            }
+        });
+
+        Self { heads, handle }
+    }
+
+    /// The number of the latest L1 head, or `None` if not subscribed
+    pub fn head(&self) -> Option<u64> {
+        *self.heads.borrow()
+    }
+
+    /// Waits until a new head arrives or the subscription drops, for at most `max_wait`
+    pub async fn changed(&mut self, max_wait: Duration) {
+        let _ = timeout(max_wait, self.heads.changed()).await;
+    }
+}
+
+impl Drop for HeadSubscription {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Publishes the heads of a single subscription until it ends
+async fn follow_heads(url: &str, sender: &watch::Sender<Option<u64>>) -> Result<()> {
+    let provider = Provider::<Ws>::connect(url).await?;
+    let mut stream = provider.subscribe_blocks().await?;
+    tracing::info!("subscribed to new L1 heads");
+
+    loop {
+        let head = timeout(HEAD_TIMEOUT, stream.next())
+            .await
+            .map_err(|_| eyre::eyre!("no new head for {:?}", HEAD_TIMEOUT))?;
+
+        let Some(head) = head else {
+            return Ok(());
+        };
+
+        if let Some(number) = head.number {
+            tracing::debug!("new L1 head {}", number);
+            sender.send_replace(Some(number.as_u64()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ethers::types::{Block, H256, U64};
+    use futures::{SinkExt, StreamExt};
+    use serde_json::{json, Value};
+    use tokio::{net::TcpListener, spawn, time::timeout};
+    use tokio_tungstenite::{accept_async, tungstenite::Message};
+
+    use super::HeadSubscription;
+
+    /// Serves one connection: answers the subscription request, sends the heads, then
+    /// closes the connection
+    async fn serve_heads(listener: &TcpListener, heads: &[u64]) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = accept_async(stream).await.unwrap();
+
+        let request = loop {
+            if let Message::Text(text) = ws.next().await.unwrap().unwrap() {
+                break serde_json::from_str::<Value>(&text).unwrap();
+            }
+        };
+        assert_eq!(request["method"], "eth_subscribe");
+        assert_eq!(request["params"][0], "newHeads");
+
+        let response = json!({"jsonrpc": "2.0", "id": request["id"], "result": "0x1"});
+        ws.send(Message::Text(response.to_string())).await.unwrap();
+
+        for number in heads {
+            let head = Block::<H256> {
+                number: Some(U64::from(*number)),
+                ..Default::default()
+            };
+            let notification = json!({
+                "jsonrpc": "2.0",
+                "method": "eth_subscription",
+                "params": {"subscription": "0x1", "result": head},
+            });
+            ws.send(Message::Text(notification.to_string()))
+                .await
+                .unwrap();
+        }
+
+        ws.close(None).await.unwrap();
+    }
+
+    async fn wait_for_head(subscription: &mut HeadSubscription, number: u64) {
+        timeout(Duration::from_secs(10), async {
+            while subscription.head() != Some(number) {
+                subscription.changed(Duration::from_millis(100)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_and_resubscribe() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+
+        let server = spawn(async move {
+            serve_heads(&listener, &[10, 11]).await;
+            serve_heads(&listener, &[12]).await;
+            listener
+        });
+
+        let mut subscription = HeadSubscription::spawn(url);
+        wait_for_head(&mut subscription, 11).await;
+        wait_for_head(&mut subscription, 12).await;
+
+        server.await.unwrap();
+    }
+}
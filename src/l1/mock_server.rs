@@ -0,0 +1,101 @@
+//! A minimal HTTP server for tests that talk to L1, beacon or alt-DA endpoints
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+/// A request received by the mock server
+pub struct Request {
+    pub path: String,
+    pub body: String,
+}
+
+/// A response of the mock server
+pub struct Response {
+    pub status: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn ok(body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status: "200 OK",
+            body: body.into(),
+        }
+    }
+
+    pub fn not_found() -> Self {
+        Self {
+            status: "404 Not Found",
+            body: Vec::new(),
+        }
+    }
+}
+
+/// Serves every request with `handler`, one connection per request. Returns the
+/// server URL.
+pub async fn serve<F>(handler: F) -> String
+where
+    F: Fn(Request) -> Response + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    tokio::spawn(async move {
+        loop {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let request = read_request(&mut socket).await;
+            let response = handler(request);
+
+            let header = format!(
+                "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                response.status,
+                response.body.len()
+            );
+            socket.write_all(header.as_bytes()).await.unwrap();
+            socket.write_all(&response.body).await.unwrap();
+        }
+    });
+
+    url
+}
+
+/// Reads a request until its whole body, as given by its content length, arrived
+async fn read_request<R: AsyncReadExt + Unpin>(socket: &mut R) -> Request {
+    let mut data = Vec::new();
+    loop {
+        let mut buf = [0u8; 4096];
+        let n = socket.read(&mut buf).await.unwrap();
+        data.extend_from_slice(&buf[..n]);
+
+        let request = String::from_utf8_lossy(&data).to_string();
+        let Some((header, body)) = request.split_once("\r\n\r\n") else {
+            if n == 0 {
+                return Request {
+                    path: String::new(),
+                    body: String::new(),
+                };
+            }
+            continue;
+        };
+
+        let length = header
+            .lines()
+            .find_map(|line| {
+                line.to_lowercase()
+                    .strip_prefix("content-length: ")
+                    .map(str::to_string)
+            })
+            .and_then(|length| length.trim().parse::<usize>().ok())
+            .unwrap_or_default();
+
+        if body.len() >= length || n == 0 {
+            let path = header.split_whitespace().nth(1).unwrap_or_default();
+            return Request {
+                path: path.to_string(),
+                body: body.to_string(),
+            };
+        }
+    }
+}
@@ -1,14 +1,11 @@
 use std::{
     cmp::max,
-    collections::HashMap,
-    sync::Arc,
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
     time::{Duration, SystemTime},
 };
 
-use ethers::{
-    providers::{Http, HttpRateLimitRetryPolicy, Middleware, Provider, RetryClient},
-    types::Log,
-};
+use ethers::providers::{Http, HttpRateLimitRetryPolicy, Middleware, Provider, RetryClient};
 use ethers::{
     types::{Address, Block, BlockNumber, Filter, Transaction, H256, U256},
     utils::keccak256,
@@ -17,18 +14,43 @@ use ethers::{
 use eyre::Result;
 use once_cell::sync::Lazy;
 use reqwest::Url;
+use serde::{Deserialize, Serialize};
 use tokio::{spawn, sync::mpsc, task::JoinHandle, time::sleep};
 
 use crate::{
     common::BlockInfo,
     config::{Config, SystemConfig},
     derive::stages::attributes::UserDeposited,
+    telemetry::metrics,
 };
 
+pub mod alt_da;
+pub mod blob_encoding;
+pub mod blob_fetcher;
+pub mod block_cache;
+pub mod data_source;
+pub mod fixture;
+pub mod head_subscription;
+#[cfg(test)]
+pub(crate) mod mock_server;
+pub mod provider;
+pub mod system_config;
 pub mod utils;
 
-static CONFIG_UPDATE_TOPIC: Lazy<H256> =
-    Lazy::new(|| H256::from_slice(&keccak256("ConfigUpdate(uint256,uint8,bytes)")));
+use blob_fetcher::blob_versioned_hashes;
+use block_cache::L1_BLOCK_CACHE;
+use head_subscription::HeadSubscription;
+use provider::{generate_l1_provider, L1Client, L1Provider};
+use system_config::{SystemConfigTracker, CONFIG_UPDATE_TOPIC};
+
+/// How long to wait for a new subscribed head before checking the L1 chain again
+const HEAD_WAIT: Duration = Duration::from_secs(12);
+
+/// The number of blocks fetched ahead while catching up, unless configured
+const DEFAULT_PREFETCH_WINDOW: usize = 16;
+
+/// Reorgs replacing more blocks than this are logged as warnings
+const DEEP_REORG_DEPTH: u64 = 3;
 
 static TRANSACTION_DEPOSITED_TOPIC: Lazy<H256> = Lazy::new(|| {
     H256::from_slice(&keccak256(
@@ -49,22 +71,26 @@ pub struct ChainWatcher {
     l1_start_block: u64,
     /// The L2 starting block
     l2_start_block: u64,
+    /// The system config history, shared with the watcher task and kept across restarts
+    system_configs: Arc<Mutex<Option<SystemConfigTracker>>>,
     /// Channel for receiving block updates for each new block
     block_update_receiver: Option<mpsc::Receiver<BlockUpdate>>,
 }
 
 /// Updates L1Info
+#[derive(Debug, Serialize, Deserialize)]
 pub enum BlockUpdate {
     /// A new block extending the current chain
     NewBlock(Box<L1Info>),
     /// Updates the most recent finalized block
     FinalityUpdate(u64),
-    /// Reorg detected
-    Reorg,
+    /// Reorg detected. Holds the latest ingested block that is still canonical, or
+    /// `None` if the reorg reaches past the unfinalized blocks.
+    Reorg(Option<BlockInfo>),
 }
 
 /// Data tied to a specific L1 block
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct L1Info {
     /// L1 block data
     pub block_info: L1BlockInfo,
@@ -79,7 +105,7 @@ pub struct L1Info {
 }
 
 /// L1 block info
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct L1BlockInfo {
     /// L1 block number
     pub number: u64,
@@ -93,6 +119,12 @@ pub struct L1BlockInfo {
     pub mix_hash: H256,
     /// L1 state root
     pub state_root: H256,
+    /// L1 parent beacon block root, set from Dencun on
+    pub parent_beacon_root: Option<H256>,
+    /// L1 excess blob gas, set from Dencun on
+    pub excess_blob_gas: Option<u64>,
+    /// L1 blob base fee, the minimum of 1 wei before Dencun
+    pub blob_base_fee: U256,
 }
 
 /// Watcher actually ingests the L1 blocks. Should be run in another
@@ -101,7 +133,7 @@ struct InnerWatcher {
     /// Global Config
     config: Arc<Config>,
     /// Ethers provider for L1
-    provider: Arc<Provider<RetryClient<Http>>>,
+    provider: Arc<L1Provider>,
     /// Channel to send block updates
     block_update_sender: mpsc::Sender<BlockUpdate>,
     /// Most recent ingested block
@@ -115,13 +147,27 @@ struct InnerWatcher {
     /// Mapping from block number to user deposits. Past block deposits
     /// are removed as they are no longer needed
     deposits: HashMap<u64, Vec<UserDeposited>>,
-    /// Current system config value
-    system_config: SystemConfig,
-    /// Next system config if it exists and the L1 block number it activates
-    system_config_update: (u64, Option<SystemConfig>),
+    /// The system config history, initialized when the watcher is created
+    system_configs: Arc<Mutex<Option<SystemConfigTracker>>>,
+    /// Subscription to new L1 heads, if a WebSocket URL is configured
+    head_subscription: Option<HeadSubscription>,
+    /// The subscribed head at the last finality check
+    finality_checked_at: Option<u64>,
+    /// Blocks fetched ahead of the current block, by number
+    prefetched: BTreeMap<u64, Block<Transaction>>,
 }
 
-type BatcherTransactionData = Vec<u8>;
+/// A batcher transaction to the batch inbox. Its data is resolved by the
+/// pipeline's data source.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatcherTransactionData {
+    /// Transaction hash
+    pub hash: H256,
+    /// Transaction calldata
+    pub calldata: Vec<u8>,
+    /// Versioned hashes of the blobs carried by the transaction
+    pub blob_hashes: Vec<H256>,
+}
 
 impl Drop for ChainWatcher {
     fn drop(&mut self) {
@@ -140,10 +186,19 @@ impl ChainWatcher {
             config,
             l1_start_block,
             l2_start_block,
+            system_configs: Arc::new(Mutex::new(None)),
             block_update_receiver: None,
         })
     }
 
+    /// Starts with the system config as of the end of the given L1 block, for when it
+    /// is already known. The block should be the L1 starting block or the one before.
+    pub fn with_system_config(self, l1_block: u64, system_config: SystemConfig) -> Self {
+        let tracker = SystemConfigTracker::new(l1_block, system_config);
+        *self.system_configs.lock().unwrap() = Some(tracker);
+        self
+    }
+
     /// Starts the chain watcher at the given block numbers
     pub fn start(&mut self) -> Result<()> {
         if let Some(handle) = self.handle.take() {
@@ -153,6 +208,7 @@ impl ChainWatcher {
         let (handle, recv) = start_watcher(
             self.l1_start_block,
             self.l2_start_block,
+            self.system_configs.clone(),
             self.config.clone(),
         )?;
 
@@ -162,13 +218,31 @@ impl ChainWatcher {
         Ok(())
     }
 
-    /// Resets the chain watcher at the given block numbers
+    /// Resets the chain watcher at the given block numbers. The system config history
+    /// is rolled back to the block before the L1 starting block, or discarded if it
+    /// does not reach back that far.
     pub fn restart(&mut self, l1_start_block: u64, l2_start_block: u64) -> Result<()> {
         if let Some(handle) = self.handle.take() {
             handle.abort();
         }
 
-        let (handle, recv) = start_watcher(l1_start_block, l2_start_block, self.config.clone())?;
+        {
+            let mut system_configs = self.system_configs.lock().unwrap();
+            let rolled_back = system_configs
+                .as_mut()
+                .and_then(|tracker| tracker.rollback(l1_start_block.saturating_sub(1)));
+
+            if rolled_back.is_none() {
+                *system_configs = None;
+            }
+        }
+
+        let (handle, recv) = start_watcher(
+            l1_start_block,
+            l2_start_block,
+            self.system_configs.clone(),
+            self.config.clone(),
+        )?;
 
         self.handle = Some(handle);
         self.block_update_receiver = Some(recv);
@@ -205,43 +279,18 @@ impl InnerWatcher {
         block_update_sender: mpsc::Sender<BlockUpdate>,
         l1_start_block: u64,
         l2_start_block: u64,
+        system_configs: Arc<Mutex<Option<SystemConfigTracker>>>,
     ) -> Self {
-        let provider = generate_http_provider(&config.l1_rpc_url);
-
-        let system_config = if l2_start_block == config.chain.l2_genesis.number
-            || !config.chain.meta.enable_config_updates
-        {
-            config.chain.system_config
-        } else {
-            let l2_provider = generate_http_provider(&config.l2_rpc_url);
-
-            let block = l2_provider
-                .get_block_with_txs(l2_start_block - 1)
-                .await
-                .unwrap()
-                .unwrap();
-
-            let input = &block
-                .transactions
-                .first()
-                .expect(
-                    "Could not find the L1 attributes deposited transaction in the parent L2 block",
-                )
-                .input;
-
-            let batch_sender = Address::from_slice(&input[176..196]);
-            let l1_fee_overhead = U256::from(H256::from_slice(&input[196..228]).as_bytes());
-            let l1_fee_scalar = U256::from(H256::from_slice(&input[228..260]).as_bytes());
-
-            SystemConfig {
-                batch_sender,
-                l1_fee_overhead,
-                l1_fee_scalar,
-                gas_limit: block.gas_limit,
-                // TODO: fetch from contract
-                unsafe_block_signer: config.chain.system_config.unsafe_block_signer,
-            }
-        };
+        let provider = generate_l1_provider(&config);
+        let head_subscription = config.l1_ws_url.clone().map(HeadSubscription::spawn);
+
+        let has_history = system_configs.lock().unwrap().is_some();
+        if !has_history {
+            let system_config =
+                system_config::initial_system_config(&config, l1_start_block, l2_start_block).await;
+            let tracker = SystemConfigTracker::new(l1_start_block, system_config);
+            *system_configs.lock().unwrap() = Some(tracker);
+        }
 
         Self {
             config,
@@ -252,15 +301,23 @@ impl InnerWatcher {
             finalized_block: 0,
             unfinalized_blocks: Vec::new(),
             deposits: HashMap::new(),
-            system_config,
-            system_config_update: (l1_start_block, None),
+            system_configs,
+            head_subscription,
+            finality_checked_at: None,
+            prefetched: BTreeMap::new(),
         }
     }
 
     async fn try_ingest_block(&mut self) -> Result<()> {
         let now = SystemTime::now();
-        if self.current_block > self.finalized_block {
+        let subscribed_head = self.head_subscription.as_ref().and_then(|s| s.head());
+
+        // with a subscription, finality can only change once a new head arrives
+        let finality_due = subscribed_head.is_none() || subscribed_head != self.finality_checked_at;
+
+        if self.current_block > self.finalized_block && finality_due {
             let finalized_block = self.get_finalized().await?;
+            self.finality_checked_at = subscribed_head;
 
             // Only update finalized block if it has changed to avoid spamming the channel.
             if self.finalized_block < finalized_block {
@@ -275,16 +332,17 @@ impl InnerWatcher {
         }
 
         if self.current_block > self.head_block {
-            let head_block = self.get_head().await?;
+            let head_block = match subscribed_head {
+                Some(head_block) => head_block,
+                None => self.get_head().await?,
+            };
             self.head_block = head_block;
         }
 
         if self.current_block <= self.head_block {
-            if self.config.chain.meta.enable_config_updates {
-                self.update_system_config().await?;
-            }
-
             let block = self.get_block(self.current_block).await?;
+            let system_config = self.update_system_config(&block).await?;
+
             let user_deposits = if self.config.chain.meta.enable_deposited_txs {
                 self.get_deposits(self.current_block).await?
             } else {
@@ -297,26 +355,34 @@ impl InnerWatcher {
                 user_deposits,
                 self.config.chain.batch_inbox,
                 finalized,
-                self.system_config,
+                system_config,
             )?;
 
-            if l1_info.block_info.number >= self.finalized_block {
-                let block_info = BlockInfo {
-                    hash: l1_info.block_info.hash,
-                    number: l1_info.block_info.number,
-                    timestamp: l1_info.block_info.timestamp,
-                    parent_hash: block.parent_hash,
-                };
+            let block_info = BlockInfo {
+                hash: l1_info.block_info.hash,
+                number: l1_info.block_info.number,
+                timestamp: l1_info.block_info.timestamp,
+                parent_hash: block.parent_hash,
+            };
 
-                self.unfinalized_blocks.push(block_info);
-            }
+            let is_unfinalized = block_info.number >= self.finalized_block;
+            let update = if is_unfinalized && self.check_reorg(&block_info) {
+                let ancestor =
+                    find_common_ancestor(self.provider.as_ref(), &self.unfinalized_blocks).await?;
+                self.report_reorg(ancestor);
 
-            let update = if self.check_reorg() {
-                BlockUpdate::Reorg
+                let canonical = ancestor.map(|ancestor| ancestor.number);
+                L1_BLOCK_CACHE.rollback(canonical.unwrap_or(self.finalized_block));
+                BlockUpdate::Reorg(ancestor)
             } else {
+                L1_BLOCK_CACHE.insert_canonical(l1_info.block_info.clone());
                 BlockUpdate::NewBlock(Box::new(l1_info))
             };
 
+            if is_unfinalized {
+                self.unfinalized_blocks.push(block_info);
+            }
+
             self.block_update_sender.send(update).await?;
 
             self.current_block += 1;
@@ -324,74 +390,96 @@ impl InnerWatcher {
             let watcher_delay = self.config.watcher_delay;
             let elapsed = now.elapsed().unwrap_or_default().as_millis() as u64;
             let delay = max(0, watcher_delay.saturating_sub(elapsed));
-            sleep(Duration::from_millis(delay)).await;
+
+            match &mut self.head_subscription {
+                Some(subscription) if subscribed_head.is_some() => {
+                    subscription.changed(HEAD_WAIT).await
+                }
+                _ => sleep(Duration::from_millis(delay)).await,
+            }
         }
 
         Ok(())
     }
 
-    async fn update_system_config(&mut self) -> Result<()> {
-        let (last_update_block, _) = self.system_config_update;
-
-        if last_update_block < self.current_block {
-            let to_block = last_update_block + 1000;
-            let filter = Filter::new()
-                .address(self.config.chain.system_config_contract)
-                .topic0(*CONFIG_UPDATE_TOPIC)
-                .from_block(last_update_block + 1)
-                .to_block(to_block);
-
-            let updates = self.provider.get_logs(&filter).await?;
-            let update = updates.into_iter().next();
-
-            let update_block = update.as_ref().and_then(|update| update.block_number);
-            let update = update.and_then(|update| SystemConfigUpdate::try_from(update).ok());
-
-            if let Some((update_block, update)) = update_block.zip(update) {
-                let mut config = self.system_config;
-                match update {
-                    SystemConfigUpdate::BatchSender(addr) => {
-                        config.batch_sender = addr;
-                    }
-                    SystemConfigUpdate::Fees(overhead, scalar) => {
-                        config.l1_fee_overhead = overhead;
-                        config.l1_fee_scalar = scalar;
-                    }
-                    SystemConfigUpdate::Gas(gas) => {
-                        config.gas_limit = gas;
-                    }
-                    SystemConfigUpdate::UnsafeBlockSigner(addr) => {
-                        config.unsafe_block_signer = addr;
-                    }
-                }
+    /// Fetches the system config updates up to the current block if needed, and
+    /// returns the system config of the block
+    async fn update_system_config(&mut self, block: &Block<Transaction>) -> Result<SystemConfig> {
+        let next_fetch_block = self.with_system_configs(|tracker| tracker.next_fetch_block());
+
+        if next_fetch_block <= self.current_block {
+            let to_block = (next_fetch_block + 999)
+                .min(self.head_block)
+                .max(self.current_block);
 
-                self.system_config_update = (update_block.as_u64(), Some(config));
+            let logs = if self.config.chain.meta.enable_config_updates {
+                let filter = Filter::new()
+                    .address(self.config.chain.system_config_contract)
+                    .topic0(*CONFIG_UPDATE_TOPIC)
+                    .from_block(next_fetch_block)
+                    .to_block(to_block);
+
+                self.provider.get_logs(&filter).await?
             } else {
-                self.system_config_update = (to_block, None);
-            }
+                Vec::new()
+            };
+
+            self.with_system_configs(|tracker| tracker.add_logs(logs, to_block));
         }
 
-        let (last_update_block, next_config) = self.system_config_update;
+        let hash = block.hash.ok_or(eyre::eyre!("block not included"))?;
+        let timestamp = block.timestamp.as_u64();
+        let chain = &self.config.chain;
 
-        if last_update_block == self.current_block {
-            if let Some(next_config) = next_config {
-                tracing::info!("system config updated");
-                tracing::debug!("{:?}", next_config);
-                self.system_config = next_config;
-            }
-        }
+        self.with_system_configs(|tracker| {
+            tracker.advance(self.current_block, hash, timestamp, chain)
+        })
+    }
 
-        Ok(())
+    /// Runs `f` on the system config history
+    fn with_system_configs<T>(&self, f: impl FnOnce(&mut SystemConfigTracker) -> T) -> T {
+        let mut system_configs = self.system_configs.lock().unwrap();
+        f(system_configs
+            .as_mut()
+            .expect("system config history is set on creation"))
     }
 
-    fn check_reorg(&self) -> bool {
-        let len = self.unfinalized_blocks.len();
-        if len >= 2 {
-            let last = self.unfinalized_blocks[len - 1];
-            let parent = self.unfinalized_blocks[len - 2];
-            last.parent_hash != parent.hash
+    /// Returns true if the block does not build on the last ingested block
+    fn check_reorg(&self, block: &BlockInfo) -> bool {
+        self.unfinalized_blocks
+            .last()
+            .map(|parent| parent.hash != block.parent_hash)
+            .unwrap_or(false)
+    }
+
+    fn report_reorg(&self, ancestor: Option<BlockInfo>) {
+        let Some(ancestor) = ancestor else {
+            tracing::warn!(
+                "L1 reorg at block {} reaches past all {} unfinalized blocks",
+                self.current_block,
+                self.unfinalized_blocks.len()
+            );
+            return;
+        };
+
+        let depth = self.current_block - ancestor.number - 1;
+        metrics::L1_REORG_DEPTH.observe(depth as f64);
+
+        if depth > DEEP_REORG_DEPTH {
+            tracing::warn!(
+                "deep L1 reorg of {} blocks at block {}, common ancestor {} {:?}",
+                depth,
+                self.current_block,
+                ancestor.number,
+                ancestor.hash
+            );
         } else {
-            false
+            tracing::info!(
+                "L1 reorg of {} blocks at block {}, common ancestor {}",
+                depth,
+                self.current_block,
+                ancestor.number
+            );
         }
     }
 
@@ -422,13 +510,54 @@ impl InnerWatcher {
             .as_u64())
     }
 
-    async fn get_block(&self, block_num: u64) -> Result<Block<Transaction>> {
-        self.provider
-            .get_block_with_txs(block_num)
-            .await?
+    async fn get_block(&mut self, block_num: u64) -> Result<Block<Transaction>> {
+        if !self.prefetched.contains_key(&block_num) {
+            self.prefetch(block_num).await?;
+        }
+
+        self.prefetched
+            .remove(&block_num)
             .ok_or(eyre::eyre!("block not found"))
     }
 
+    /// Fetches the blocks from `block_num` on in one batch. The further behind the
+    /// head the watcher is, the more blocks are fetched, up to the prefetch window.
+    /// Blocks are still ingested one by one, so reorgs are detected as usual, and a
+    /// block that was reorged out after it was prefetched shows up as a reorg once
+    /// the next block no longer builds on it.
+    async fn prefetch(&mut self, block_num: u64) -> Result<()> {
+        let window = self
+            .config
+            .l1_prefetch_window
+            .unwrap_or(DEFAULT_PREFETCH_WINDOW)
+            .max(1) as u64;
+        let last_block = (block_num + window - 1).min(self.head_block).max(block_num);
+
+        let params = (block_num..=last_block)
+            .map(|number| (BlockNumber::Number(number.into()), true))
+            .collect::<Vec<_>>();
+
+        let client: &L1Client = self.provider.as_ref().as_ref();
+        let blocks = client
+            .request_batch::<_, Option<Block<Transaction>>>("eth_getBlockByNumber", &params)
+            .await;
+
+        self.prefetched.clear();
+        for (number, block) in (block_num..=last_block).zip(blocks) {
+            match block {
+                Ok(Some(block)) => {
+                    self.prefetched.insert(number, block);
+                }
+                // later blocks are fetched again when the watcher reaches them
+                Ok(None) | Err(_) if number > block_num => break,
+                Ok(None) => eyre::bail!("block not found"),
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(())
+    }
+
     async fn get_deposits(&mut self, block_num: u64) -> Result<Vec<UserDeposited>> {
         match self.deposits.remove(&block_num) {
             Some(deposits) => Ok(deposits),
@@ -489,6 +618,9 @@ impl L1Info {
                 .ok_or(eyre::eyre!("block is pre london"))?,
             mix_hash: block.mix_hash.ok_or(eyre::eyre!("block not included"))?,
             state_root: block.state_root,
+            parent_beacon_root: block.parent_beacon_block_root,
+            excess_blob_gas: block.excess_blob_gas.map(|gas| gas.as_u64()),
+            blob_base_fee: utils::calc_blob_base_fee(block.excess_blob_gas),
         };
 
         let batcher_transactions =
@@ -504,6 +636,7 @@ impl L1Info {
     }
 }
 
+/// Collects the batcher transactions of the block in transaction order
 fn create_batcher_transactions(
     block: &Block<Transaction>,
     batch_sender: Address,
@@ -512,21 +645,45 @@ fn create_batcher_transactions(
     block
         .transactions
         .iter()
-        .filter(|tx| tx.from == batch_sender && tx.to.map(|to| to == batch_inbox).unwrap_or(false))
-        .map(|tx| tx.input.to_vec())
+        .filter(|tx| is_batcher_tx(tx, batch_sender, batch_inbox))
+        .map(|tx| BatcherTransactionData {
+            hash: tx.hash,
+            calldata: tx.input.to_vec(),
+            blob_hashes: blob_versioned_hashes(tx),
+        })
         .collect()
 }
 
+fn is_batcher_tx(tx: &Transaction, batch_sender: Address, batch_inbox: Address) -> bool {
+    tx.from == batch_sender && tx.to.map(|to| to == batch_inbox).unwrap_or(false)
+}
+
 fn start_watcher(
     l1_start_block: u64,
     l2_start_block: u64,
+    system_configs: Arc<Mutex<Option<SystemConfigTracker>>>,
     config: Arc<Config>,
 ) -> Result<(JoinHandle<()>, mpsc::Receiver<BlockUpdate>)> {
+    if let Some(path) = &config.l1_fixture {
+        tracing::info!(
+            "replaying L1 fixture {:?} from block {}",
+            path,
+            l1_start_block
+        );
+        return Ok(fixture::start_replay(path.clone(), l1_start_block));
+    }
+
     let (block_update_sender, block_update_receiver) = mpsc::channel(1000);
 
     let handle = spawn(async move {
-        let mut watcher =
-            InnerWatcher::new(config, block_update_sender, l1_start_block, l2_start_block).await;
+        let mut watcher = InnerWatcher::new(
+            config,
+            block_update_sender,
+            l1_start_block,
+            l2_start_block,
+            system_configs,
+        )
+        .await;
 
         loop {
             tracing::debug!("fetching L1 data for block {}", watcher.current_block);
@@ -543,80 +700,27 @@ fn start_watcher(
     Ok((handle, block_update_receiver))
 }
 
-enum SystemConfigUpdate {
-    BatchSender(Address),
-    Fees(U256, U256),
-    Gas(U256),
-    UnsafeBlockSigner(Address),
-}
-
-impl TryFrom<Log> for SystemConfigUpdate {
-    type Error = eyre::Report;
-
-    fn try_from(log: Log) -> Result<Self> {
-        let version = log
-            .topics
-            .get(1)
-            .ok_or(eyre::eyre!("invalid system config update"))?
-            .to_low_u64_be();
-
-        if version != 0 {
-            return Err(eyre::eyre!("invalid system config update"));
-        }
-
-        let update_type = log
-            .topics
-            .get(2)
-            .ok_or(eyre::eyre!("invalid system config update"))?
-            .to_low_u64_be();
-
-        match update_type {
-            0 => {
-                let addr_bytes = log
-                    .data
-                    .get(76..96)
-                    .ok_or(eyre::eyre!("invalid system config update"))?;
-
-                let addr = Address::from_slice(addr_bytes);
-                Ok(Self::BatchSender(addr))
-            }
-            1 => {
-                let fee_overhead = log
-                    .data
-                    .get(64..96)
-                    .ok_or(eyre::eyre!("invalid system config update"))?;
-
-                let fee_scalar = log
-                    .data
-                    .get(96..128)
-                    .ok_or(eyre::eyre!("invalid system config update"))?;
-
-                let fee_overhead = U256::from_big_endian(fee_overhead);
-                let fee_scalar = U256::from_big_endian(fee_scalar);
+/// Walks back the ingested blocks to the latest one that is still on the canonical
+/// chain
+async fn find_common_ancestor<M: Middleware>(
+    provider: &M,
+    blocks: &[BlockInfo],
+) -> Result<Option<BlockInfo>>
+where
+    M::Error: 'static,
+{
+    for block in blocks.iter().rev() {
+        let canonical = provider
+            .get_block(block.number)
+            .await?
+            .and_then(|block| block.hash);
 
-                Ok(Self::Fees(fee_overhead, fee_scalar))
-            }
-            2 => {
-                let gas_bytes = log
-                    .data
-                    .get(64..96)
-                    .ok_or(eyre::eyre!("invalid system config update"))?;
-
-                let gas = U256::from_big_endian(gas_bytes);
-                Ok(Self::Gas(gas))
-            }
-            3 => {
-                let addr_bytes = log
-                    .data
-                    .get(76..96)
-                    .ok_or(eyre::eyre!("invalid system config update"))?;
-
-                let addr = Address::from_slice(addr_bytes);
-                Ok(Self::UnsafeBlockSigner(addr))
-            }
-            _ => Err(eyre::eyre!("invalid system config update")),
+        if canonical == Some(block.hash) {
+            return Ok(Some(*block));
         }
     }
+
+    Ok(None)
 }
 
 pub(crate) fn generate_http_provider(url: &str) -> Arc<Provider<RetryClient<Http>>> {
@@ -629,3 +733,63 @@ pub(crate) fn generate_http_provider(url: &str) -> Arc<Provider<RetryClient<Http
     let client = RetryClient::new(http, policy, 100, 50);
     Arc::new(Provider::new(client))
 }
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        providers::Provider,
+        types::{Block, H256, U64},
+    };
+
+    use crate::common::BlockInfo;
+
+    use super::find_common_ancestor;
+
+    fn block_info(number: u64, hash: H256) -> BlockInfo {
+        BlockInfo {
+            hash,
+            number,
+            parent_hash: H256::zero(),
+            timestamp: number * 12,
+        }
+    }
+
+    fn block(number: u64, hash: H256) -> Block<H256> {
+        Block {
+            number: Some(U64::from(number)),
+            hash: Some(hash),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_find_common_ancestor() {
+        let ingested = (10..15)
+            .map(|number| block_info(number, H256::from_low_u64_be(number)))
+            .collect::<Vec<_>>();
+
+        // blocks 13 and 14 were replaced, responses are returned last in first out
+        let (provider, mock) = Provider::mocked();
+        mock.push::<Block<H256>, _>(block(12, H256::from_low_u64_be(12)))
+            .unwrap();
+        mock.push::<Block<H256>, _>(block(13, H256::repeat_byte(0xaa)))
+            .unwrap();
+        mock.push::<Block<H256>, _>(block(14, H256::repeat_byte(0xbb)))
+            .unwrap();
+
+        let ancestor = find_common_ancestor(&provider, &ingested).await.unwrap();
+        assert_eq!(ancestor, Some(ingested[2]));
+    }
+
+    #[tokio::test]
+    async fn test_reorg_past_unfinalized_blocks() {
+        let ingested = vec![block_info(10, H256::from_low_u64_be(10))];
+
+        let (provider, mock) = Provider::mocked();
+        mock.push::<Block<H256>, _>(block(10, H256::repeat_byte(0xaa)))
+            .unwrap();
+
+        let ancestor = find_common_ancestor(&provider, &ingested).await.unwrap();
+        assert_eq!(ancestor, None);
+    }
+}
@@ -0,0 +1,891 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{Debug, Display},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use ethers::providers::{
+    Http, HttpRateLimitRetryPolicy, JsonRpcClient, JsonRpcError, Provider, ProviderError,
+    RetryClient, RpcError,
+};
+use eyre::Result;
+use futures::future::join_all;
+use reqwest::Url;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::{config::Config, telemetry::metrics};
+
+/// How long an endpoint is ranked last after its first failure. Doubles with every
+/// consecutive failure, up to `MAX_BACKOFF`.
+const BASE_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The longest an endpoint is ranked last after failing
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Weight of the newest sample in the latency moving average
+const LATENCY_WEIGHT: f64 = 0.2;
+
+/// Timeout of a batch request, which may return many full blocks at once
+const BATCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A provider for the L1 RPC endpoints in the config
+pub type L1Provider = Provider<L1Client>;
+
+/// Creates a provider that spreads L1 requests over the configured endpoints.
+/// Panics if a URL is invalid, or the quorum exceeds the number of endpoints.
+pub fn generate_l1_provider(config: &Config) -> Arc<L1Provider> {
+    let client = L1Client::from_config(config).expect("invalid L1 provider config");
+    Arc::new(Provider::new(client))
+}
+
+/// A JSON-RPC client over several L1 endpoints. Requests go to the healthiest
+/// endpoint first, ranked by recent failures and then by latency, and fail over to
+/// the next endpoint on error. Blocks, logs, calls and storage requested at a block
+/// number or hash are checked against `quorum` endpoints, which must return the same
+/// block hash, logs or data, so a single faulty or reorged endpoint cannot feed the
+/// pipeline data the others do not know.
+#[derive(Debug)]
+pub struct L1Client<C = RetryClient<Http>> {
+    endpoints: Vec<Endpoint<C>>,
+    quorum: usize,
+}
+
+#[derive(Debug)]
+struct Endpoint<C> {
+    client: C,
+    /// The endpoint host, used in logs instead of the URL, which may hold an API key
+    host: String,
+    /// The endpoint position in the config, used as metrics label
+    label: String,
+    health: Mutex<Health>,
+    /// Sends batch requests to the endpoint, if it is reached over HTTP
+    batch: Option<BatchTransport>,
+}
+
+/// Sends JSON-RPC batches over HTTP, which the ethers transports do not support
+#[derive(Debug)]
+struct BatchTransport {
+    client: reqwest::Client,
+    url: Url,
+    /// Cleared once the endpoint rejects a batch
+    supported: AtomicBool,
+}
+
+/// A response in a JSON-RPC batch
+#[derive(Deserialize)]
+struct BatchResponse {
+    id: usize,
+    #[serde(default)]
+    result: Value,
+    error: Option<JsonRpcError>,
+}
+
+#[derive(Debug, Default)]
+struct Health {
+    /// Moving average of the request latency in milliseconds
+    latency_ms: f64,
+    consecutive_failures: u32,
+    /// Until when the endpoint is ranked behind the healthy ones
+    backoff_until: Option<Instant>,
+}
+
+/// An error of the multi-endpoint L1 client
+#[derive(Debug, Error)]
+pub enum L1ClientError {
+    #[error(transparent)]
+    Endpoint(#[from] ProviderError),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+    #[error(transparent)]
+    Response(JsonRpcError),
+    #[error("no response to request {0} of the batch")]
+    MissingResponse(usize),
+    #[error("only {agreeing} of {quorum} required L1 endpoints agree on {method}")]
+    NoQuorum {
+        method: String,
+        agreeing: usize,
+        quorum: usize,
+    },
+}
+
+impl RpcError for L1ClientError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            Self::Endpoint(err) => err.as_error_response(),
+            Self::Response(err) => Some(err),
+            _ => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            Self::Endpoint(err) => err.as_serde_error(),
+            Self::Serde(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<L1ClientError> for ProviderError {
+    fn from(err: L1ClientError) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(err))
+    }
+}
+
+impl L1Client {
+    /// Creates a client over the L1 RPC endpoints in the config
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let urls = std::iter::once(&config.l1_rpc_url)
+            .chain(&config.l1_fallback_rpc_urls)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        Self::from_urls(&urls, config.l1_rpc_quorum.unwrap_or(1))
+    }
+
+    /// Creates a client over HTTP endpoints, the first being the primary one
+    pub fn from_urls(urls: &[String], quorum: usize) -> Result<Self> {
+        // with a single endpoint there is nothing to fail over to, so keep retrying it
+        let max_retry = if urls.len() > 1 { 3 } else { 100 };
+
+        let mut endpoints = Vec::new();
+        let mut batches = Vec::new();
+        for url in urls {
+            let url = Url::parse(url)?;
+            let host = url.host_str().unwrap_or_default().to_string();
+
+            let client = reqwest::ClientBuilder::new()
+                .timeout(Duration::from_secs(5))
+                .build()?;
+            let http = Http::new_with_client(url.clone(), client);
+            let policy = Box::new(HttpRateLimitRetryPolicy);
+            endpoints.push((host, RetryClient::new(http, policy, max_retry, 50)));
+
+            batches.push(BatchTransport {
+                client: reqwest::ClientBuilder::new()
+                    .timeout(BATCH_TIMEOUT)
+                    .build()?,
+                url,
+                supported: AtomicBool::new(true),
+            });
+        }
+
+        let mut client = Self::new(endpoints, quorum)?;
+        for (endpoint, batch) in client.endpoints.iter_mut().zip(batches) {
+            endpoint.batch = Some(batch);
+        }
+
+        Ok(client)
+    }
+}
+
+impl<C: JsonRpcClient> L1Client<C> {
+    /// Creates a client over the given endpoints and their hosts, the first being
+    /// the primary one. A quorum of 1 disables the agreement check.
+    pub fn new(endpoints: Vec<(String, C)>, quorum: usize) -> Result<Self> {
+        if endpoints.is_empty() {
+            eyre::bail!("no L1 endpoints");
+        }
+
+        if quorum == 0 || quorum > endpoints.len() {
+            eyre::bail!(
+                "L1 quorum must be between 1 and the number of endpoints ({})",
+                endpoints.len()
+            );
+        }
+
+        let endpoints = endpoints
+            .into_iter()
+            .enumerate()
+            .map(|(i, (host, client))| Endpoint {
+                client,
+                host,
+                label: i.to_string(),
+                health: Mutex::new(Health::default()),
+                batch: None,
+            })
+            .collect();
+
+        Ok(Self { endpoints, quorum })
+    }
+
+    /// Sends a request with each of the params, as a single JSON-RPC batch if the best
+    /// ranked endpoint supports batches, or as concurrent requests otherwise. Blocks
+    /// that have to be checked against a quorum are never batched.
+    pub async fn request_batch<T, R>(
+        &self,
+        method: &str,
+        params: &[T],
+    ) -> Vec<Result<R, L1ClientError>>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        if self.quorum == 1 {
+            let index = self.ranked()[0];
+            if let Some(responses) = self.send_batch(index, method, params).await {
+                return responses
+                    .into_iter()
+                    .map(|response| Ok(serde_json::from_value(response?)?))
+                    .collect();
+            }
+        }
+
+        join_all(params.iter().map(|params| self.request(method, params))).await
+    }
+
+    /// Sends a batch to the endpoint. Returns `None` if the endpoint does not take
+    /// batches or the batch failed, so the requests can be sent one by one.
+    async fn send_batch<T: Serialize>(
+        &self,
+        index: usize,
+        method: &str,
+        params: &[T],
+    ) -> Option<Vec<Result<Value, L1ClientError>>> {
+        let endpoint = &self.endpoints[index];
+        let batch = endpoint
+            .batch
+            .as_ref()
+            .filter(|batch| batch.supported.load(Ordering::Relaxed))?;
+
+        let requests = params
+            .iter()
+            .enumerate()
+            .map(|(id, params)| json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params}))
+            .collect::<Vec<_>>();
+
+        let start = Instant::now();
+        let response = async {
+            let body = serde_json::to_vec(&requests)?;
+            let response = batch
+                .client
+                .post(batch.url.clone())
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body)
+                .send()
+                .await?
+                .error_for_status()?;
+
+            Ok::<_, eyre::Report>(serde_json::from_slice::<Value>(&response.bytes().await?)?)
+        }
+        .await;
+
+        let responses = match response {
+            Ok(Value::Array(responses)) => responses,
+            Ok(response) if rejects_batches(&response) => {
+                tracing::info!("L1 endpoint {} does not support batches", endpoint.host);
+                batch.supported.store(false, Ordering::Relaxed);
+                return None;
+            }
+            Ok(response) => {
+                let err = eyre::eyre!("unexpected batch response: {}", response);
+                self.record_failure(index, method, &err);
+                return None;
+            }
+            Err(err) => {
+                self.record_failure(index, method, &err);
+                return None;
+            }
+        };
+
+        self.record_success(index, start.elapsed());
+
+        let mut results = (0..params.len())
+            .map(|id| Err(L1ClientError::MissingResponse(id)))
+            .collect::<Vec<_>>();
+
+        for response in responses {
+            let Ok(response) = serde_json::from_value::<BatchResponse>(response) else {
+                continue;
+            };
+
+            if let Some(result) = results.get_mut(response.id) {
+                *result = match response.error {
+                    Some(err) => Err(L1ClientError::Response(err)),
+                    None => Ok(response.result),
+                };
+            }
+        }
+
+        Some(results)
+    }
+
+    /// The endpoint indexes, healthy endpoints first and then by latency
+    fn ranked(&self) -> Vec<usize> {
+        let now = Instant::now();
+        let mut ranked = self
+            .endpoints
+            .iter()
+            .enumerate()
+            .map(|(i, endpoint)| {
+                let health = endpoint.health.lock().unwrap();
+                let backoff = health.backoff_until.filter(|until| *until > now);
+                (i, backoff, health.latency_ms)
+            })
+            .collect::<Vec<_>>();
+
+        ranked.sort_by(|(_, a_backoff, a_latency), (_, b_backoff, b_latency)| {
+            a_backoff
+                .cmp(b_backoff)
+                .then(a_latency.total_cmp(b_latency))
+        });
+
+        ranked.into_iter().map(|(i, _, _)| i).collect()
+    }
+
+    /// Sends the request to a single endpoint and records its health
+    async fn query(
+        &self,
+        index: usize,
+        method: &str,
+        params: &Value,
+    ) -> Result<Value, ProviderError> {
+        let endpoint = &self.endpoints[index];
+        let start = Instant::now();
+        let result = endpoint
+            .client
+            .request::<_, Value>(method, params)
+            .await
+            .map_err(Into::into);
+
+        match &result {
+            Ok(_) => self.record_success(index, start.elapsed()),
+            Err(err) => self.record_failure(index, method, err),
+        }
+
+        result
+    }
+
+    fn record_success(&self, index: usize, elapsed: Duration) {
+        let endpoint = &self.endpoints[index];
+        let mut health = endpoint.health.lock().unwrap();
+
+        let latency = elapsed.as_secs_f64() * 1000.0;
+        health.latency_ms = if health.latency_ms == 0.0 {
+            latency
+        } else {
+            LATENCY_WEIGHT * latency + (1.0 - LATENCY_WEIGHT) * health.latency_ms
+        };
+        health.consecutive_failures = 0;
+        health.backoff_until = None;
+
+        metrics::L1_ENDPOINT_LATENCY
+            .with_label_values(&[&endpoint.label])
+            .set(health.latency_ms as i64);
+    }
+
+    fn record_failure(&self, index: usize, method: &str, err: &dyn Display) {
+        let endpoint = &self.endpoints[index];
+        let mut health = endpoint.health.lock().unwrap();
+
+        let backoff = BASE_BACKOFF
+            .saturating_mul(1 << health.consecutive_failures.min(16))
+            .min(MAX_BACKOFF);
+        health.consecutive_failures += 1;
+        health.backoff_until = Some(Instant::now() + backoff);
+
+        metrics::L1_ENDPOINT_FAILURES
+            .with_label_values(&[&endpoint.label])
+            .inc();
+        tracing::warn!(
+            "L1 endpoint {} failed on {}: {}",
+            endpoint.host,
+            method,
+            err
+        );
+    }
+
+    /// Tries the endpoints in rank order until one succeeds
+    async fn request_any(&self, method: &str, params: &Value) -> Result<Value, L1ClientError> {
+        let mut last_err = None;
+        for index in self.ranked() {
+            match self.query(index, method, params).await {
+                Ok(value) => return Ok(value),
+                Err(err) => last_err = Some(err),
+            }
+        }
+
+        Err(last_err.expect("at least one endpoint").into())
+    }
+
+    /// Queries the best ranked `quorum` endpoints, and all others as well if they
+    /// fail or disagree. Returns the response at least `quorum` endpoints agree on.
+    async fn request_agreed(&self, method: &str, params: &Value) -> Result<Value, L1ClientError> {
+        let ranked = self.ranked();
+        let (first, rest) = ranked.split_at(self.quorum);
+
+        let mut responses = self.query_all(first, method, params).await;
+        let mut votes = Votes::count(method, &responses);
+        if votes.agreeing() < self.quorum {
+            responses.extend(self.query_all(rest, method, params).await);
+            votes = Votes::count(method, &responses);
+        }
+
+        if votes.responses.len() > 1 {
+            metrics::L1_ENDPOINT_DISAGREEMENTS.inc();
+            let keys = responses
+                .iter()
+                .filter_map(|(i, response)| {
+                    let key = response
+                        .as_ref()
+                        .ok()
+                        .and_then(|value| agreement_key(method, value))?;
+                    Some(format!("{}: {}", self.endpoints[*i].host, key))
+                })
+                .collect::<Vec<_>>();
+            tracing::warn!(
+                "L1 endpoints disagree on {} {}: {}",
+                method,
+                params,
+                keys.join(", ")
+            );
+        }
+
+        let agreeing = votes.agreeing();
+        if agreeing >= self.quorum {
+            return Ok(votes.into_best().unwrap_or_default());
+        }
+
+        if votes.empty {
+            return Ok(Value::Null);
+        }
+
+        if votes.responses.is_empty() {
+            if let Some((_, Err(err))) = responses.pop() {
+                return Err(err.into());
+            }
+        }
+
+        Err(L1ClientError::NoQuorum {
+            method: method.to_string(),
+            agreeing,
+            quorum: self.quorum,
+        })
+    }
+
+    async fn query_all(
+        &self,
+        indexes: &[usize],
+        method: &str,
+        params: &Value,
+    ) -> Vec<(usize, Result<Value, ProviderError>)> {
+        let queries = indexes.iter().map(|i| async move {
+            let response = self.query(*i, method, params).await;
+            (*i, response)
+        });
+
+        join_all(queries).await
+    }
+}
+
+#[async_trait]
+impl<C: JsonRpcClient> JsonRpcClient for L1Client<C> {
+    type Error = L1ClientError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let params = serde_json::to_value(params)?;
+        let value = if self.quorum > 1 && is_pinned_to_block(method, &params) {
+            self.request_agreed(method, &params).await?
+        } else {
+            self.request_any(method, &params).await?
+        };
+
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+/// Whether a response to a batch is an error rejecting batches themselves, rather than
+/// a failure of the endpoint such as rate limiting
+fn rejects_batches(response: &Value) -> bool {
+    let Some(err) = response
+        .get("error")
+        .and_then(|err| serde_json::from_value::<JsonRpcError>(err.clone()).ok())
+    else {
+        return false;
+    };
+
+    // invalid request or method not found, as returned by endpoints without batches
+    err.code == -32600 || err.code == -32601 || err.message.to_lowercase().contains("batch")
+}
+
+/// The successful responses to a request, grouped by their agreement key
+struct Votes {
+    /// The number of endpoints returning each response, and the response
+    responses: BTreeMap<String, (usize, Value)>,
+    /// Whether no endpoint returned a result, but at least one answered
+    empty: bool,
+}
+
+impl Votes {
+    fn count(method: &str, responses: &[(usize, Result<Value, ProviderError>)]) -> Self {
+        let mut votes = BTreeMap::new();
+        let mut answered = false;
+
+        for (_, response) in responses {
+            let Ok(value) = response else {
+                continue;
+            };
+
+            answered = true;
+            if let Some(key) = agreement_key(method, value) {
+                votes.entry(key).or_insert_with(|| (0, value.clone())).0 += 1;
+            }
+        }
+
+        Self {
+            empty: answered && votes.is_empty(),
+            responses: votes,
+        }
+    }
+
+    /// The number of endpoints agreeing on the most returned response
+    fn agreeing(&self) -> usize {
+        self.responses.values().map(|(n, _)| *n).max().unwrap_or(0)
+    }
+
+    /// The most returned response
+    fn into_best(self) -> Option<Value> {
+        self.responses
+            .into_values()
+            .max_by_key(|(n, _)| *n)
+            .map(|(_, response)| response)
+    }
+}
+
+/// What endpoints must agree on: the hash of a block, the block hash and index of
+/// every log, or the returned data. `None` if the endpoint returned no result.
+fn agreement_key(method: &str, value: &Value) -> Option<String> {
+    match method {
+        "eth_getBlockByNumber" => block_hash(value),
+        "eth_getLogs" => value.as_array().map(|logs| {
+            logs.iter()
+                .map(|log| {
+                    let hash = block_hash_of_log(log).unwrap_or_default();
+                    let index = log["logIndex"].as_str().unwrap_or_default();
+                    format!("{}:{}", hash, index)
+                })
+                .collect::<Vec<_>>()
+                .join(",")
+        }),
+        _ => value.as_str().map(str::to_lowercase),
+    }
+}
+
+fn block_hash(block: &Value) -> Option<String> {
+    block.get("hash")?.as_str().map(str::to_lowercase)
+}
+
+fn block_hash_of_log(log: &Value) -> Option<String> {
+    log.get("blockHash")?.as_str().map(str::to_lowercase)
+}
+
+/// Whether the request reads the chain at a block given by number or hash rather
+/// than by tag. Endpoints may legitimately disagree on tagged blocks like `latest`,
+/// so only these are checked. Covers the blocks, logs, calls and storage reads the
+/// pipeline depends on.
+fn is_pinned_to_block(method: &str, params: &Value) -> bool {
+    match method {
+        "eth_getBlockByNumber" => is_block_number(&params[0]),
+        "eth_getLogs" => {
+            let filter = &params[0];
+            filter.get("blockHash").is_some()
+                || (is_block_number(&filter["fromBlock"]) && is_block_number(&filter["toBlock"]))
+        }
+        "eth_call" => is_block_id(&params[1]),
+        "eth_getStorageAt" => is_block_id(&params[2]),
+        _ => false,
+    }
+}
+
+/// Whether the block param is a number or a hash, rather than a tag
+fn is_block_id(block: &Value) -> bool {
+    is_block_number(block) || block.get("blockHash").is_some()
+}
+
+fn is_block_number(block: &Value) -> bool {
+    block
+        .as_str()
+        .map(|block| block.starts_with("0x"))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::{is_pinned_to_block, L1Client};
+    use crate::l1::mock_server::{self, Response};
+    use crate::telemetry::metrics;
+    use ethers::{
+        providers::{Middleware, MockProvider, Provider},
+        types::{Address, Block, Filter, Log, H256, U256, U64},
+    };
+    use serde_json::{json, Value};
+
+    /// Serves JSON-RPC requests by echoing their first param. Batches are answered in
+    /// reverse order, or rejected if `batches` is false. Returns the URL and the
+    /// number of HTTP requests served.
+    async fn echo_server(batches: bool) -> (String, Arc<AtomicUsize>) {
+        let served = Arc::new(AtomicUsize::new(0));
+        let counter = served.clone();
+
+        let url = mock_server::serve(move |request| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let echo = |request: &Value| json!({"jsonrpc": "2.0", "id": request["id"], "result": request["params"][0]});
+
+            let response = match serde_json::from_str::<Value>(&request.body).unwrap() {
+                Value::Array(requests) if batches => {
+                    Value::Array(requests.iter().rev().map(echo).collect())
+                }
+                Value::Array(_) => json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": {"code": -32600, "message": "batches are not supported"},
+                }),
+                request => echo(&request),
+            };
+
+            Response::ok(response.to_string())
+        })
+        .await;
+
+        (url, served)
+    }
+
+    fn block(number: u64, hash: H256) -> Block<H256> {
+        Block {
+            number: Some(U64::from(number)),
+            hash: Some(hash),
+            ..Default::default()
+        }
+    }
+
+    fn client(mocks: &[MockProvider], quorum: usize) -> Provider<L1Client<MockProvider>> {
+        let endpoints = mocks
+            .iter()
+            .enumerate()
+            .map(|(i, mock)| (format!("node-{}", i), mock.clone()))
+            .collect();
+
+        Provider::new(L1Client::new(endpoints, quorum).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_failover() {
+        let failing = MockProvider::new();
+        let healthy = MockProvider::new();
+        healthy.push::<U64, _>(U64::from(11)).unwrap();
+        healthy.push::<U64, _>(U64::from(10)).unwrap();
+
+        let provider = client(&[failing, healthy], 1);
+
+        assert_eq!(provider.get_block_number().await.unwrap().as_u64(), 10);
+        assert_eq!(provider.as_ref().ranked(), vec![1, 0]);
+
+        // the failed endpoint is skipped while it backs off
+        assert_eq!(provider.get_block_number().await.unwrap().as_u64(), 11);
+    }
+
+    #[tokio::test]
+    async fn test_quorum_agreement() {
+        let mocks = [MockProvider::new(), MockProvider::new()];
+        for mock in &mocks {
+            mock.push::<Block<H256>, _>(block(5, H256::repeat_byte(1)))
+                .unwrap();
+        }
+
+        let provider = client(&mocks, 2);
+        let fetched = provider.get_block(5).await.unwrap().unwrap();
+
+        assert_eq!(fetched.hash, Some(H256::repeat_byte(1)));
+    }
+
+    #[tokio::test]
+    async fn test_quorum_disagreement() {
+        let mocks = [
+            MockProvider::new(),
+            MockProvider::new(),
+            MockProvider::new(),
+        ];
+        mocks[0]
+            .push::<Block<H256>, _>(block(5, H256::repeat_byte(1)))
+            .unwrap();
+        mocks[1]
+            .push::<Block<H256>, _>(block(5, H256::repeat_byte(2)))
+            .unwrap();
+        mocks[2]
+            .push::<Block<H256>, _>(block(5, H256::repeat_byte(2)))
+            .unwrap();
+
+        let disagreements = metrics::L1_ENDPOINT_DISAGREEMENTS.get();
+        let provider = client(&mocks, 2);
+        let fetched = provider.get_block(5).await.unwrap().unwrap();
+
+        assert_eq!(fetched.hash, Some(H256::repeat_byte(2)));
+        assert!(metrics::L1_ENDPOINT_DISAGREEMENTS.get() > disagreements);
+
+        // without a majority there is no quorum
+        mocks[0]
+            .push::<Block<H256>, _>(block(5, H256::repeat_byte(1)))
+            .unwrap();
+        mocks[1]
+            .push::<Block<H256>, _>(block(5, H256::repeat_byte(2)))
+            .unwrap();
+
+        assert!(provider.get_block(5).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tagged_blocks_skip_quorum() {
+        let mocks = [MockProvider::new(), MockProvider::new()];
+        mocks[0]
+            .push::<Block<H256>, _>(block(7, H256::repeat_byte(1)))
+            .unwrap();
+
+        let provider = client(&mocks, 2);
+        let fetched = provider
+            .get_block(ethers::types::BlockNumber::Latest)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(fetched.number, Some(U64::from(7)));
+    }
+
+    #[tokio::test]
+    async fn test_quorum_storage_and_logs() {
+        let mocks = [
+            MockProvider::new(),
+            MockProvider::new(),
+            MockProvider::new(),
+        ];
+        let slot = H256::repeat_byte(9);
+        mocks[0].push::<H256, _>(H256::repeat_byte(1)).unwrap();
+        mocks[1].push::<H256, _>(slot).unwrap();
+        mocks[2].push::<H256, _>(slot).unwrap();
+
+        let provider = client(&mocks, 2);
+        let fetched = provider
+            .get_storage_at(Address::zero(), H256::zero(), Some(5u64.into()))
+            .await
+            .unwrap();
+
+        assert_eq!(fetched, slot);
+
+        let log = |hash: H256| Log {
+            block_hash: Some(hash),
+            log_index: Some(U256::zero()),
+            ..Default::default()
+        };
+        mocks[0].push::<Vec<Log>, _>(vec![log(slot)]).unwrap();
+        mocks[1]
+            .push::<Vec<Log>, _>(vec![log(H256::repeat_byte(1))])
+            .unwrap();
+
+        let filter = Filter::new().from_block(5).to_block(6);
+        assert!(provider.get_logs(&filter).await.is_err());
+    }
+
+    #[test]
+    fn test_pinned_requests() {
+        let filter = |from: Value| json!([{"fromBlock": from, "toBlock": "0x6"}]);
+
+        assert!(is_pinned_to_block("eth_getLogs", &filter(json!("0x5"))));
+        assert!(!is_pinned_to_block("eth_getLogs", &filter(json!("latest"))));
+        assert!(is_pinned_to_block(
+            "eth_getLogs",
+            &json!([{"blockHash": "0x01"}])
+        ));
+        assert!(is_pinned_to_block("eth_call", &json!([{}, "0x5"])));
+        assert!(is_pinned_to_block(
+            "eth_call",
+            &json!([{}, {"blockHash": "0x01"}])
+        ));
+        assert!(!is_pinned_to_block("eth_call", &json!([{}, "latest"])));
+        assert!(is_pinned_to_block(
+            "eth_getStorageAt",
+            &json!(["0x00", "0x00", "0x5"])
+        ));
+        assert!(!is_pinned_to_block("eth_blockNumber", &json!([])));
+    }
+
+    #[tokio::test]
+    async fn test_batch_request() {
+        let (url, served) = echo_server(true).await;
+        let client = L1Client::from_urls(&[url], 1).unwrap();
+
+        let params = (0..5u64).map(|i| vec![i]).collect::<Vec<_>>();
+        let results = client.request_batch::<_, u64>("echo", &params).await;
+        let results = results.into_iter().map(Result::unwrap).collect::<Vec<_>>();
+
+        assert_eq!(results, vec![0, 1, 2, 3, 4]);
+        assert_eq!(served.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_batch_fallback() {
+        let (url, served) = echo_server(false).await;
+        let client = L1Client::from_urls(&[url], 1).unwrap();
+
+        let params = (0..3u64).map(|i| vec![i]).collect::<Vec<_>>();
+        let results = client.request_batch::<_, u64>("echo", &params).await;
+        let results = results.into_iter().map(Result::unwrap).collect::<Vec<_>>();
+
+        // the rejected batch and one request per param
+        assert_eq!(results, vec![0, 1, 2]);
+        assert_eq!(served.load(Ordering::SeqCst), 4);
+
+        // later batches are not tried again
+        client.request_batch::<_, u64>("echo", &params).await;
+        assert_eq!(served.load(Ordering::SeqCst), 7);
+    }
+
+    #[tokio::test]
+    async fn test_batch_failure_keeps_batches() {
+        let batches = Arc::new(AtomicUsize::new(0));
+        let counter = batches.clone();
+        let url = mock_server::serve(move |request| {
+            let echo = |request: &Value| json!({"jsonrpc": "2.0", "id": request["id"], "result": request["params"][0]});
+
+            let response = match serde_json::from_str::<Value>(&request.body).unwrap() {
+                // the first batch is rate limited
+                Value::Array(_) if counter.fetch_add(1, Ordering::SeqCst) == 0 => json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": {"code": -32005, "message": "rate limit exceeded"},
+                }),
+                Value::Array(requests) => Value::Array(requests.iter().map(echo).collect()),
+                request => echo(&request),
+            };
+
+            Response::ok(response.to_string())
+        })
+        .await;
+        let client = L1Client::from_urls(&[url], 1).unwrap();
+
+        let params = (0..3u64).map(|i| vec![i]).collect::<Vec<_>>();
+        let results = client.request_batch::<_, u64>("echo", &params).await;
+        let results = results.into_iter().map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(results, vec![0, 1, 2]);
+
+        // the endpoint failed, but still supports batches
+        let results = client.request_batch::<_, u64>("echo", &params).await;
+        let results = results.into_iter().map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(results, vec![0, 1, 2]);
+        assert_eq!(batches.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,629 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use ethers::{
+    providers::Middleware,
+    types::{Address, BlockId, Log, TransactionRequest, H256, U256},
+    utils::{id, keccak256},
+};
+use eyre::Result;
+use once_cell::sync::Lazy;
+use tokio::time::sleep;
+
+use crate::config::{ChainConfig, Config, SystemConfig};
+
+use super::provider::generate_l1_provider;
+
+pub(super) static CONFIG_UPDATE_TOPIC: Lazy<H256> =
+    Lazy::new(|| H256::from_slice(&keccak256("ConfigUpdate(uint256,uint8,bytes)")));
+
+/// The storage slot of the unsafe block signer in the SystemConfig contract
+static UNSAFE_BLOCK_SIGNER_SLOT: Lazy<H256> =
+    Lazy::new(|| H256::from_slice(&keccak256("systemconfig.unsafeblocksigner")));
+
+/// Tracks the system config across L1 blocks. The `ConfigUpdate` logs of the
+/// SystemConfig contract are fetched ahead of the chain watcher, and applied in log
+/// order once the watcher reaches their block, since the L1 timestamp of the block
+/// decides how some updates are interpreted. The config of every block that changed
+/// it is kept, so the config at any tracked block can be looked up after a reorg.
+#[derive(Debug)]
+pub struct SystemConfigTracker {
+    /// The system config from each block on, starting with the base block
+    history: BTreeMap<u64, SystemConfig>,
+    /// The last L1 block whose updates have been applied
+    head: u64,
+    /// Fetched updates of the blocks after the head, with the hash of their block
+    pending: BTreeMap<u64, Vec<(H256, SystemConfigUpdate)>>,
+    /// The last L1 block updates have been fetched for
+    fetched_to: u64,
+}
+
+impl SystemConfigTracker {
+    /// Creates a tracker with the system config as of the end of `base_block`
+    pub fn new(base_block: u64, base_config: SystemConfig) -> Self {
+        Self {
+            history: BTreeMap::from([(base_block, base_config)]),
+            head: base_block,
+            pending: BTreeMap::new(),
+            fetched_to: base_block,
+        }
+    }
+
+    /// Returns the system config at the given L1 block, if the tracker has reached it
+    pub fn config_at(&self, block: u64) -> Option<SystemConfig> {
+        if block > self.head {
+            return None;
+        }
+
+        self.history
+            .range(..=block)
+            .next_back()
+            .map(|(_, config)| *config)
+    }
+
+    /// The first L1 block whose update logs have not been fetched yet
+    pub fn next_fetch_block(&self) -> u64 {
+        self.fetched_to + 1
+    }
+
+    /// Adds the update logs fetched for the blocks from [Self::next_fetch_block] through
+    /// `to_block`. Logs that are not valid updates are skipped.
+    pub fn add_logs(&mut self, logs: Vec<Log>, to_block: u64) {
+        let mut logs = logs
+            .into_iter()
+            .filter_map(|log| {
+                let block = log.block_number?.as_u64();
+                let hash = log.block_hash?;
+                let index = log.log_index.unwrap_or_default();
+                Some((block, index, hash, log))
+            })
+            .filter(|(block, ..)| *block > self.fetched_to && *block <= to_block)
+            .collect::<Vec<_>>();
+
+        logs.sort_by_key(|(block, index, ..)| (*block, *index));
+
+        for (block, _, hash, log) in logs {
+            match SystemConfigUpdate::try_from(log) {
+                Ok(update) => self.pending.entry(block).or_default().push((hash, update)),
+                Err(err) => tracing::warn!("skipping system config update in {}: {}", block, err),
+            }
+        }
+
+        self.fetched_to = self.fetched_to.max(to_block);
+    }
+
+    /// Applies the updates of the next L1 block and returns its system config. Blocks
+    /// at or before the head roll the tracker back first, and the base block keeps the
+    /// base config. Fails if the updates of the
+    /// block have not been fetched yet, or were fetched from a block that has since
+    /// been reorged out, in which case they are dropped so they are fetched again.
+    pub fn advance(
+        &mut self,
+        block: u64,
+        block_hash: H256,
+        timestamp: u64,
+        chain: &ChainConfig,
+    ) -> Result<SystemConfig> {
+        if block <= self.head {
+            let base = *self.history.keys().next().expect("history is never empty");
+
+            // the updates of the base block are part of the base config
+            if block == base {
+                return self.rollback(base).ok_or(eyre::eyre!("invalid base block"));
+            }
+
+            self.rollback(block - 1).ok_or(eyre::eyre!(
+                "block {} is before the system config history",
+                block
+            ))?;
+        }
+
+        if block != self.head + 1 {
+            eyre::bail!("expected block {}, got {}", self.head + 1, block);
+        }
+
+        if self.fetched_to < block {
+            eyre::bail!("system config updates of block {} not fetched", block);
+        }
+
+        let updates = self.pending.remove(&block).unwrap_or_default();
+        if updates.iter().any(|(hash, _)| *hash != block_hash) {
+            self.pending.clear();
+            self.fetched_to = self.head;
+            eyre::bail!("system config updates of block {} are reorged out", block);
+        }
+
+        let mut config = self.config_at(self.head).unwrap_or(chain.system_config);
+        let is_ecotone = chain.is_ecotone_active(timestamp);
+
+        for (_, update) in &updates {
+            update.apply(&mut config, is_ecotone);
+        }
+
+        if !updates.is_empty() {
+            tracing::info!("system config updated at L1 block {}", block);
+            tracing::debug!("{:?}", config);
+            self.history.insert(block, config);
+        }
+
+        self.head = block;
+        Ok(config)
+    }
+
+    /// Rolls the tracker back to the end of the given L1 block and returns the system
+    /// config at it. Returns `None` and keeps the tracker as is if the block is before
+    /// the history or after the head.
+    pub fn rollback(&mut self, block: u64) -> Option<SystemConfig> {
+        let config = self.config_at(block)?;
+
+        self.history.split_off(&(block + 1));
+        self.pending.clear();
+        self.head = block;
+        self.fetched_to = block;
+
+        Some(config)
+    }
+}
+
+/// Returns the system config to start the chain watcher with. Past genesis, it is read
+/// from the SystemConfig contract at the given L1 block, retrying until it succeeds.
+pub async fn initial_system_config(
+    config: &Config,
+    l1_block: u64,
+    l2_start_block: u64,
+) -> SystemConfig {
+    if l2_start_block == config.chain.l2_genesis.number
+        || !config.chain.meta.enable_config_updates
+        || config.l1_fixture.is_some()
+    {
+        return config.chain.system_config;
+    }
+
+    let provider = generate_l1_provider(config);
+    loop {
+        match fetch_system_config(&provider, &config.chain, l1_block).await {
+            Ok(system_config) => return system_config,
+            Err(err) => {
+                tracing::warn!(
+                    "failed to read system config at block {}: {}",
+                    l1_block,
+                    err
+                );
+                sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
+
+/// Reads the system config at the end of the given L1 block from the SystemConfig
+/// contract. The fee overhead is zeroed from Ecotone on, like a fee update would, and
+/// the EIP-1559 parameters are only read from Holocene on.
+pub async fn fetch_system_config<M: Middleware>(
+    provider: &M,
+    chain: &ChainConfig,
+    block: u64,
+) -> Result<SystemConfig>
+where
+    M::Error: 'static,
+{
+    let contract = chain.system_config_contract;
+    let block_id = BlockId::from(block);
+
+    let timestamp = provider
+        .get_block(block_id)
+        .await?
+        .ok_or(eyre::eyre!("block {} not found", block))?
+        .timestamp
+        .as_u64();
+
+    let call = |signature: &'static str| async move {
+        let tx = TransactionRequest::new()
+            .to(contract)
+            .data(id(signature).to_vec());
+        let data = provider.call(&tx.into(), Some(block_id)).await?;
+        let word = data
+            .get(0..32)
+            .ok_or(eyre::eyre!("invalid {} return data", signature))?;
+        Ok::<_, eyre::Report>(H256::from_slice(word))
+    };
+
+    let batcher_hash = call("batcherHash()").await?;
+    let gas_limit = U256::from(call("gasLimit()").await?.as_bytes());
+    let overhead = U256::from(call("overhead()").await?.as_bytes());
+    let scalar = U256::from(call("scalar()").await?.as_bytes());
+
+    let signer_slot = provider
+        .get_storage_at(contract, *UNSAFE_BLOCK_SIGNER_SLOT, Some(block_id))
+        .await?;
+
+    let eip_1559_params = if chain.is_holocene_active(timestamp) {
+        let denominator = call("eip1559Denominator()").await?.to_low_u64_be();
+        let elasticity = call("eip1559Elasticity()").await?.to_low_u64_be();
+        (denominator << 32) | elasticity
+    } else {
+        chain.system_config.eip_1559_params
+    };
+
+    let l1_fee_overhead = if chain.is_ecotone_active(timestamp) {
+        U256::zero()
+    } else {
+        overhead
+    };
+
+    Ok(SystemConfig {
+        batch_sender: Address::from(batcher_hash),
+        gas_limit,
+        l1_fee_overhead,
+        l1_fee_scalar: scalar,
+        unsafe_block_signer: Address::from(signer_slot),
+        eip_1559_params,
+    })
+}
+
+/// A decoded `ConfigUpdate` log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SystemConfigUpdate {
+    BatchSender(Address),
+    /// The fee overhead and scalar. From Ecotone on, the overhead is unused and the
+    /// scalar holds the versioned base fee and blob base fee scalars.
+    Fees(U256, U256),
+    Gas(U256),
+    UnsafeBlockSigner(Address),
+    /// The packed EIP-1559 denominator and elasticity, introduced with Holocene
+    Eip1559Params(u64),
+}
+
+impl SystemConfigUpdate {
+    /// Applies the update to the config. Invalid Ecotone scalars are ignored.
+    fn apply(&self, config: &mut SystemConfig, is_ecotone: bool) {
+        match *self {
+            Self::BatchSender(addr) => config.batch_sender = addr,
+            Self::Fees(overhead, scalar) => {
+                if !is_ecotone {
+                    config.l1_fee_overhead = overhead;
+                    config.l1_fee_scalar = scalar;
+                } else if is_valid_ecotone_scalar(scalar) {
+                    config.l1_fee_overhead = U256::zero();
+                    config.l1_fee_scalar = scalar;
+                } else {
+                    tracing::warn!("ignoring invalid ecotone fee scalar {:?}", scalar);
+                }
+            }
+            Self::Gas(gas) => config.gas_limit = gas,
+            Self::UnsafeBlockSigner(addr) => config.unsafe_block_signer = addr,
+            Self::Eip1559Params(params) => config.eip_1559_params = params,
+        }
+    }
+}
+
+impl TryFrom<Log> for SystemConfigUpdate {
+    type Error = eyre::Report;
+
+    fn try_from(log: Log) -> Result<Self> {
+        let version = log
+            .topics
+            .get(1)
+            .ok_or(eyre::eyre!("invalid system config update"))?
+            .to_low_u64_be();
+
+        if version != 0 {
+            eyre::bail!("invalid system config update version: {}", version);
+        }
+
+        let update_type = log
+            .topics
+            .get(2)
+            .ok_or(eyre::eyre!("invalid system config update"))?
+            .to_low_u64_be();
+
+        match update_type {
+            0 => {
+                let data = update_data(&log, 32)?;
+                Ok(Self::BatchSender(Address::from_slice(&data[12..32])))
+            }
+            1 => {
+                let data = update_data(&log, 64)?;
+                let fee_overhead = U256::from_big_endian(&data[0..32]);
+                let fee_scalar = U256::from_big_endian(&data[32..64]);
+                Ok(Self::Fees(fee_overhead, fee_scalar))
+            }
+            2 => {
+                let data = update_data(&log, 32)?;
+                Ok(Self::Gas(U256::from_big_endian(data)))
+            }
+            3 => {
+                let data = update_data(&log, 32)?;
+                Ok(Self::UnsafeBlockSigner(Address::from_slice(&data[12..32])))
+            }
+            4 => {
+                let data = update_data(&log, 32)?;
+                let params = u64::from_be_bytes(data[24..32].try_into()?);
+                Ok(Self::Eip1559Params(params))
+            }
+            _ => eyre::bail!("invalid system config update type: {}", update_type),
+        }
+    }
+}
+
+/// Returns the content of the ABI encoded `bytes` log data, checking its offset and
+/// that it has the expected length
+fn update_data(log: &Log, len: usize) -> Result<&[u8]> {
+    let word = |index: usize| {
+        log.data
+            .get(index * 32..(index + 1) * 32)
+            .map(U256::from_big_endian)
+            .ok_or(eyre::eyre!("invalid system config update"))
+    };
+
+    if word(0)? != U256::from(32) || word(1)? != U256::from(len) {
+        eyre::bail!("invalid system config update data");
+    }
+
+    log.data
+        .get(64..64 + len)
+        .ok_or(eyre::eyre!("invalid system config update"))
+}
+
+/// Checks that an Ecotone fee scalar has a known version and zero padding
+fn is_valid_ecotone_scalar(scalar: U256) -> bool {
+    let mut bytes = [0u8; 32];
+    scalar.to_big_endian(&mut bytes);
+
+    match bytes[0] {
+        0 => bytes[1..28].iter().all(|b| *b == 0),
+        1 => bytes[1..24].iter().all(|b| *b == 0),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use ethers::{
+        abi::{encode, Token},
+        providers::{MockProvider, Provider},
+        types::{Address, Block, Bytes, Log, H256, U256, U64},
+    };
+
+    use crate::config::ChainConfig;
+
+    use super::{
+        fetch_system_config, SystemConfigTracker, SystemConfigUpdate, CONFIG_UPDATE_TOPIC,
+        UNSAFE_BLOCK_SIGNER_SLOT,
+    };
+
+    fn update_log(block: u64, index: u64, update_type: u64, content: Vec<Token>) -> Log {
+        Log {
+            topics: vec![
+                *CONFIG_UPDATE_TOPIC,
+                H256::zero(),
+                H256::from_low_u64_be(update_type),
+            ],
+            data: encode(&[Token::Bytes(encode(&content))]).into(),
+            block_number: Some(U64::from(block)),
+            block_hash: Some(H256::from_low_u64_be(block)),
+            log_index: Some(U256::from(index)),
+            ..Default::default()
+        }
+    }
+
+    fn fees_log(block: u64, index: u64, overhead: u64, scalar: U256) -> Log {
+        update_log(
+            block,
+            index,
+            1,
+            vec![Token::Uint(overhead.into()), Token::Uint(scalar)],
+        )
+    }
+
+    fn advance(tracker: &mut SystemConfigTracker, block: u64, chain: &ChainConfig) {
+        tracker
+            .advance(block, H256::from_low_u64_be(block), 0, chain)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_decode_updates() {
+        let batcher = Address::from_low_u64_be(0xba);
+        let log = update_log(1, 0, 0, vec![Token::Address(batcher)]);
+        assert_eq!(
+            SystemConfigUpdate::try_from(log).unwrap(),
+            SystemConfigUpdate::BatchSender(batcher)
+        );
+
+        let log = fees_log(1, 0, 188, U256::from(684000));
+        assert_eq!(
+            SystemConfigUpdate::try_from(log).unwrap(),
+            SystemConfigUpdate::Fees(U256::from(188), U256::from(684000))
+        );
+
+        let log = update_log(1, 0, 2, vec![Token::Uint(U256::from(30_000_000))]);
+        assert_eq!(
+            SystemConfigUpdate::try_from(log).unwrap(),
+            SystemConfigUpdate::Gas(U256::from(30_000_000))
+        );
+
+        let params = (250u64 << 32) | 6;
+        let log = update_log(1, 0, 4, vec![Token::Uint(U256::from(params))]);
+        assert_eq!(
+            SystemConfigUpdate::try_from(log).unwrap(),
+            SystemConfigUpdate::Eip1559Params(params)
+        );
+
+        let log = update_log(1, 0, 9, vec![Token::Uint(U256::one())]);
+        assert!(SystemConfigUpdate::try_from(log).is_err());
+
+        // the fee update carries two words
+        let log = update_log(1, 0, 1, vec![Token::Uint(U256::one())]);
+        assert!(SystemConfigUpdate::try_from(log).is_err());
+    }
+
+    #[test]
+    fn test_applies_all_updates_in_order() {
+        let chain = ChainConfig::optimism_goerli();
+        let mut tracker = SystemConfigTracker::new(10, chain.system_config);
+
+        let first = Address::from_low_u64_be(1);
+        let second = Address::from_low_u64_be(2);
+        let logs = vec![
+            update_log(12, 3, 2, vec![Token::Uint(U256::from(5))]),
+            update_log(12, 1, 0, vec![Token::Address(first)]),
+            update_log(12, 2, 0, vec![Token::Address(second)]),
+            update_log(14, 0, 2, vec![Token::Uint(U256::from(7))]),
+        ];
+        tracker.add_logs(logs, 20);
+
+        advance(&mut tracker, 11, &chain);
+        advance(&mut tracker, 12, &chain);
+        advance(&mut tracker, 13, &chain);
+        advance(&mut tracker, 14, &chain);
+
+        let config = tracker.config_at(12).unwrap();
+        assert_eq!(config.batch_sender, second);
+        assert_eq!(config.gas_limit, U256::from(5));
+
+        assert_eq!(
+            tracker.config_at(11).unwrap().batch_sender,
+            chain.system_config.batch_sender
+        );
+        assert_eq!(tracker.config_at(13).unwrap().gas_limit, U256::from(5));
+        assert_eq!(tracker.config_at(14).unwrap().gas_limit, U256::from(7));
+        assert_eq!(tracker.config_at(14).unwrap().batch_sender, second);
+        assert!(tracker.config_at(15).is_none());
+    }
+
+    #[test]
+    fn test_ecotone_fee_update() {
+        let mut chain = ChainConfig::optimism_goerli();
+        chain.ecotone_time = 100;
+
+        let mut tracker = SystemConfigTracker::new(0, chain.system_config);
+
+        let mut scalar = [0u8; 32];
+        scalar[0] = 1;
+        scalar[24..28].copy_from_slice(&810949u32.to_be_bytes());
+        scalar[28..32].copy_from_slice(&1368u32.to_be_bytes());
+        let scalar = U256::from_big_endian(&scalar);
+
+        let invalid_scalar = U256::from_big_endian(&[2; 32]);
+
+        tracker.add_logs(
+            vec![
+                fees_log(1, 0, 188, U256::from(684000)),
+                fees_log(2, 0, 188, scalar),
+                fees_log(3, 0, 188, invalid_scalar),
+            ],
+            3,
+        );
+
+        let pre_ecotone = tracker
+            .advance(1, H256::from_low_u64_be(1), 99, &chain)
+            .unwrap();
+        assert_eq!(pre_ecotone.l1_fee_overhead, U256::from(188));
+        assert_eq!(pre_ecotone.l1_fee_scalar, U256::from(684000));
+
+        let ecotone = tracker
+            .advance(2, H256::from_low_u64_be(2), 100, &chain)
+            .unwrap();
+        assert_eq!(ecotone.l1_fee_overhead, U256::zero());
+        assert_eq!(ecotone.ecotone_scalars().unwrap(), (1368, 810949));
+
+        let ignored = tracker
+            .advance(3, H256::from_low_u64_be(3), 102, &chain)
+            .unwrap();
+        assert_eq!(ignored.l1_fee_scalar, scalar);
+    }
+
+    #[test]
+    fn test_reorged_updates() {
+        let chain = ChainConfig::optimism_goerli();
+        let mut tracker = SystemConfigTracker::new(0, chain.system_config);
+
+        tracker.add_logs(
+            vec![update_log(2, 0, 2, vec![Token::Uint(U256::from(5))])],
+            5,
+        );
+        advance(&mut tracker, 1, &chain);
+
+        // the updates were fetched from a different block 2
+        let reorged = tracker.advance(2, H256::from_low_u64_be(0x22), 0, &chain);
+        assert!(reorged.is_err());
+        assert_eq!(tracker.next_fetch_block(), 2);
+
+        tracker.add_logs(Vec::new(), 5);
+        let config = tracker
+            .advance(2, H256::from_low_u64_be(0x22), 0, &chain)
+            .unwrap();
+        assert_eq!(config.gas_limit, chain.system_config.gas_limit);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_system_config() {
+        let mut chain = ChainConfig::optimism_goerli();
+        chain.ecotone_time = 100;
+
+        let batcher = Address::from_low_u64_be(0xba);
+        let signer = Address::from_low_u64_be(0x51);
+        let word = |value: U256| Bytes::from(encode(&[Token::Uint(value)]));
+
+        // responses are returned in reverse order
+        let mock = MockProvider::new();
+        mock.push::<H256, _>(H256::from(signer)).unwrap();
+        mock.push::<Bytes, _>(word(U256::from(684000))).unwrap();
+        mock.push::<Bytes, _>(word(U256::from(188))).unwrap();
+        mock.push::<Bytes, _>(word(U256::from(30_000_000))).unwrap();
+        mock.push::<Bytes, _>(Bytes::from(encode(&[Token::Address(batcher)])))
+            .unwrap();
+        mock.push::<Block<H256>, _>(Block {
+            number: Some(U64::from(10)),
+            timestamp: U256::from(200),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let provider = Provider::new(mock);
+        let config = fetch_system_config(&provider, &chain, 10).await.unwrap();
+
+        assert_eq!(config.batch_sender, batcher);
+        assert_eq!(config.unsafe_block_signer, signer);
+        assert_eq!(config.gas_limit, U256::from(30_000_000));
+        assert_eq!(config.l1_fee_overhead, U256::zero());
+        assert_eq!(config.l1_fee_scalar, U256::from(684000));
+        assert_eq!(config.eip_1559_params, chain.system_config.eip_1559_params);
+
+        let signer_slot = "0x65a7ed542fb37fe237fdfbdd70b31598523fe5b32879e307bae27a0bd9581c08";
+        assert_eq!(
+            *UNSAFE_BLOCK_SIGNER_SLOT,
+            H256::from_str(signer_slot).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_rollback() {
+        let chain = ChainConfig::optimism_goerli();
+        let mut tracker = SystemConfigTracker::new(10, chain.system_config);
+
+        tracker.add_logs(
+            vec![
+                update_log(11, 0, 2, vec![Token::Uint(U256::from(5))]),
+                update_log(13, 0, 2, vec![Token::Uint(U256::from(7))]),
+            ],
+            13,
+        );
+        for block in 11..=13 {
+            advance(&mut tracker, block, &chain);
+        }
+
+        assert!(tracker.rollback(9).is_none());
+        assert_eq!(tracker.rollback(12).unwrap().gas_limit, U256::from(5));
+        assert!(tracker.config_at(13).is_none());
+        assert_eq!(tracker.next_fetch_block(), 13);
+
+        tracker.add_logs(
+            vec![update_log(13, 0, 2, vec![Token::Uint(U256::from(9))])],
+            13,
+        );
+        advance(&mut tracker, 13, &chain);
+        assert_eq!(tracker.config_at(13).unwrap().gas_limit, U256::from(9));
+    }
+}
@@ -1,11 +1,17 @@
 use ethers::{
     providers::{JsonRpcClient, Middleware, Provider},
-    types::{Block, BlockId, H256},
+    types::{Block, BlockId, H256, U256},
 };
 use eyre::{Result, WrapErr};
 
 use super::L1BlockInfo;
 
+/// Minimum blob base fee in wei
+const MIN_BLOB_BASE_FEE: u64 = 1;
+
+/// Controls the maximum rate of change of the blob base fee (Cancun)
+const BLOB_BASE_FEE_UPDATE_FRACTION: u64 = 3338477;
+
 /// Fetches the l1 block info for `block_id` (which can be either a block number or a block hash), using `provider`.
 pub async fn get_l1_block_info<T: Into<BlockId> + Send + Sync, U: JsonRpcClient>(
     block_id: T,
@@ -32,5 +38,57 @@ fn try_create_l1_block_info(block: &Block<H256>) -> Result<L1BlockInfo> {
             .ok_or(eyre::eyre!("base fee missing"))?,
         mix_hash: block.mix_hash.ok_or(eyre::eyre!("mix_hash missing"))?,
         state_root: block.state_root,
+        parent_beacon_root: block.parent_beacon_block_root,
+        excess_blob_gas: block.excess_blob_gas.map(|gas| gas.as_u64()),
+        blob_base_fee: calc_blob_base_fee(block.excess_blob_gas),
     })
 }
+
+/// Computes the EIP-4844 blob base fee from the block's excess blob gas. Blocks from before
+/// Dencun have no excess blob gas and use the minimum blob base fee.
+pub fn calc_blob_base_fee(excess_blob_gas: Option<U256>) -> U256 {
+    match excess_blob_gas {
+        Some(excess_blob_gas) => fake_exponential(
+            U256::from(MIN_BLOB_BASE_FEE),
+            excess_blob_gas,
+            U256::from(BLOB_BASE_FEE_UPDATE_FRACTION),
+        ),
+        None => U256::from(MIN_BLOB_BASE_FEE),
+    }
+}
+
+/// Approximates `factor * e ** (numerator / denominator)` using a Taylor expansion, as
+/// specified by EIP-4844.
+fn fake_exponential(factor: U256, numerator: U256, denominator: U256) -> U256 {
+    let mut i = U256::one();
+    let mut output = U256::zero();
+    let mut numerator_accum = factor.saturating_mul(denominator);
+
+    while !numerator_accum.is_zero() {
+        output = output.saturating_add(numerator_accum);
+        numerator_accum = numerator_accum.saturating_mul(numerator) / (denominator * i);
+        i += U256::one();
+    }
+
+    output / denominator
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::U256;
+
+    use super::{calc_blob_base_fee, BLOB_BASE_FEE_UPDATE_FRACTION};
+
+    #[test]
+    fn test_calc_blob_base_fee() {
+        let fraction = BLOB_BASE_FEE_UPDATE_FRACTION;
+
+        assert_eq!(calc_blob_base_fee(None), U256::one());
+        assert_eq!(calc_blob_base_fee(Some(U256::zero())), U256::one());
+        assert_eq!(calc_blob_base_fee(Some(fraction.into())), U256::from(2));
+        assert_eq!(
+            calc_blob_base_fee(Some((10 * fraction).into())),
+            U256::from(22026)
+        );
+    }
+}
@@ -1,4 +1,3 @@
-use std::sync::mpsc::{channel, Receiver, Sender};
 use std::time::SystemTime;
 
 use ethers::types::{Address, Bytes, Signature, H256};
@@ -6,7 +5,10 @@ use ethers::utils::keccak256;
 use eyre::Result;
 use libp2p::gossipsub::{IdentTopic, Message, MessageAcceptance, TopicHash};
 use ssz_rs::{prelude::*, List, Vector, U256};
-use tokio::sync::watch;
+use tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    watch,
+};
 
 use crate::{common::RawTransaction, engine::ExecutionPayload};
 
@@ -14,7 +16,7 @@ use super::Handler;
 
 pub struct BlockHandler {
     chain_id: u64,
-    block_sender: Sender<ExecutionPayload>,
+    block_sender: UnboundedSender<ExecutionPayload>,
     unsafe_signer_recv: watch::Receiver<Address>,
 }
 
@@ -48,8 +50,8 @@ impl BlockHandler {
     pub fn new(
         chain_id: u64,
         unsafe_recv: watch::Receiver<Address>,
-    ) -> (Self, Receiver<ExecutionPayload>) {
-        let (sender, recv) = channel();
+    ) -> (Self, UnboundedReceiver<ExecutionPayload>) {
+        let (sender, recv) = unbounded_channel();
 
         let handler = Self {
             chain_id,
@@ -163,6 +165,10 @@ impl From<ExecutionPayloadSSZ> for ExecutionPayload {
             base_fee_per_gas: convert_uint(value.base_fee_per_gas),
             block_hash: convert_hash(value.block_hash),
             transactions: convert_tx_list(value.transactions),
+            withdrawals: None,
+            blob_gas_used: None,
+            excess_blob_gas: None,
+            parent_beacon_block_root: None,
         }
     }
 }
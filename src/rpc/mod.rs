@@ -1,6 +1,8 @@
 use std::{fmt::Display, net::SocketAddr, sync::Arc};
 
 use crate::config::Config;
+use crate::derive::trace::{DerivationEvent, DERIVATION_TRACE};
+use crate::l1::{block_cache::L1_BLOCK_CACHE, provider::L1Client, L1BlockInfo};
 
 use eyre::Result;
 
@@ -22,11 +24,22 @@ use serde::{Deserialize, Serialize};
 pub trait Rpc {
     #[method(name = "outputAtBlock")]
     async fn output_at_block(&self, block_number: u64) -> Result<OutputRootResponse, Error>;
+
+    /// Returns the most recent dropped derivation data, oldest first
+    #[method(name = "derivationTrace")]
+    async fn derivation_trace(&self, limit: Option<usize>) -> Result<Vec<DerivationEvent>, Error>;
+
+    /// Returns the info of the canonical L1 block with the given number, from the L1
+    /// block cache if possible
+    #[method(name = "l1BlockInfo")]
+    async fn l1_block_info(&self, block_number: u64) -> Result<L1BlockInfo, Error>;
 }
 
 #[derive(Debug)]
 pub struct RpcServerImpl {
     config: Arc<Config>,
+    /// Fetches the L1 blocks missing from the cache, unless the L1 config is invalid
+    l1_provider: Result<Provider<L1Client>, String>,
 }
 
 #[async_trait]
@@ -66,6 +79,15 @@ impl RpcServer for RpcServerImpl {
             withdrawal_storage_root,
         })
     }
+
+    async fn derivation_trace(&self, limit: Option<usize>) -> Result<Vec<DerivationEvent>, Error> {
+        Ok(DERIVATION_TRACE.recent(limit.unwrap_or(usize::MAX)))
+    }
+
+    async fn l1_block_info(&self, block_number: u64) -> Result<L1BlockInfo, Error> {
+        let l1_provider = convert_err(self.l1_provider.as_ref())?;
+        convert_err(L1_BLOCK_CACHE.get_or_fetch(block_number, l1_provider).await)
+    }
 }
 
 fn convert_err<T, E: Display>(res: Result<T, E>) -> Result<T, Error> {
@@ -93,7 +115,13 @@ pub async fn run_server(config: Arc<Config>) -> Result<SocketAddr> {
         .build(format!("127.0.0.1:{}", port))
         .await?;
     let addr = server.local_addr()?;
-    let rpc_impl = RpcServerImpl { config };
+    let l1_provider = L1Client::from_config(&config)
+        .map(Provider::new)
+        .map_err(|err| format!("invalid L1 provider config: {}", err));
+    let rpc_impl = RpcServerImpl {
+        config,
+        l1_provider,
+    };
     let handle = server.start(rpc_impl.into_rpc())?;
 
     // In this example we don't care about doing shutdown so let's it run forever.
@@ -23,10 +23,11 @@ use crate::{
     driver::{
         engine_driver::EngineDriver,
         sequencing::{self, driver::SequencingDriver},
+        verifier::Verifier,
         Driver,
     },
-    engine::{Engine, EngineApi, ExecutionPayload, ForkchoiceState, Status},
-    l1::generate_http_provider,
+    engine::{Engine, EngineApi, EngineForks, ExecutionPayload, ForkchoiceState, Status},
+    l1::{generate_http_provider, provider::generate_l1_provider},
     specular,
 };
 
@@ -74,6 +75,7 @@ impl Runner {
             SyncMode::Challenge => self.challenge_sync().await,
             SyncMode::Full => self.full_sync().await,
             SyncMode::Checkpoint => self.checkpoint_sync().await,
+            SyncMode::Verify => self.verify_sync().await,
         }
     }
 
@@ -94,6 +96,14 @@ impl Runner {
         Ok(())
     }
 
+    pub async fn verify_sync(&self) -> Result<()> {
+        tracing::info!("starting verifier");
+
+        let mut verifier =
+            Verifier::from_config(self.config.clone(), self.shutdown_recv.clone()).await?;
+        verifier.start().await
+    }
+
     pub async fn checkpoint_sync(&self) -> Result<()> {
         tracing::info!("starting checkpoint sync");
 
@@ -144,7 +154,8 @@ impl Runner {
         let checkpoint_hash = checkpoint_block.hash.expect("block hash is missing");
         tracing::info!("using checkpoint block {}", checkpoint_hash);
 
-        let engine_api = EngineApi::new(&self.config.l2_engine_url, &self.config.jwt_secret);
+        let engine_api = EngineApi::new(&self.config.l2_engine_url, &self.config.jwt_secret)
+            .with_forks(EngineForks::from(&self.config.chain));
         while !engine_api.is_available().await {
             self.check_shutdown()?;
             sleep(Duration::from_secs(3)).await;
@@ -228,7 +239,7 @@ impl Runner {
                     let cfg = specular::sequencing::config::Config::new(&self.config);
                     let l2_provider = generate_http_provider(&self.config.l2_rpc_url);
                     let policy = specular::sequencing::AttributesBuilder::new(cfg, l2_provider);
-                    let l1_provider = generate_http_provider(&self.config.l1_rpc_url);
+                    let l1_provider = generate_l1_provider(&self.config);
                     let sequencing_src = sequencing::Source::new(policy, l1_provider.clone());
                     SequencingDriver::new(
                         engine_driver,
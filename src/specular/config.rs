@@ -5,7 +5,7 @@ use std::{path::Path, str::FromStr};
 
 use crate::{
     common::{BlockInfo, Epoch},
-    config::{ChainConfig, ProtocolMetaConfig, SystemConfig},
+    config::{ChainConfig, DataSourceConfig, ProtocolMetaConfig, SystemConfig},
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -81,6 +81,7 @@ impl From<ExternalChainConfig> for ChainConfig {
                 l1_fee_overhead: external.genesis.system_config.overhead.0.into(),
                 l1_fee_scalar: external.genesis.system_config.scalar.0.into(),
                 unsafe_block_signer: Address::zero(), // not used?
+                eip_1559_params: 0,
             },
             batch_inbox: external.batch_inbox_address,
             deposit_contract: Address::zero(),         // not used
@@ -90,9 +91,16 @@ impl From<ExternalChainConfig> for ChainConfig {
             seq_window_size: external.seq_window_size, // NOTE: not used in derivation, but used in `State`
             max_seq_drift: external.max_sequencer_drift,
             regolith_time: 0, // not used
+            canyon_time: u64::MAX,
+            delta_time: u64::MAX,
+            ecotone_time: u64::MAX,
+            fjord_time: u64::MAX,
+            granite_time: u64::MAX,
+            holocene_time: u64::MAX,
             blocktime: external.block_time,
             l2_to_l1_message_passer: Address::zero(), // not used?
             meta: ProtocolMetaConfig::specular(),
+            data_sources: vec![DataSourceConfig::Blobs],
         }
     }
 }
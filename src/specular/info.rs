@@ -152,7 +152,6 @@ async fn to_specular_head_info<P: InnerProvider>(
 mod test_utils {
     use super::*;
     use crate::common::{BlockInfo, Epoch};
-    use crate::config::{ChainConfig, Config};
     use ethers::types::H256;
     use std::str::FromStr;
 
@@ -226,21 +225,6 @@ mod test_utils {
         serde_json::from_str(raw_block).ok()
     }
 
-    pub fn optimism_config() -> Config {
-        Config {
-            l1_rpc_url: Default::default(),
-            l2_rpc_url: Default::default(),
-            l2_engine_url: Default::default(),
-            chain: ChainConfig::optimism(),
-            jwt_secret: Default::default(),
-            checkpoint_sync_url: Default::default(),
-            rpc_port: Default::default(),
-            devnet: false,
-            local_sequencer: Default::default(),
-            watcher_delay: Default::default(),
-        }
-    }
-
     #[async_trait::async_trait]
     impl InnerProvider for MockProvider {
         async fn get_block_with_txs(
@@ -282,7 +266,7 @@ mod tests {
     #[tokio::test]
     async fn test_get_head_info_fails() {
         let provider = test_utils::mock_provider(None, None);
-        let config = test_utils::optimism_config();
+        let config = Config::default_for_tests();
         let head_info =
             HeadInfoQuery::get_head_info(&provider, &config, BlockNumber::Finalized, None).await;
         assert_eq!(test_utils::default_head_info(), head_info);
@@ -291,7 +275,7 @@ mod tests {
     #[tokio::test]
     async fn test_get_head_info_empty_block() {
         let provider = test_utils::mock_provider(Some(Block::default()), Some(Epoch::default()));
-        let config = test_utils::optimism_config();
+        let config = Config::default_for_tests();
         let head_info =
             HeadInfoQuery::get_head_info(&provider, &config, BlockNumber::Finalized, None).await;
         assert_eq!(test_utils::default_head_info(), head_info);
@@ -303,7 +287,7 @@ mod tests {
             test_utils::valid_block(),
             Some(test_utils::default_head_info().l1_epoch),
         );
-        let config = test_utils::optimism_config();
+        let config = Config::default_for_tests();
         let head_info =
             HeadInfoQuery::get_head_info(&provider, &config, BlockNumber::Finalized, None).await;
         // In Optimism's case their `valid_block` does not contain the AttributeDeposit transaction
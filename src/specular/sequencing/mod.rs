@@ -161,6 +161,8 @@ impl<M: Middleware + 'static> SequencingPolicy for AttributesBuilder<M> {
             transactions: Some(txs),
             no_tx_pool,
             gas_limit: U64::from(gas_limit),
+            withdrawals: None,
+            parent_beacon_block_root: None,
             epoch: Some(create_epoch(next_origin)),
             l1_inclusion_block: None,
             seq_number: None,
@@ -222,7 +224,7 @@ mod tests {
         let provider: Provider<MockProvider> = Provider::new(mock_client);
         let attrs_builder = AttributesBuilder::new(config.clone(), provider);
         // Run test cases.
-        let cases = vec![(true, true), (true, false), (false, true), (false, false)];
+        let cases = [(true, true), (true, false), (false, true), (false, false)];
         for case in cases.iter() {
             let (input, expected) = generate_is_ready_case(case.0, case.1, config.clone());
             assert_eq!(
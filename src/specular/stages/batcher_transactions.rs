@@ -7,6 +7,7 @@ use std::collections::VecDeque;
 
 use crate::derive::async_iterator::AsyncIterator;
 use crate::derive::stages::batcher_transactions::BatcherTransactionMessage;
+use crate::derive::trace::{self, DerivationEvent, DropReason, EventTarget};
 use crate::derive::PurgeableAsyncIterator;
 use crate::specular::common::{AppendTxBatchInput, APPEND_TX_BATCH_ABI, APPEND_TX_BATCH_SELECTOR};
 
@@ -45,15 +46,18 @@ impl SpecularBatcherTransactions {
     }
 
     pub fn process_incoming(&mut self) {
-        while let Ok(BatcherTransactionMessage { txs, l1_origin }) = self.transaction_rx.try_recv()
+        while let Ok(BatcherTransactionMessage { txs, l1_origin, .. }) =
+            self.transaction_rx.try_recv()
         {
             for data in txs {
-                let res = SpecularBatcherTransaction::new(l1_origin, &data).map(|tx| {
-                    self.txs.push_back(tx);
-                });
-
-                if res.is_err() {
-                    tracing::warn!("dropping invalid batcher transaction");
+                match SpecularBatcherTransaction::new(l1_origin, &data) {
+                    Ok(tx) => self.txs.push_back(tx),
+                    Err(err) => trace::record(DerivationEvent::new(
+                        EventTarget::BatcherTransaction,
+                        DropReason::Decode,
+                        l1_origin,
+                        format!("invalid batcher transaction: {}", err),
+                    )),
                 }
             }
         }
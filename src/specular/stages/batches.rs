@@ -8,12 +8,15 @@ use async_trait::async_trait;
 use ethers::types::H256;
 use ethers::utils::rlp::Rlp;
 use eyre::Result;
+use serde::{Deserialize, Serialize};
 
 use crate::common::RawTransaction;
 use crate::config::Config;
 use crate::derive::async_iterator::AsyncIterator;
+use crate::derive::snapshot::PipelineSnapshot;
 use crate::derive::stages::batches::Batch;
 use crate::derive::state::State;
+use crate::derive::trace::{self, DerivationEvent, DropReason, EventTarget};
 use crate::derive::PurgeableAsyncIterator;
 
 use super::batcher_transactions::SpecularBatcherTransaction;
@@ -39,8 +42,8 @@ where
     type Item = Batch;
 
     async fn next(&mut self) -> Option<Self::Item> {
-        self.try_next().await.unwrap_or_else(|_| {
-            tracing::debug!("Failed to decode batch");
+        self.try_next().await.unwrap_or_else(|err| {
+            tracing::debug!("failed to derive batch: {}", err);
             None
         })
     }
@@ -55,6 +58,20 @@ where
         self.batcher_transaction_iter.purge().await;
         self.batches.clear();
     }
+
+    fn snapshot(&self, snapshot: &mut PipelineSnapshot) -> Result<()> {
+        self.batcher_transaction_iter.snapshot(snapshot)?;
+        snapshot.set_stage("specular_batches", &self.batches)
+    }
+
+    fn restore(&mut self, snapshot: &PipelineSnapshot) -> Result<()> {
+        self.batcher_transaction_iter.restore(snapshot)?;
+        if let Some(batches) = snapshot.stage("specular_batches")? {
+            self.batches = batches;
+        }
+
+        Ok(())
+    }
 }
 
 impl<I> SpecularBatches<I> {
@@ -80,7 +97,16 @@ where
     /// returns the first valid batch if possible.
     async fn try_next(&mut self) -> Result<Option<Batch>> {
         if let Some(batcher_transaction) = self.batcher_transaction_iter.next().await {
-            let batches = decode_batches(&batcher_transaction, &self.state, &self.config)?;
+            let batches = decode_batches(&batcher_transaction, &self.state, &self.config)
+                .unwrap_or_else(|err| {
+                    trace::record(DerivationEvent::new(
+                        EventTarget::BatcherTransaction,
+                        DropReason::Decode,
+                        batcher_transaction.l1_inclusion_block,
+                        format!("failed to decode batches: {}", err),
+                    ));
+                    Vec::new()
+                });
             batches.into_iter().for_each(|batch| {
                 tracing::debug!(
                     "saw batch: t={}, bn={:?}, e={}",
@@ -100,8 +126,16 @@ where
                         self.batches.remove(&batch.timestamp);
                         break Some(batch);
                     }
-                    BatchStatus::Drop => {
-                        tracing::warn!("dropping invalid batch");
+                    BatchStatus::Drop(reason) => {
+                        trace::record(DerivationEvent::new(
+                            EventTarget::Batch,
+                            reason,
+                            batch.l1_inclusion_block,
+                            format!(
+                                "invalid batch t={}, bn={}",
+                                batch.timestamp, batch.l2_block_number
+                            ),
+                        ));
                         let timestamp = batch.timestamp;
                         self.batches.remove(&timestamp);
                     }
@@ -173,20 +207,20 @@ where
         // check timestamp range
         // TODO[zhe]: do we need this?
         match batch.timestamp.cmp(&next_timestamp) {
-            Ordering::Greater | Ordering::Less => return BatchStatus::Drop,
+            Ordering::Greater | Ordering::Less => return BatchStatus::Drop(DropReason::Timestamp),
             Ordering::Equal => (),
         }
 
         // check that block builds on existing chain
         if batch.l2_block_number != head.number + 1 {
-            tracing::warn!("invalid block number");
-            return BatchStatus::Drop;
+            tracing::debug!("invalid block number");
+            return BatchStatus::Drop(DropReason::ParentHash);
         }
 
         // check the inclusion delay
         if batch.epoch_num + self.config.chain.seq_window_size < batch.l1_inclusion_block {
-            tracing::warn!("inclusion window elapsed");
-            return BatchStatus::Drop;
+            tracing::debug!("inclusion window elapsed");
+            return BatchStatus::Drop(DropReason::InclusionWindow);
         }
 
         // TODO[zhe]: check origin epoch and sequencer drift
@@ -194,14 +228,14 @@ where
         // check L1 oracle update transaction
         if batch.l1_oracle_values.is_some() {
             if let Err(err) = check_epoch_update_batch(batch, &state) {
-                tracing::warn!("invalid epoch update batch, err={:?}", err);
-                return BatchStatus::Drop;
+                tracing::debug!("invalid epoch update batch, err={:?}", err);
+                return BatchStatus::Drop(DropReason::Origin);
             }
         }
 
         if batch.has_invalid_transactions() {
-            tracing::warn!("invalid transaction");
-            return BatchStatus::Drop;
+            tracing::debug!("invalid transaction");
+            return BatchStatus::Drop(DropReason::InvalidTransaction);
         }
 
         BatchStatus::Accept
@@ -323,12 +357,12 @@ fn decode_batches_v0(
 
 #[derive(Debug, Clone, PartialEq)]
 enum BatchStatus {
-    Drop,
+    Drop(DropReason),
     Accept,
 }
 
 /// A batch of transactions, along with payload attributes.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SpecularBatchV0 {
     pub epoch_num: u64,
     pub epoch_hash: H256,
@@ -394,7 +428,7 @@ mod tests {
 
         use crate::{
             common::RawTransaction,
-            config::{ChainConfig, Config},
+            config::Config,
             derive::state::State,
             specular::{
                 common::{
@@ -458,16 +492,7 @@ mod tests {
         #[test]
         fn decode() -> eyre::Result<()> {
             let config = Arc::new(Config {
-                l1_rpc_url: Default::default(),
-                l2_rpc_url: Default::default(),
-                l2_engine_url: Default::default(),
-                chain: ChainConfig::optimism(),
-                jwt_secret: Default::default(),
-                checkpoint_sync_url: Default::default(),
-                rpc_port: Default::default(),
-                devnet: false,
-                local_sequencer: Default::default(),
-                watcher_delay: Default::default(),
+                ..Config::default_for_tests()
             });
             let state = RwLock::new(State::new(
                 Default::default(),
@@ -571,7 +596,7 @@ mod tests {
                 state.safe_epoch.number = epoch_num;
                 state.safe_epoch.hash = epoch_hash;
                 state.safe_head.number = first_l2_block_num + 1;
-                state.safe_head.timestamp = timestamp + 1 * config.as_ref().chain.blocktime;
+                state.safe_head.timestamp = timestamp + config.as_ref().chain.blocktime;
             }
 
             // Test the case where the first two sub-batches is already seen.
@@ -1,7 +1,11 @@
 use eyre::{Result, WrapErr};
 use lazy_static::lazy_static;
 use prometheus_exporter::{
-    prometheus::{register_int_gauge, IntGauge},
+    prometheus::{
+        register_histogram, register_histogram_vec, register_int_counter, register_int_counter_vec,
+        register_int_gauge, register_int_gauge_vec, Histogram, HistogramVec, IntCounter,
+        IntCounterVec, IntGauge, IntGaugeVec,
+    },
     start,
 };
 
@@ -11,6 +15,62 @@ lazy_static! {
     pub static ref SAFE_HEAD: IntGauge =
         register_int_gauge!("safe_head", "safe head number").unwrap();
     pub static ref SYNCED: IntGauge = register_int_gauge!("synced", "synced flag").unwrap();
+    pub static ref QUEUED_ATTRIBUTES: IntGauge = register_int_gauge!(
+        "queued_attributes",
+        "number of derived attributes waiting for the engine"
+    )
+    .unwrap();
+    pub static ref DERIVE_STAGE_SECONDS: HistogramVec = register_histogram_vec!(
+        "derive_stage_seconds",
+        "time spent in each derivation stage",
+        &["stage"]
+    )
+    .unwrap();
+    pub static ref DECODE_MEMORY: IntGauge = register_int_gauge!(
+        "decode_memory_bytes",
+        "memory reserved by running channel decoding jobs"
+    )
+    .unwrap();
+    pub static ref L1_ENDPOINT_LATENCY: IntGaugeVec = register_int_gauge_vec!(
+        "l1_endpoint_latency_ms",
+        "average request latency of each L1 endpoint",
+        &["endpoint"]
+    )
+    .unwrap();
+    pub static ref L1_ENDPOINT_FAILURES: IntCounterVec = register_int_counter_vec!(
+        "l1_endpoint_failures",
+        "number of failed requests to each L1 endpoint",
+        &["endpoint"]
+    )
+    .unwrap();
+    pub static ref L1_ENDPOINT_DISAGREEMENTS: IntCounter = register_int_counter!(
+        "l1_endpoint_disagreements",
+        "number of block requests the L1 endpoints returned different blocks for"
+    )
+    .unwrap();
+    pub static ref L1_REORG_DEPTH: Histogram = register_histogram!(
+        "l1_reorg_depth",
+        "number of ingested L1 blocks replaced by each reorg",
+        vec![1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0]
+    )
+    .unwrap();
+    pub static ref L1_BLOCK_CACHE_HITS: IntCounter = register_int_counter!(
+        "l1_block_cache_hits",
+        "number of L1 block lookups served from the cache"
+    )
+    .unwrap();
+    pub static ref L1_BLOCK_CACHE_MISSES: IntCounter = register_int_counter!(
+        "l1_block_cache_misses",
+        "number of L1 block lookups not found in the cache"
+    )
+    .unwrap();
+    pub static ref VERIFIED_HEAD: IntGauge =
+        register_int_gauge!("verified_head", "verified head number").unwrap();
+    pub static ref VERIFIER_MISMATCHES: IntCounter = register_int_counter!(
+        "verifier_mismatches",
+        "number of blocks that do not match the derived attributes"
+    )
+    .unwrap();
 }
 
 pub fn init() -> Result<()> {